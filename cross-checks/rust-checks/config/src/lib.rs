@@ -6,12 +6,21 @@ extern crate serde_derive;
 
 extern crate serde;
 extern crate serde_yaml;
+extern crate yaml_rust;
+extern crate serde_json;
+extern crate regex;
 
 pub mod attr;
+mod location;
+
+pub use location::ConfigLocation;
 
 use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
 
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum XCheckType {
     // Basic types
@@ -26,6 +35,22 @@ pub enum XCheckType {
     Fixed(u64),
     Djb2(String),
 
+    // Hash the in-memory representation of the value using CRC-32,
+    // which is much faster than the default hash for large arrays
+    Crc32,
+
+    // Hash the in-memory representation of the value using BLAKE3,
+    // for data where hash collisions must be avoided
+    Blake3,
+
+    // Hash the in-memory representation of the value using Adler-32,
+    // which is even cheaper than CRC-32 on targets without CRC hardware
+    Adler32,
+
+    // Hash the value using SipHash, through its `Hash` implementation
+    #[serde(rename = "siphash")]
+    SipHash,
+
     // Hash using the default hash for another type
     AsType(String),
 
@@ -49,7 +74,34 @@ impl Default for XCheckType {
     }
 }
 
-#[derive(Deserialize, Debug, Clone)]
+// How to compute the cross-check values for arguments and return values
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckMode {
+    // Cross-check the hash of each value
+    Hash,
+
+    // Cross-check the values themselves, so they get compared by equality
+    Eq,
+}
+
+impl CheckMode {
+    pub fn from_str(s: &str) -> Option<CheckMode> {
+        match s {
+            "hash" => Some(CheckMode::Hash),
+            "eq"   => Some(CheckMode::Eq),
+            _      => None
+        }
+    }
+}
+
+impl Default for CheckMode {
+    fn default() -> CheckMode {
+        CheckMode::Hash
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum XCheckTag {
     Unknown,
@@ -65,15 +117,51 @@ impl Default for XCheckTag {
     }
 }
 
-#[derive(Deserialize, Debug, Default, Clone)]
+// Tag for an extra cross-check: either one of the built-in tags,
+// or a symbolic name that gets resolved using the top-level `tag_map`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ExtraXCheckTag {
+    Builtin(XCheckTag),
+    Symbolic(String),
+}
+
+impl Default for ExtraXCheckTag {
+    fn default() -> ExtraXCheckTag {
+        ExtraXCheckTag::Builtin(XCheckTag::Unknown)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct ExtraXCheck {
     #[serde(default)]
-    pub tag: XCheckTag,
+    pub tag: ExtraXCheckTag,
 
     pub custom: String,
 }
 
-#[derive(Deserialize, Debug, Default)]
+// Extra cross-check for a function argument, which can also
+// be written as just the custom expression, e.g., `"custom_fn(x)"`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ArgExtraXCheck {
+    Custom(String),
+    Full(ExtraXCheck),
+}
+
+impl ArgExtraXCheck {
+    pub fn to_extra_xcheck(&self) -> ExtraXCheck {
+        match *self {
+            ArgExtraXCheck::Custom(ref custom) => ExtraXCheck {
+                tag: Default::default(),
+                custom: custom.clone(),
+            },
+            ArgExtraXCheck::Full(ref ex) => ex.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 #[serde(default)]
 pub struct DefaultsConfig {
     pub disable_xchecks: Option<bool>,
@@ -85,6 +173,31 @@ pub struct DefaultsConfig {
 
     #[serde(rename = "return")]
     pub ret: Option<XCheckType>,
+
+    pub unsafe_block_check: Option<bool>,
+    pub loop_count_check: Option<bool>,
+    pub check_match_arms: Option<bool>,
+
+    pub check_mode: Option<CheckMode>,
+
+    pub max_args: Option<usize>,
+
+    pub min_complexity: Option<usize>,
+
+    pub hash_width: Option<u8>,
+
+    pub public_only: Option<bool>,
+
+    // Cross-check all `static mut` variables in the file
+    // at the beginning of every instrumented function
+    pub check_static_mut: Option<bool>,
+
+    // Only keep the function cross-checks in `#[cfg(test)]` builds
+    pub enabled_for_tests: Option<bool>,
+
+    // Prefix for the names of the built-in tags, which get
+    // replaced by the prefixed tags from the `tag_map`
+    pub tag_prefix: Option<String>,
 }
 
 impl DefaultsConfig {
@@ -101,16 +214,34 @@ impl DefaultsConfig {
         update_field!(exit);
         update_field!(all_args);
         update_field!(ret);
+        update_field!(unsafe_block_check);
+        update_field!(loop_count_check);
+        update_field!(check_match_arms);
+        update_field!(check_mode);
+        update_field!(max_args);
+        update_field!(min_complexity);
+        update_field!(hash_width);
+        update_field!(public_only);
+        update_field!(check_static_mut);
+        update_field!(enabled_for_tests);
+        update_field!(tag_prefix);
     }
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 #[serde(default)]
 pub struct FunctionConfig {
     // Name of the function
     // FIXME: where do we get this???
     pub name: String,
 
+    // Alternative name for the function, e.g., the name of the C function
+    // it was translated from, which also matches this entry
+    pub c_name: Option<String>,
+
+    // Group this function belongs to, if any
+    pub group: Option<String>,
+
     // Overrides for the attribute config items
     pub disable_xchecks: Option<bool>,
 
@@ -131,12 +262,82 @@ pub struct FunctionConfig {
     pub ahasher: Option<String>,
     pub shasher: Option<String>,
 
+    // Whether to add entry/exit cross-checks around `unsafe` blocks
+    pub unsafe_block_check: Option<bool>,
+
+    // Whether to cross-check the iteration counts of loops
+    pub loop_count_check: Option<bool>,
+
+    // Whether to cross-check which arm each `match` takes
+    pub check_match_arms: Option<bool>,
+
+    // Whether to cross-check arguments and return values
+    // by hash or by equality
+    pub check_mode: Option<CheckMode>,
+
+    // Width of the cross-check hashes, either 32 or 64 bits
+    pub hash_width: Option<u8>,
+
+    // Skip functions with a lower cyclomatic complexity than this
+    pub min_complexity: Option<usize>,
+
+    // Only emit the cross-checks if the function panics
+    pub only_on_panic: Option<bool>,
+
+    // Only record the function call for comparison against the C
+    // side, for Rust functions that get called from C as callbacks
+    pub callback_check: Option<bool>,
+
+    // Only cross-check the function when compiling for a configuration
+    // that matches this `cfg` predicate, e.g., `target_os = "linux"`
+    pub cfg_predicate: Option<String>,
+
+    // Mark the function as `#[inline(never)]`, so the optimizer
+    // cannot hoist or eliminate its cross-checks
+    pub no_inline: Option<bool>,
+
+    // Use the demangled name from the `export_name` attribute
+    // of the function as its cross-check name, for functions
+    // called from C++ using their mangled names
+    pub demangle: Option<bool>,
+
+    // Check that the integer values returned by the function
+    // never decrease, e.g., for counters
+    pub monotonic: Option<bool>,
+
+    // Record the ABI of the function in a link section,
+    // so it can be checked against the C function
+    pub abi_check: Option<bool>,
+
+    // Cross-check the return value before the final cast in the
+    // function body, e.g., a pointer before it gets cast to `usize`
+    pub hash_before_cast: Option<bool>,
+
+    // Cross-check the error values returned by the function,
+    // which needs to return a `Result`
+    pub hash_errors: Option<bool>,
+
+    // Only keep the cross-checks in `#[cfg(test)]` builds
+    pub enabled_for_tests: Option<bool>,
+
     // Nested items
     nested: Option<ItemList>,
 
     // Extra cross-checks
     pub entry_extra: Vec<ExtraXCheck>,
     pub exit_extra: Vec<ExtraXCheck>,
+
+    // Extra cross-checks to perform right after the cross-check
+    // of each argument, indexed by argument name
+    pub arg_extra: HashMap<String, Vec<ArgExtraXCheck>>,
+
+    // Replace the extra cross-checks inherited from the parent
+    // scope instead of appending to them
+    pub no_inherit_extra: bool,
+
+    // Where this item was defined in the configuration files
+    #[serde(skip)]
+    pub location: Option<ConfigLocation>,
 }
 
 impl FunctionConfig {
@@ -145,6 +346,8 @@ impl FunctionConfig {
     pub fn clone_current(&self) -> FunctionConfig {
         FunctionConfig {
             name: self.name.clone(),
+            c_name: self.c_name.clone(),
+            group: self.group.clone(),
             disable_xchecks: self.disable_xchecks,
             entry: self.entry.clone(),
             exit: self.exit.clone(),
@@ -153,33 +356,187 @@ impl FunctionConfig {
             ret: self.ret.clone(),
             ahasher: self.ahasher.clone(),
             shasher: self.shasher.clone(),
+            unsafe_block_check: self.unsafe_block_check,
+            loop_count_check: self.loop_count_check,
+            check_match_arms: self.check_match_arms,
+            check_mode: self.check_mode,
+            hash_width: self.hash_width,
+            min_complexity: self.min_complexity,
+            only_on_panic: self.only_on_panic,
+            callback_check: self.callback_check,
+            cfg_predicate: self.cfg_predicate.clone(),
+            no_inline: self.no_inline,
+            demangle: self.demangle,
+            monotonic: self.monotonic,
+            abi_check: self.abi_check,
+            hash_before_cast: self.hash_before_cast,
+            hash_errors: self.hash_errors,
+            enabled_for_tests: self.enabled_for_tests,
             nested: Default::default(),
             entry_extra: self.entry_extra.clone(),
             exit_extra: self.exit_extra.clone(),
+            arg_extra: self.arg_extra.clone(),
+            no_inherit_extra: self.no_inherit_extra,
+            location: self.location.clone(),
         }
     }
 }
 
 // Index of a structure/aggregate field
-// Can be an integer (for tuples) or a string (for structures)
-#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
-#[serde(untagged)]
+// Can be an integer (for tuples) or a string (for structures),
+// or a regular expression that matches the names of several
+// fields, written between slashes, e.g., `/^pad_[0-9]+$/`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum FieldIndex {
     Int(usize),
     Str(String),
+    Regex(FieldRegex),
+}
+
+// Regular expression from a FieldIndex, compiled once when we parse it;
+// `Regex` can't be compared or hashed, so we use its pattern for those
+#[derive(Clone)]
+pub struct FieldRegex(regex::Regex);
+
+impl FieldRegex {
+    pub fn new(re: &str) -> Result<FieldRegex, regex::Error> {
+        regex::Regex::new(re).map(FieldRegex)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl PartialEq for FieldRegex {
+    fn eq(&self, other: &FieldRegex) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for FieldRegex {}
+
+impl Hash for FieldRegex {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl fmt::Debug for FieldRegex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+// Serialized form of a FieldIndex, before we parse the regexes
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawFieldIndex {
+    Int(usize),
+    Str(String),
+}
+
+impl serde::Serialize for FieldIndex {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where S: serde::Serializer {
+        let raw = match *self {
+            FieldIndex::Int(idx) => RawFieldIndex::Int(idx),
+            FieldIndex::Str(ref s) => RawFieldIndex::Str(s.clone()),
+            FieldIndex::Regex(ref re) => RawFieldIndex::Str(format!("/{}/", re.as_str())),
+        };
+        raw.serialize(serializer)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for FieldIndex {
+    fn deserialize<D>(deserializer: D) -> Result<FieldIndex, D::Error>
+            where D: serde::Deserializer<'de> {
+        match RawFieldIndex::deserialize(deserializer)? {
+            RawFieldIndex::Int(idx) => Ok(FieldIndex::Int(idx)),
+            RawFieldIndex::Str(ref s) if s.len() >= 2 && s.starts_with('/') && s.ends_with('/') => {
+                let re = &s[1..s.len() - 1];
+                FieldRegex::new(re)
+                    .map(FieldIndex::Regex)
+                    .map_err(|e| serde::de::Error::custom(format!("invalid field regex {}: {}", s, e)))
+            }
+            RawFieldIndex::Str(s) => Ok(FieldIndex::Str(s)),
+        }
+    }
 }
 
 impl FieldIndex {
     pub fn from_str(s: &str) -> FieldIndex {
         FieldIndex::Str(String::from(s))
     }
+
+    // Check if this regex matches the given field name;
+    // only field names can match, not tuple indices
+    pub fn matches(&self, field: &FieldIndex) -> bool {
+        match (self, field) {
+            (&FieldIndex::Regex(ref re), &FieldIndex::Str(ref name)) => {
+                re.0.is_match(name)
+            }
+            _ => false
+        }
+    }
+
+    // Split a nested field path, e.g., "outer.inner.leaf",
+    // into its first field and the remainder of the path
+    pub fn split_path(&self) -> Option<(FieldIndex, FieldIndex)> {
+        match *self {
+            FieldIndex::Str(ref s) => {
+                let mut parts = s.splitn(2, '.');
+                let first = parts.next().unwrap();
+                parts.next().map(|rest| {
+                    let first = first.parse::<usize>()
+                        .map(FieldIndex::Int)
+                        .unwrap_or_else(|_| FieldIndex::from_str(first));
+                    (first, FieldIndex::from_str(rest))
+                })
+            }
+            FieldIndex::Int(_) |
+            FieldIndex::Regex(_) => None
+        }
+    }
 }
 
-#[derive(Deserialize, Debug, Default)]
+// Order in which to hash the fields of a structure
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldOrder {
+    // Hash the fields in the order they're declared in
+    Declaration,
+
+    // Sort the fields by name before hashing, which makes the
+    // hash independent of the order of the fields
+    Alphabetical,
+}
+
+impl FieldOrder {
+    pub fn from_str(s: &str) -> Option<FieldOrder> {
+        match s {
+            "declaration"  => Some(FieldOrder::Declaration),
+            "alphabetical" => Some(FieldOrder::Alphabetical),
+            _              => None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            FieldOrder::Declaration  => "declaration",
+            FieldOrder::Alphabetical => "alphabetical",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 #[serde(default)]
 pub struct StructConfig {
     pub name: String,
 
+    // Group this structure belongs to, if any
+    pub group: Option<String>,
+
     // Overrides for the attribute config items
     pub disable_xchecks: Option<bool>,
 
@@ -195,12 +552,74 @@ pub struct StructConfig {
 
     pub fields: HashMap<FieldIndex, XCheckType>,
 
+    // Conditions for hashing some of the fields, e.g., `data: "self.kind == 1"`
+    // for a tagged union; the other fields get hashed unconditionally
+    pub hash_if: HashMap<FieldIndex, String>,
+
+    // Fallback cross-check type for fields not listed in `fields`
+    pub all_fields: Option<XCheckType>,
+
+    // Hash `PhantomData` fields instead of skipping them
+    pub phantom_field_hash: Option<bool>,
+
+    // Order in which the fields get hashed
+    pub field_order: Option<FieldOrder>,
+
+    // Only hash the discriminant of an enum, and ignore the variant payloads
+    pub discriminant_only: Option<bool>,
+
+    // Generate a `CrossCheckHash` implementation for a type defined
+    // in another crate, using a local newtype wrapper
+    pub remote_hash_impl: Option<bool>,
+
+    // Maximum time in milliseconds to spend hashing a value,
+    // which prevents hangs on cyclic data structures
+    pub timeout_ms: Option<u64>,
+
+    // Hash the raw bytes of the structure, e.g., for structures with bit fields
+    pub bitfield_hash: Option<bool>,
+
+    // Only derive `CrossCheckHash` when compiling for a configuration
+    // that matches this `cfg` predicate
+    pub cfg_predicate: Option<String>,
+
+    // Hash the string output of the `Display` or `Debug` implementation
+    pub hash_display: Option<bool>,
+    pub hash_debug: Option<bool>,
+
+    // Alignment of the corresponding C structure, which
+    // gets checked against the Rust one at compile time
+    pub c_align: Option<usize>,
+
+    // Add `#[repr(C)]` to the structure, so its layout matches the C one
+    pub mirror_c_layout: Option<bool>,
+
+    // XOR the hash of each field name into the hash of the field
+    pub field_salt: Option<bool>,
+
+    // Groups of integer fields that hold parts of a single value, e.g.,
+    // `[["hi", "lo"]]`, which get concatenated and hashed as one value
+    pub field_groups: Option<Vec<Vec<String>>>,
+
+    // Hash the fields in parallel, and combine their hashes using XOR
+    pub parallel_hash: Option<bool>,
+
+    // XOR the size of the structure into its hash, to catch layout changes
+    pub include_size: Option<bool>,
+
+    // Hash the raw bytes of the structure, with its padding zeroed
+    pub ignore_padding: Option<bool>,
+
     // Nested items; in this context, it means
     // methods implemented in impl's
     nested: Option<ItemList>,
+
+    // Where this item was defined in the configuration files
+    #[serde(skip)]
+    pub location: Option<ConfigLocation>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "item", rename_all = "lowercase")]
 pub enum ItemConfig {
     Defaults(DefaultsConfig),
@@ -208,10 +627,14 @@ pub enum ItemConfig {
     Struct(StructConfig),
     Value,   // TODO
     Closure, // TODO
+
+    // Reference to a block from the `macros` section, written as `$NAME`,
+    // which gets replaced by the items in the block by `Config::resolve_macros`
+    Macro { name: String },
 }
 
 impl ItemConfig {
-    fn name(&self) -> Option<&str> {
+    pub fn name(&self) -> Option<&str> {
         match *self {
             ItemConfig::Function(FunctionConfig { ref name, .. }) => Some(&name[..]),
             ItemConfig::Struct(StructConfig { ref name, .. }) => Some(&name[..]),
@@ -219,6 +642,21 @@ impl ItemConfig {
         }
     }
 
+    fn alt_name(&self) -> Option<&str> {
+        match *self {
+            ItemConfig::Function(FunctionConfig { ref c_name, .. }) => c_name.as_ref().map(|n| &n[..]),
+            _ => None
+        }
+    }
+
+    pub fn group(&self) -> Option<&str> {
+        match *self {
+            ItemConfig::Function(FunctionConfig { ref group, .. }) |
+            ItemConfig::Struct(StructConfig { ref group, .. }) => group.as_ref().map(|g| &g[..]),
+            _ => None
+        }
+    }
+
     pub fn nested_items(&self) -> Option<&ItemList> {
         match *self {
             ItemConfig::Function(FunctionConfig { ref nested, .. }) => nested.as_ref(),
@@ -227,11 +665,120 @@ impl ItemConfig {
             _ => None
         }
     }
+
+    pub fn location(&self) -> Option<&ConfigLocation> {
+        match *self {
+            ItemConfig::Function(FunctionConfig { ref location, .. }) |
+            ItemConfig::Struct(StructConfig { ref location, .. }) => location.as_ref(),
+            _ => None
+        }
+    }
+
+    fn set_location(&mut self, loc: ConfigLocation) {
+        match *self {
+            ItemConfig::Function(FunctionConfig { ref mut location, .. }) |
+            ItemConfig::Struct(StructConfig { ref mut location, .. }) => *location = Some(loc),
+            _ => ()
+        }
+    }
+
+    // Check whether this entry leaves all settings at their defaults,
+    // in which case applying it has no effect; the names, location
+    // and nested items of the entry do not count as settings
+    pub fn is_empty(&self) -> bool {
+        match *self {
+            ItemConfig::Defaults(ref defs) => {
+                same_value(defs, &DefaultsConfig::default())
+            }
+            ItemConfig::Function(ref func) => {
+                same_value(&func.clone_current(), &FunctionConfig {
+                    name: func.name.clone(),
+                    c_name: func.c_name.clone(),
+                    ..Default::default()
+                })
+            }
+            ItemConfig::Struct(ref struc) => {
+                same_value(struc, &StructConfig {
+                    name: struc.name.clone(),
+                    nested: struc.nested.clone(),
+                    ..Default::default()
+                })
+            }
+            ItemConfig::Value | ItemConfig::Closure => true,
+            ItemConfig::Macro { .. } => false,
+        }
+    }
+
+    fn nested_items_mut(&mut self) -> Option<&mut ItemList> {
+        match *self {
+            ItemConfig::Function(FunctionConfig { ref mut nested, .. }) => nested.as_mut(),
+            ItemConfig::Struct(StructConfig { ref mut nested, .. }) => nested.as_mut(),
+            _ => None
+        }
+    }
+}
+
+// An entry in an item list, which is either a full item, or
+// a reference to a block from the `macros` section
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ItemListEntry {
+    Macro(String),
+    Item(ItemConfig),
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Debug, Default, Clone)]
 pub struct ItemList(Vec<ItemConfig>);
 
+impl<'de> serde::Deserialize<'de> for ItemList {
+    fn deserialize<D>(deserializer: D) -> Result<ItemList, D::Error>
+            where D: serde::Deserializer<'de> {
+        let entries: Vec<ItemListEntry> = serde::Deserialize::deserialize(deserializer)?;
+        entries.into_iter().map(|entry| match entry {
+            ItemListEntry::Item(item) => Ok(item),
+            ItemListEntry::Macro(ref s) if s.starts_with('$') => {
+                Ok(ItemConfig::Macro { name: String::from(&s[1..]) })
+            }
+            ItemListEntry::Macro(s) => {
+                Err(serde::de::Error::custom(format!("invalid macro reference: {}", s)))
+            }
+        }).collect::<Result<Vec<_>, _>>().map(ItemList)
+    }
+}
+
+// Replace the macro references in an item list, including the lists
+// of nested items, with the contents of their macro blocks
+fn resolve_item_list_macros(items: &mut ItemList,
+                            macros: &HashMap<String, serde_yaml::Value>) -> Result<(), String> {
+    let old_items = mem::replace(&mut items.0, vec![]);
+    for mut item in old_items.into_iter() {
+        if let ItemConfig::Macro { ref name } = item {
+            let block = macros.get(name)
+                .ok_or_else(|| format!("undefined config macro: ${}", name))?;
+            let block_items: ItemList = serde_yaml::from_value(block.clone())
+                .map_err(|e| format!("invalid config macro ${}: {}", name, e))?;
+            // We only do a single substitution pass
+            if block_items.0.iter().any(|item| matches_macro(item)) {
+                return Err(format!("config macro ${} references another macro", name));
+            }
+            items.0.extend(block_items.0.into_iter());
+            continue;
+        }
+        if let Some(nested) = item.nested_items_mut() {
+            resolve_item_list_macros(nested, macros)?;
+        }
+        items.0.push(item);
+    }
+    Ok(())
+}
+
+fn matches_macro(item: &ItemConfig) -> bool {
+    match *item {
+        ItemConfig::Macro { .. } => true,
+        _ => false
+    }
+}
+
 impl ItemList {
     pub fn items(&self) -> &Vec<ItemConfig> {
         &self.0
@@ -240,50 +787,313 @@ impl ItemList {
 
 pub struct NamedItemList<'a> {
     // FIXME: _items is unused; do we really need it???
-    _items: &'a ItemList,
+    _items: Vec<&'a ItemList>,
     pub name_map: HashMap<&'a str, &'a ItemConfig>,
 }
 
 impl<'a> NamedItemList<'a> {
     pub fn new(items: &'a ItemList) -> NamedItemList<'a> {
-        let map = items.0.iter()
-            .filter_map(|item| item.name().map(|name| (name, item)))
-            .collect();
+        NamedItemList::from_lists(&[items])
+    }
+
+    // Build a single name map from several item lists, e.g., the items
+    // for the same file coming from different configuration files;
+    // items from later lists take priority over earlier ones
+    pub fn from_lists(lists: &[&'a ItemList]) -> NamedItemList<'a> {
+        let mut map = HashMap::new();
+        for items in lists {
+            // Insert the alternative names first, so that
+            // the primary names take priority over them
+            let alt_names = items.0.iter()
+                .filter_map(|item| item.alt_name().map(|name| (name, item)));
+            let names = items.0.iter()
+                .filter_map(|item| item.name().map(|name| (name, item)));
+            map.extend(alt_names.chain(names));
+        }
         NamedItemList {
-            _items: items,
+            _items: lists.to_vec(),
             name_map: map,
         }
     }
+
+    // Map the names of the items back to the locations where
+    // they were defined, for the items that have one
+    pub fn inverse_map(&self) -> HashMap<String, ConfigLocation> {
+        self.name_map.iter()
+            .filter_map(|(name, item)| {
+                item.location().map(|loc| (String::from(*name), loc.clone()))
+            })
+            .collect()
+    }
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct FileConfig(ItemList);
 
-#[derive(Deserialize, Debug, Default)]
-pub struct Config(HashMap<String, FileConfig>);
+// Settings shared by all the items in a group, which
+// override the configuration of the items themselves
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct GroupConfig {
+    pub enabled: Option<bool>,
+}
+
+// Version of the configuration file format, stored in the `version` key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SchemaVersion {
+    V1,
+    V2,
+}
+
+impl SchemaVersion {
+    pub const CURRENT: SchemaVersion = SchemaVersion::V2;
+
+    pub fn from_number(n: u64) -> Option<SchemaVersion> {
+        match n {
+            1 => Some(SchemaVersion::V1),
+            2 => Some(SchemaVersion::V2),
+            _ => None
+        }
+    }
+
+    pub fn number(&self) -> u64 {
+        match *self {
+            SchemaVersion::V1 => 1,
+            SchemaVersion::V2 => 2,
+        }
+    }
+}
+
+// Files without a `version` key predate versioning, so they are V1
+impl Default for SchemaVersion {
+    fn default() -> SchemaVersion {
+        SchemaVersion::V1
+    }
+}
+
+impl serde::Serialize for SchemaVersion {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where S: serde::Serializer {
+        serializer.serialize_u64(self.number())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for SchemaVersion {
+    fn deserialize<D>(deserializer: D) -> Result<SchemaVersion, D::Error>
+            where D: serde::Deserializer<'de> {
+        let n = u64::deserialize(deserializer)?;
+        SchemaVersion::from_number(n).ok_or_else(|| {
+            <D::Error as serde::de::Error>::custom(
+                format!("unknown configuration version: {}", n))
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Config {
+    // Version of the format this configuration was written in
+    #[serde(default)]
+    version: SchemaVersion,
+
+    // Mapping from symbolic tag names to their numeric values
+    #[serde(default)]
+    tag_map: HashMap<String, u8>,
+
+    // Named groups of items, which can be configured together
+    #[serde(default)]
+    groups: HashMap<String, GroupConfig>,
+
+    // Named blocks of items, which item lists can include using `$NAME`
+    #[serde(default)]
+    macros: HashMap<String, serde_yaml::Value>,
+
+    // Whether the runtime should normalize paths before hashing them
+    #[serde(default)]
+    normalize_paths: bool,
+
+    // Warnings from parsing this configuration, e.g., about a deprecated
+    // format version, for the caller to report along with its own
+    #[serde(skip)]
+    warnings: Vec<String>,
+
+    #[serde(flatten)]
+    files: HashMap<String, FileConfig>,
+}
 
 impl Config {
     pub fn get_file_config(&self, file: &str) -> Option<&FileConfig> {
-        self.0.get(file)
+        self.files.get(file)
+    }
+
+    pub fn get_tag(&self, name: &str) -> Option<u8> {
+        self.tag_map.get(name).cloned()
+    }
+
+    pub fn get_group(&self, name: &str) -> Option<&GroupConfig> {
+        self.groups.get(name)
     }
 
     pub fn get_file_items(&self, file: &str) -> Option<&ItemList> {
         self.get_file_config(file).map(|fc| &fc.0)
     }
 
+    pub fn all_file_items(&self) -> Vec<&ItemList> {
+        self.files.values().map(|fc| &fc.0).collect()
+    }
+
+    pub fn version(&self) -> SchemaVersion {
+        self.version
+    }
+
+    pub fn normalize_paths(&self) -> bool {
+        self.normalize_paths
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        mem::replace(&mut self.warnings, vec![])
+    }
+
     pub fn merge(mut self, other: Self) -> Self {
-        for (file_name, cfg) in other.0.into_iter() {
+        self.version = self.version.max(other.version);
+        for (file_name, cfg) in other.files.into_iter() {
             // FIXME: check for duplicates???
-            (self.0.entry(file_name.clone())
-                   .or_insert(Default::default())
-                   .0).0.extend((cfg.0).0);
+            (self.files.entry(file_name.clone())
+                       .or_insert(Default::default())
+                       .0).0.extend((cfg.0).0);
         }
+        // Later tag definitions override earlier ones
+        self.tag_map.extend(other.tag_map.into_iter());
+        self.groups.extend(other.groups.into_iter());
+        self.macros.extend(other.macros.into_iter());
+        self.normalize_paths |= other.normalize_paths;
+        self.warnings.extend(other.warnings.into_iter());
         self
     }
+
+    // Replace all `$NAME` macro references in the item lists
+    // with the items from the corresponding `macros` blocks
+    pub fn resolve_macros(mut self) -> Result<Config, String> {
+        {
+            let Config { ref macros, ref mut files, .. } = self;
+            for fc in files.values_mut() {
+                resolve_item_list_macros(&mut fc.0, macros)?;
+            }
+        }
+        Ok(self)
+    }
+
+    // Build a configuration with only the entries of this one that are
+    // missing from `other` or differ from the ones there, e.g., to see
+    // which settings a merged configuration overrides on top of its base
+    pub fn subtract(&self, other: &Config) -> Config {
+        let files = self.files.iter().filter_map(|(file_name, fc)| {
+            let other_items = other.get_file_items(file_name)
+                .map_or(&[][..], |items| &items.0[..]);
+            let items = (fc.0).0.iter()
+                .filter(|item| !other_items.iter().any(|oi| same_value(*item, oi)))
+                .cloned()
+                .collect::<Vec<_>>();
+            if items.is_empty() {
+                None
+            } else {
+                Some((file_name.clone(), FileConfig(ItemList(items))))
+            }
+        }).collect();
+        Config {
+            version: self.version,
+            tag_map: subtract_map(&self.tag_map, &other.tag_map),
+            groups: subtract_map(&self.groups, &other.groups),
+            macros: subtract_map(&self.macros, &other.macros),
+            normalize_paths: self.normalize_paths && !other.normalize_paths,
+            warnings: vec![],
+            files: files,
+        }
+    }
+}
+
+fn subtract_map<V>(ours: &HashMap<String, V>, theirs: &HashMap<String, V>) -> HashMap<String, V>
+        where V: serde::Serialize + Clone {
+    ours.iter()
+        .filter(|&(k, v)| !theirs.get(k).map_or(false, |tv| same_value(v, tv)))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
+
+// Compare two configuration entries by their YAML representation; the
+// entries contain `HashMap`s, so we ignore the order of mapping keys
+fn same_value<T: serde::Serialize>(a: &T, b: &T) -> bool {
+    match (serde_yaml::to_value(a), serde_yaml::to_value(b)) {
+        (Ok(ref a), Ok(ref b)) => same_yaml_value(a, b),
+        _ => false
+    }
+}
+
+fn same_yaml_value(a: &serde_yaml::Value, b: &serde_yaml::Value) -> bool {
+    use serde_yaml::Value;
+    match (a, b) {
+        (&Value::Sequence(ref a), &Value::Sequence(ref b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| same_yaml_value(x, y))
+        }
+        (&Value::Mapping(ref a), &Value::Mapping(ref b)) => {
+            a.len() == b.len() && a.iter().all(|(k, v)| {
+                b.get(k).map_or(false, |bv| same_yaml_value(v, bv))
+            })
+        }
+        _ => a == b
+    }
+}
+
+// Convert a V1 configuration to V2; the V2 format is the first one with
+// an explicit `version` key, and is otherwise identical to V1, so this
+// only needs to update the version
+pub fn migrate_v1_to_v2(mut cfg: Config) -> Config {
+    assert_eq!(cfg.version, SchemaVersion::V1);
+    cfg.version = SchemaVersion::V2;
+    cfg
+}
+
+// Migrate a configuration of any version to the current one; the
+// caller gets the deprecation warnings through `Config::warnings`
+fn migrate(mut cfg: Config) -> Config {
+    if cfg.version == SchemaVersion::V1 {
+        cfg = migrate_v1_to_v2(cfg);
+        cfg.warnings.push(format!("cross-check configuration format version 1 is deprecated, \
+                                   add `version: {}` to the configuration file",
+                                  SchemaVersion::CURRENT.number()));
+    }
+    assert_eq!(cfg.version, SchemaVersion::CURRENT);
+    cfg
 }
 
 pub fn parse_string(s: &str) -> Result<Config, String> {
-    serde_yaml::from_str(s).map_err(|e| format!("serde_yaml error: {}", e))
+    serde_yaml::from_str(s)
+        .map(migrate)
+        .map_err(|e| format!("serde_yaml error: {}", e))
+}
+
+// Parse the contents of the given configuration file, and record
+// the location of each item in the file, e.g., for diagnostics
+pub fn parse_file_string(s: &str, file: &str) -> Result<Config, String> {
+    let mut cfg = parse_string(s)?;
+    location::set_item_locations(&mut cfg, s, file)?;
+    for warning in cfg.warnings.iter_mut() {
+        *warning = format!("{}: {}", file, warning);
+    }
+    Ok(cfg)
+}
+
+// Parse a single configuration item from a JSON string, e.g., one passed
+// inline to a `#[cross_check(config = "...")]` attribute
+pub fn parse_json_string<T: serde::de::DeserializeOwned>(s: &str) -> Result<T, String> {
+    serde_json::from_str(s).map_err(|e| format!("serde_json error: {}", e))
+}
+
+pub fn write_string(cfg: &Config) -> Result<String, String> {
+    serde_yaml::to_string(cfg).map_err(|e| format!("serde_yaml error: {}", e))
 }
 
 #[cfg(test)]
@@ -311,6 +1121,327 @@ mod tests {
                    XCheckType::Fixed(1234));
         assert_eq!(parse_test_yaml::<XCheckType>("{ \"djb2\": \"foo\" }"),
                    XCheckType::Djb2(String::from("foo")));
+        assert_eq!(parse_test_yaml::<XCheckType>("crc32"),
+                   XCheckType::Crc32);
+        assert_eq!(parse_test_yaml::<XCheckType>("blake3"),
+                   XCheckType::Blake3);
+        assert_eq!(parse_test_yaml::<XCheckType>("siphash"),
+                   XCheckType::SipHash);
+        assert_eq!(parse_test_yaml::<XCheckType>("adler32"),
+                   XCheckType::Adler32);
+    }
+
+    #[test]
+    fn test_check_mode() {
+        assert_eq!(parse_test_yaml::<CheckMode>("hash"), CheckMode::Hash);
+        assert_eq!(parse_test_yaml::<CheckMode>("eq"), CheckMode::Eq);
+        assert_eq!(CheckMode::from_str("eq"), Some(CheckMode::Eq));
+        assert_eq!(CheckMode::from_str("foo"), None);
+    }
+
+    #[test]
+    fn test_tag_map() {
+        let cfg = parse_test_yaml::<Config>(r#"
+tag_map:
+  MY_TAG: 0x42
+main.rs:
+  - item: function
+    name: foo
+    entry_extra:
+      - { custom: "x", tag: MY_TAG }
+"#);
+        assert_eq!(cfg.get_tag("MY_TAG"), Some(0x42));
+        assert_eq!(cfg.get_tag("OTHER_TAG"), None);
+        assert!(cfg.get_file_items("main.rs").is_some());
+        assert!(cfg.get_file_items("tag_map").is_none());
+    }
+
+    #[test]
+    fn test_normalize_paths() {
+        let cfg = parse_test_yaml::<Config>(r#"
+normalize_paths: true
+main.rs: []
+"#);
+        assert!(cfg.normalize_paths());
+        assert!(cfg.get_file_items("normalize_paths").is_none());
+        assert!(!parse_test_yaml::<Config>("main.rs: []").normalize_paths());
+        let cfg = parse_test_yaml::<Config>("main.rs: []").merge(cfg);
+        assert!(cfg.normalize_paths());
+    }
+
+    #[test]
+    fn test_arg_extra() {
+        let fcfg = parse_test_yaml::<FunctionConfig>(r#"
+name: foo
+arg_extra:
+  x:
+    - "custom_fn(x)"
+    - { custom: "other_fn(x)", tag: FUNCTION_ARG }
+"#);
+        let extra = fcfg.arg_extra["x"].iter()
+            .map(ArgExtraXCheck::to_extra_xcheck)
+            .collect::<Vec<_>>();
+        assert_eq!(extra.len(), 2);
+        assert_eq!(extra[0].custom, "custom_fn(x)");
+        match extra[0].tag {
+            ExtraXCheckTag::Builtin(XCheckTag::Unknown) => {}
+            ref tag => panic!("unexpected tag: {:?}", tag)
+        }
+        assert_eq!(extra[1].custom, "other_fn(x)");
+        match extra[1].tag {
+            ExtraXCheckTag::Builtin(XCheckTag::FunctionArg) => {}
+            ref tag => panic!("unexpected tag: {:?}", tag)
+        }
+    }
+
+    #[test]
+    fn test_item_is_empty() {
+        let items = parse_test_yaml::<ItemList>(r#"
+- item: function
+  name: foo
+  c_name: c_foo
+- item: function
+  name: bar
+  entry: none
+- item: struct
+  name: Baz
+- item: struct
+  name: Qux
+  include_size: true
+- item: defaults
+"#);
+        let empty = items.items().iter().map(ItemConfig::is_empty).collect::<Vec<_>>();
+        assert_eq!(empty, vec![true, false, true, false, true]);
+    }
+
+    #[test]
+    fn test_field_path() {
+        assert_eq!(FieldIndex::from_str("outer.inner.leaf").split_path(),
+                   Some((FieldIndex::from_str("outer"),
+                         FieldIndex::from_str("inner.leaf"))));
+        assert_eq!(FieldIndex::from_str("0.leaf").split_path(),
+                   Some((FieldIndex::Int(0), FieldIndex::from_str("leaf"))));
+        assert_eq!(FieldIndex::from_str("leaf").split_path(), None);
+        assert_eq!(FieldIndex::Int(1).split_path(), None);
+    }
+
+    #[test]
+    fn test_field_regex() {
+        assert_eq!(parse_test_yaml::<FieldIndex>("\"/^pad_[0-9]+$/\""),
+                   FieldIndex::Regex(FieldRegex::new("^pad_[0-9]+$").unwrap()));
+        assert_eq!(parse_test_yaml::<FieldIndex>("\"pad_0\""),
+                   FieldIndex::from_str("pad_0"));
+        assert_eq!(parse_test_yaml::<FieldIndex>("3"), FieldIndex::Int(3));
+        assert!(serde_yaml::from_str::<FieldIndex>("\"/pad_(/\"").is_err());
+
+        let re = FieldIndex::Regex(FieldRegex::new("^pad_[0-9]+$").unwrap());
+        assert!(re.matches(&FieldIndex::from_str("pad_12")));
+        assert!(!re.matches(&FieldIndex::from_str("pad_x")));
+        assert!(!re.matches(&FieldIndex::Int(0)));
+        assert!(!FieldIndex::from_str("pad_12").matches(&FieldIndex::from_str("pad_12")));
+        let re_yaml = serde_yaml::to_string(&re).unwrap();
+        assert_eq!(serde_yaml::from_str::<FieldIndex>(&re_yaml).unwrap(), re);
+    }
+
+    #[test]
+    fn test_groups() {
+        let cfg = parse_test_yaml::<Config>(r#"
+groups:
+  net_io: { enabled: false }
+main.rs:
+  - item: function
+    name: foo
+    group: net_io
+"#);
+        assert_eq!(cfg.get_group("net_io").and_then(|g| g.enabled), Some(false));
+        assert!(cfg.get_group("other").is_none());
+        let items = cfg.get_file_items("main.rs").unwrap().items();
+        assert_eq!(items[0].group(), Some("net_io"));
+    }
+
+    #[test]
+    fn test_macros() {
+        let cfg = parse_test_yaml::<Config>(r#"
+macros:
+  COMMON:
+    - item: function
+      name: foo
+    - item: function
+      name: bar
+main.rs:
+  - $COMMON
+  - item: function
+    name: baz
+    nested:
+      - $COMMON
+"#).resolve_macros().unwrap();
+        let items = cfg.get_file_items("main.rs").unwrap().items();
+        let names = items.iter().map(|item| item.name()).collect::<Vec<_>>();
+        assert_eq!(names, vec![Some("foo"), Some("bar"), Some("baz")]);
+        assert_eq!(items[2].nested_items().unwrap().items().len(), 2);
+
+        let cfg = parse_test_yaml::<Config>(r#"
+main.rs:
+  - $MISSING
+"#);
+        assert!(cfg.resolve_macros().is_err());
+    }
+
+    #[test]
+    fn test_c_name() {
+        let items = parse_test_yaml::<ItemList>(r#"
+- item: function
+  name: foo_bar
+  c_name: FooBar
+- item: function
+  name: baz
+  c_name: foo_bar
+"#);
+        let named = NamedItemList::new(&items);
+        assert_eq!(named.name_map["FooBar"].name(), Some("foo_bar"));
+        assert_eq!(named.name_map["foo_bar"].name(), Some("foo_bar"));
+        assert_eq!(named.name_map["baz"].name(), Some("baz"));
+    }
+
+    #[test]
+    fn test_subtract() {
+        let base = r#"
+version: 2
+tag_map:
+  MY_TAG: 0x42
+main.rs:
+  - item: function
+    name: foo
+    args: { a: none, b: default }
+  - item: function
+    name: bar
+"#;
+        let overrides = r#"
+version: 2
+tag_map:
+  OTHER_TAG: 0x43
+main.rs:
+  - item: function
+    name: foo
+    args: { b: default, a: none }
+  - item: function
+    name: bar
+    entry: disabled
+"#;
+        let base_cfg = parse_string(base).unwrap();
+        let merged = parse_string(base).unwrap().merge(parse_string(overrides).unwrap());
+        let diff = merged.subtract(&base_cfg);
+        assert_eq!(diff.get_tag("MY_TAG"), None);
+        assert_eq!(diff.get_tag("OTHER_TAG"), Some(0x43));
+        let items = diff.get_file_items("main.rs").unwrap().items();
+        assert_eq!(items.len(), 1);
+        match items[0] {
+            ItemConfig::Function(ref fcfg) => {
+                assert_eq!(fcfg.name, "bar");
+                assert_eq!(fcfg.entry, Some(XCheckType::Disabled));
+            }
+            _ => panic!("expected function item")
+        }
+        assert!(base_cfg.subtract(&base_cfg).get_file_items("main.rs").is_none());
+    }
+
+    #[test]
+    fn test_named_lists() {
+        let main_items = parse_test_yaml::<ItemList>(r#"
+- item: function
+  name: foo
+  disable_xchecks: true
+- item: function
+  name: bar
+"#);
+        let sidecar_items = parse_test_yaml::<ItemList>(r#"
+- item: function
+  name: foo
+"#);
+        let named = NamedItemList::from_lists(&[&main_items, &sidecar_items]);
+        assert_eq!(named.name_map.len(), 2);
+        assert!(::std::ptr::eq(named.name_map["foo"], &sidecar_items.items()[0]));
+        assert!(::std::ptr::eq(named.name_map["bar"], &main_items.items()[1]));
+    }
+
+    #[test]
+    fn test_locations() {
+        let cfg = parse_file_string(r#"
+groups:
+  net_io: { enabled: false }
+
+main.rs:
+  - item: defaults
+    disable_xchecks: true
+  - item: function
+    name: foo
+    c_name: c_foo
+    nested:
+      - item: function
+        name: bar
+  - { item: struct, name: Baz }
+"#, "test.yaml").unwrap();
+        let items = cfg.get_file_items("main.rs").unwrap();
+        let locations = NamedItemList::new(items).inverse_map();
+        let loc = |line| ConfigLocation { file: String::from("test.yaml"), line: line };
+        assert_eq!(locations.len(), 3);
+        assert_eq!(locations["foo"], loc(8));
+        assert_eq!(locations["c_foo"], loc(8));
+        assert_eq!(locations["Baz"], loc(14));
+        assert_eq!(format!("{}", locations["Baz"]), "test.yaml:14");
+
+        let nested = items.items()[1].nested_items().unwrap();
+        assert_eq!(NamedItemList::new(nested).inverse_map()["bar"], loc(12));
+    }
+
+    #[test]
+    fn test_write_string() {
+        let cfg = parse_string(r#"
+main.rs:
+  - item: function
+    name: foo
+    args:
+      a: { fixed: 0x1234 }
+"#).unwrap();
+        let yaml = write_string(&cfg).unwrap();
+        let cfg2 = parse_string(&yaml).unwrap();
+        assert_eq!(write_string(&cfg2).unwrap(), yaml);
+    }
+
+    #[test]
+    fn test_version() {
+        let cfg = parse_string(r#"
+main.rs:
+  - item: function
+    name: foo
+"#).unwrap();
+        assert_eq!(cfg.version(), SchemaVersion::V2);
+        assert!(cfg.get_file_config("version").is_none());
+        assert_eq!(cfg.warnings().len(), 1);
+
+        let cfg = parse_string(r#"
+version: 2
+main.rs:
+  - item: function
+    name: foo
+"#).unwrap();
+        assert_eq!(cfg.version(), SchemaVersion::V2);
+        assert!(cfg.get_file_config("main.rs").is_some());
+        assert!(cfg.get_file_config("version").is_none());
+        assert!(cfg.warnings().is_empty());
+
+        assert!(parse_string("version: 3\n").is_err());
+    }
+
+    #[test]
+    fn test_json_string() {
+        let func: FunctionConfig = parse_json_string(
+            r#"{"disable_xchecks": true, "entry": {"fixed": 1234}}"#).unwrap();
+        assert_eq!(func.disable_xchecks, Some(true));
+        assert_eq!(func.entry, Some(XCheckType::Fixed(1234)));
+        assert_eq!(func.exit, None);
+
+        assert!(parse_json_string::<FunctionConfig>("{").is_err());
     }
 
     #[test]
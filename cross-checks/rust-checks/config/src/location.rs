@@ -0,0 +1,137 @@
+// Source locations of the configuration items, so the plugin can tell
+// users which configuration entry is responsible for a decision; serde
+// does not report them, so we get them from a second pass over the
+// YAML events, then match the events to the items in order
+
+use std::fmt;
+
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::Marker;
+
+use super::{Config, ItemList};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigLocation {
+    // Name of the configuration file, or its URL
+    pub file: String,
+
+    // 1-based line of the start of the item
+    pub line: usize,
+}
+
+impl fmt::Display for ConfigLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+// Minimal YAML document tree, which only
+// keeps the starting line of each node
+enum Node {
+    Scalar(String, usize),
+    Sequence(Vec<Node>, usize),
+    Mapping(Vec<(Node, Node)>, usize),
+    Alias(usize),
+}
+
+impl Node {
+    fn line(&self) -> usize {
+        match *self {
+            Node::Scalar(_, line) |
+            Node::Sequence(_, line) |
+            Node::Mapping(_, line) |
+            Node::Alias(line) => line
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Node> {
+        match *self {
+            Node::Mapping(ref entries, _) => entries.iter()
+                .find(|&&(ref k, _)| matches_scalar(k, key))
+                .map(|&(_, ref v)| v),
+            _ => None
+        }
+    }
+}
+
+fn matches_scalar(node: &Node, s: &str) -> bool {
+    match *node {
+        Node::Scalar(ref ns, _) => ns == s,
+        _ => false
+    }
+}
+
+struct EventList(Vec<(Event, Marker)>);
+
+impl MarkedEventReceiver for EventList {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        self.0.push((ev, mark));
+    }
+}
+
+// Build the next node from the event stream; returns `None`
+// at the end of the enclosing sequence or mapping
+fn build_node<I: Iterator<Item=(Event, Marker)>>(events: &mut I) -> Option<Node> {
+    let (ev, mark) = events.next()?;
+    let line = mark.line();
+    match ev {
+        Event::Scalar(s, ..) => Some(Node::Scalar(s, line)),
+        Event::Alias(_) => Some(Node::Alias(line)),
+        Event::SequenceStart(_) => {
+            let mut nodes = vec![];
+            while let Some(node) = build_node(events) {
+                nodes.push(node);
+            }
+            Some(Node::Sequence(nodes, line))
+        }
+        Event::MappingStart(_) => {
+            let mut entries = vec![];
+            while let Some(key) = build_node(events) {
+                let value = build_node(events)?;
+                entries.push((key, value));
+            }
+            Some(Node::Mapping(entries, line))
+        }
+        _ => None
+    }
+}
+
+// Set the locations of the items in a list, and their nested items,
+// from the nodes of the YAML sequence they were parsed from
+fn set_list_locations(items: &mut ItemList, nodes: &[Node], file: &str) {
+    for (item, node) in items.0.iter_mut().zip(nodes.iter()) {
+        item.set_location(ConfigLocation {
+            file: String::from(file),
+            line: node.line(),
+        });
+        if let (Some(nested), Some(&Node::Sequence(ref nested_nodes, _))) =
+                (item.nested_items_mut(), node.get("nested")) {
+            set_list_locations(nested, nested_nodes, file);
+        }
+    }
+}
+
+// Set the locations of all the items parsed from the given
+// source, which must be the source of the entire configuration;
+// this needs to happen before the configuration gets merged
+// with others and before its macros get resolved
+pub(crate) fn set_item_locations(cfg: &mut Config, source: &str, file: &str)
+        -> Result<(), String> {
+    let mut events = EventList(vec![]);
+    Parser::new(source.chars()).load(&mut events, false)
+        .map_err(|e| format!("yaml_rust error: {}", e))?;
+    let mut events = events.0.into_iter().filter(|&(ref ev, _)| match *ev {
+        Event::StreamStart | Event::DocumentStart => false,
+        _ => true
+    });
+    if let Some(Node::Mapping(entries, _)) = build_node(&mut events) {
+        for (key, value) in entries.into_iter() {
+            if let (Node::Scalar(file_name, _), Node::Sequence(nodes, _)) = (key, value) {
+                if let Some(fc) = cfg.files.get_mut(&file_name) {
+                    set_list_locations(&mut fc.0, &nodes, file);
+                }
+            }
+        }
+    }
+    Ok(())
+}
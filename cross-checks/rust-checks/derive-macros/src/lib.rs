@@ -13,47 +13,463 @@ fn get_cross_check_args(attrs: &[syn::Attribute]) -> Option<xcfg::attr::ArgList>
          .map(|attr| xcfg::attr::get_syn_item_args(&attr.value))
 }
 
-fn xcheck_hash_derive(s: synstructure::Structure) -> quote::Tokens {
-    let top_args = get_cross_check_args(&s.ast().attrs[..]).unwrap_or_default();
-
-    // Allow users to override __XCHA and __XCHS
-    let ahasher = top_args.get_ident_arg("ahasher", "__XCHA");
-    let shasher = top_args.get_ident_arg("shasher", "__XCHS");
+// Same djb2 hash function that the compiler plugin uses for names
+fn djb2_hash(s: &str) -> u32 {
+    s.bytes().fold(5381u32, |h, c| h.wrapping_mul(33).wrapping_add(c as u32))
+}
 
-    // Iterate through all fields, inserting the hash computation for each field
-    let hash_fields = s.each(|f| {
-        get_cross_check_args(&f.ast().attrs[..]).and_then(|args| {
-            // FIXME: figure out the argument priorities here
-            if args.contains_key("none") ||
-               args.contains_key("disabled") {
-                // Cross-checking is disabled
-                Some(quote::Tokens::new())
-            } else if let Some(ref sub_arg) = args.get("fixed_hash") {
-                // FIXME: should try parsing this as an integer
-                let id = sub_arg.get_str_ident();
-                Some(quote! { h.write_u64(#id) })
-            } else if let Some(ref sub_arg) = args.get("custom_hash") {
-                let id = sub_arg.get_str_ident();
+// Build the hash computation for a single structure field
+fn hash_field(f: &synstructure::BindingInfo, ahasher: &syn::Ident,
+              shasher: &syn::Ident, field_salt: bool) -> quote::Tokens {
+    // If requested, XOR the hash of the field name into the hash of
+    // each named field, so that equal values in different fields
+    // produce different hashes
+    let salt = match f.ast().ident {
+        Some(ref ident) if field_salt => {
+            let salt = djb2_hash(ident.as_ref()) as u64;
+            quote! { ^ #salt }
+        }
+        _ => quote::Tokens::new()
+    };
+    let field_hash = get_cross_check_args(&f.ast().attrs[..]).and_then(|args| {
+        // FIXME: figure out the argument priorities here
+        if args.contains_key("none") ||
+           args.contains_key("disabled") {
+            // Cross-checking is disabled
+            Some(quote::Tokens::new())
+        } else if let Some(ref sub_arg) = args.get("fixed_hash") {
+            // FIXME: should try parsing this as an integer
+            let id = sub_arg.get_str_ident();
+            Some(quote! { h.write_u64(#id #salt) })
+        } else if args.contains_key("crc32") {
+            // Hash the raw bytes of the field, which is fast for large arrays
+            Some(quote! {
+                use cross_check_runtime::hash::crc32::crc32_value;
+                h.write_u64(crc32_value(#f) as u64 #salt)
+            })
+        } else if args.contains_key("blake3") {
+            // Hash the raw bytes of the field using a strong hash
+            Some(quote! {
+                use cross_check_runtime::hash::blake3::blake3_value;
+                h.write_u64(blake3_value(#f) #salt)
+            })
+        } else if args.contains_key("adler32") {
+            // Hash the raw bytes of the field using a cheap checksum
+            Some(quote! {
+                use cross_check_runtime::hash::adler32::adler32_value;
+                h.write_u64(adler32_value(#f) as u64 #salt)
+            })
+        } else if args.contains_key("siphash") {
+            // Hash the field through its `Hash` implementation
+            Some(quote! {
+                use cross_check_runtime::hash::siphash::siphash_value;
+                h.write_u64(siphash_value(#f) #salt)
+            })
+        } else if let Some(ref sub_arg) = args.get("remote_hash") {
+            // The field has a type from another crate, so we hash it through
+            // the local wrapper that the plugin generated for that type
+            let wrapper = sub_arg.get_str_ident();
+            Some(quote! {
+                use cross_check_runtime::hash::CrossCheckHash;
+                h.write_u64(CrossCheckHash::cross_check_hash_depth::<#ahasher, #shasher>(&#wrapper(#f), _depth - 1) #salt);
+            })
+        } else if let Some(ref sub_arg) = args.get("custom_hash") {
+            let id = sub_arg.get_str_ident();
+            if salt.as_str().is_empty() {
                 Some(quote! { #id::<#ahasher, #shasher, Self, _>(&mut h, self, #f, _depth - 1) })
             } else {
-                None
+                // The custom function writes directly into the hasher it
+                // receives, so we give it a separate one and salt its result
+                Some(quote! {
+                    let mut fh = #ahasher::default();
+                    #id::<#ahasher, #shasher, Self, _>(&mut fh, self, #f, _depth - 1);
+                    h.write_u64(::std::hash::Hasher::finish(&fh) #salt);
+                })
             }
-        }).unwrap_or_else(|| {
-            // Default implementation
+        } else {
+            None
+        }
+    }).unwrap_or_else(|| {
+        // Default implementation
+        quote! {
+            use cross_check_runtime::hash::CrossCheckHash;
+            h.write_u64(CrossCheckHash::cross_check_hash_depth::<#ahasher, #shasher>(#f, _depth - 1) #salt);
+        }
+    });
+    // Only hash the field if its `hash_if` condition holds, e.g., for the
+    // payload of a tagged union whose meaning depends on another field;
+    // the condition can be in any of the #[cross_check_hash] attributes
+    let hash_if = f.ast().attrs.iter()
+        .filter(|attr| attr.name() == "cross_check_hash")
+        .filter_map(|attr| {
+            xcfg::attr::get_syn_item_args(&attr.value)
+                .get("hash_if")
+                .map(|cond| String::from(cond.as_str()))
+        })
+        .next();
+    match hash_if {
+        Some(cond) => {
+            // The condition comes from the configuration, and gets
+            // inserted verbatim into the implementation, so it can
+            // refer to any other field through `self`
+            let mut cond_tokens = quote::Tokens::new();
+            cond_tokens.append(&cond);
+            quote! { if #cond_tokens { #field_hash } }
+        }
+        None => field_hash
+    }
+}
+
+// Build the hash computation for a group of integer fields that together
+// hold a single value, e.g., the `hi` and `lo` halves of a 64-bit integer;
+// we concatenate their bits, with the first field in the most significant
+// bits, and hash the result as a single `u64` value
+fn hash_field_group(group: &[&synstructure::BindingInfo], ahasher: &syn::Ident,
+                    shasher: &syn::Ident, field_salt: bool) -> quote::Tokens {
+    let salt = match group[0].ast().ident {
+        Some(ref ident) if field_salt => {
+            let salt = djb2_hash(ident.as_ref()) as u64;
+            quote! { ^ #salt }
+        }
+        _ => quote::Tokens::new()
+    };
+    // Only integer fields implement `FieldGroupBits`, so any other field
+    // type fails to compile, and so does a group wider than 64 bits; we
+    // report the latter here if we can tell the widths from the types,
+    // and fall back to the assertion in the generated code for the
+    // types we don't know, e.g., type aliases like `libc::c_int`
+    let tys = group.iter().map(|f| &f.ast().ty).collect::<Vec<_>>();
+    let known_bits = tys.iter().map(|ty| primitive_bits(ty)).collect::<Option<Vec<_>>>();
+    if let Some(bits) = known_bits {
+        let total_bits = bits.iter().sum::<u32>();
+        if total_bits > 64 {
+            let names = group.iter()
+                .map(|f| f.ast().ident.as_ref().map_or_else(String::new, |id| id.to_string()))
+                .collect::<Vec<_>>();
+            panic!("field group {} is {} bits wide, but groups must fit in 64 bits",
+                   names.join(":"), total_bits);
+        }
+    }
+    let parts = group.iter().zip(tys.iter()).map(|(f, ty)| quote! {
+        v = v.checked_shl(<#ty as FieldGroupBits>::BITS).unwrap_or(0) |
+            FieldGroupBits::group_bits(#f);
+    }).collect::<Vec<_>>();
+    quote! {
+        use cross_check_runtime::hash::{CrossCheckHash, FieldGroupBits};
+        let _field_group_wider_than_64_bits: [(); 0] =
+            [(); (0 #(+ <#tys as ::cross_check_runtime::hash::FieldGroupBits>::BITS)* > 64) as usize];
+        let mut v = 0u64;
+        #({ #parts })*
+        h.write_u64(CrossCheckHash::cross_check_hash_depth::<#ahasher, #shasher>(&v, _depth - 1) #salt);
+    }
+}
+
+// Get the width of an integer type from its name, if it's a primitive type
+fn primitive_bits(ty: &syn::Ty) -> Option<u32> {
+    let path = match *ty {
+        syn::Ty::Path(None, ref path) if path.segments.len() == 1 => path,
+        _ => return None
+    };
+    match path.segments[0].ident.as_ref() {
+        "u8" | "i8" | "bool" => Some(8),
+        "u16" | "i16" => Some(16),
+        "u32" | "i32" | "char" => Some(32),
+        "u64" | "i64" => Some(64),
+        _ => None
+    }
+}
+
+// Parse the `field_groups` argument, which lists the groups separated
+// by commas, and the fields of each group separated by colons,
+// e.g., `field_groups="hi:lo,a:b"`
+fn parse_field_groups(arg: &str) -> Vec<Vec<String>> {
+    arg.split(',').map(|group| {
+        group.split(':').map(|field| String::from(field.trim())).collect()
+    }).collect()
+}
+
+// Build the hash computation for an enum that only hashes its discriminant
+fn discriminant_hash(s: &synstructure::Structure, ahasher: &syn::Ident,
+                     shasher: &syn::Ident) -> quote::Tokens {
+    let variants = match s.ast().body {
+        syn::Body::Enum(ref variants) => variants,
+        _ => panic!("discriminant_only is only valid for enums")
+    };
+    // Compute the discriminant of each variant the same way rustc does:
+    // explicit values are used as-is, and implicit ones are
+    // one more than the discriminant of the previous variant
+    let mut last_disc = None;
+    let mut offset = 0u64;
+    let arms = s.variants().iter().zip(variants.iter()).map(|(v, var)| {
+        if let Some(ref d) = var.discriminant {
+            last_disc = Some(d);
+            offset = 0;
+        }
+        let disc = match last_disc {
+            Some(d) => quote! { ((#d) as u64).wrapping_add(#offset) },
+            None    => quote! { #offset },
+        };
+        offset += 1;
+        let pat = v.pat();
+        quote! { #pat => #disc, }
+    }).collect::<Vec<_>>();
+    quote! {
+        use cross_check_runtime::hash::CrossCheckHash;
+        #[allow(unused_variables)]
+        let disc: u64 = match *self { #(#arms)* };
+        CrossCheckHash::cross_check_hash_depth::<#ahasher, #shasher>(&disc, _depth)
+    }
+}
+
+// Build the hash computation for a structure with the `parallel_hash`
+// option, which hashes each field with its own hasher in parallel,
+// and combines the field hashes using XOR; the result does not
+// depend on the order of the fields. All hashed fields must be `Sync`,
+// which we check for each field, so the errors name the field types.
+fn parallel_hash(s: &synstructure::Structure, hasher: &syn::Ident, ahasher: &syn::Ident,
+                 shasher: &syn::Ident, field_salt: bool) -> quote::Tokens {
+    let arms = s.variants().iter().map(|v| {
+        let pat = v.pat();
+        // Skip the disabled fields, instead of calling `finish`
+        // on a hasher that did not get any values
+        let fields = v.bindings().iter().filter(|f| {
+            get_cross_check_args(&f.ast().attrs[..]).map_or(true, |args| {
+                !args.contains_key("none") && !args.contains_key("disabled")
+            })
+        }).collect::<Vec<_>>();
+        let field_fns = fields.iter().map(|f| {
+            let field_hash = hash_field(f, ahasher, shasher, field_salt);
             quote! {
-                use cross_check_runtime::hash::CrossCheckHash;
-                h.write_u64(CrossCheckHash::cross_check_hash_depth::<#ahasher, #shasher>(#f, _depth - 1));
+                &|| -> u64 {
+                    #[allow(unused_mut)]
+                    let mut h = #hasher::default();
+                    { #field_hash }
+                    h.finish()
+                }
             }
+        }).collect::<Vec<_>>();
+        quote! {
+            #pat => {
+                // Check each field separately, so that the compiler
+                // names the field type that is not `Sync`
+                #(parallel_hash_requires_sync_fields(#fields);)*
+                let field_fns: &[&(Fn() -> u64 + Sync)] = &[#(#field_fns),*];
+                ::cross_check_runtime::hash::parallel::xor_join(field_fns)
+            }
+        }
+    });
+    quote! {
+        if _depth == 0 {
+            ::cross_check_runtime::hash::LEAF_RECORD_HASH
+        } else {
+            // The fields get hashed on other threads, so they must be `Sync`
+            fn parallel_hash_requires_sync_fields<T: ?Sized + Sync>(_: &T) {}
+            match *self { #(#arms)* }
+        }
+    }
+}
+
+// Build the hash computation for a `#[repr(transparent)]` structure,
+// which has the same layout as its only non-zero-sized field, so we
+// hash it as that field, at the same depth; all the other fields,
+// e.g., `PhantomData` markers, must be disabled
+fn transparent_hash(s: &synstructure::Structure, ahasher: &syn::Ident,
+                    shasher: &syn::Ident) -> quote::Tokens {
+    if s.variants().len() != 1 {
+        panic!("transparent_hash is only valid for structures");
+    }
+    let v = &s.variants()[0];
+    let mut hashed_fields = v.bindings().iter().filter(|f| {
+        get_cross_check_args(&f.ast().attrs[..]).map_or(true, |args| {
+            !args.contains_key("none") && !args.contains_key("disabled")
         })
     });
+    let f = match (hashed_fields.next(), hashed_fields.next()) {
+        (Some(f), None) => f,
+        _ => panic!("transparent_hash requires exactly one hashed field")
+    };
+    if get_cross_check_args(&f.ast().attrs[..]).map_or(false, |args| !args.is_empty()) {
+        panic!("transparent_hash does not support custom hashes for the inner field");
+    }
+    let pat = v.pat();
+    quote! {
+        use cross_check_runtime::hash::CrossCheckHash;
+        #[allow(unused_variables)]
+        match *self {
+            #pat => CrossCheckHash::cross_check_hash_depth::<#ahasher, #shasher>(#f, _depth)
+        }
+    }
+}
+
+// Build a copy of the bytes of a structure where all the bytes
+// outside its fields, i.e., its padding, are zeroed; the padding
+// inside the fields themselves is copied unchanged
+fn padding_zeroed_bytes(s: &synstructure::Structure) -> quote::Tokens {
+    if s.variants().len() != 1 {
+        panic!("ignore_padding is only valid for structures");
+    }
+    let v = &s.variants()[0];
+    let pat = v.pat();
+    let copy_fields = v.bindings().iter().map(|f| {
+        quote! {
+            let field_bytes = ::cross_check_runtime::hash::value_bytes(#f);
+            let offset = (#f as *const _ as *const u8 as usize) - base;
+            bytes[offset..offset + field_bytes.len()].copy_from_slice(field_bytes);
+        }
+    });
+    quote! {{
+        let base = self as *const Self as *const u8 as usize;
+        let mut bytes = vec![0u8; ::std::mem::size_of_val(self)];
+        match *self { #pat => { #({ #copy_fields })* } }
+        bytes
+    }}
+}
+
+fn xcheck_hash_derive(s: synstructure::Structure) -> quote::Tokens {
+    let top_args = get_cross_check_args(&s.ast().attrs[..]).unwrap_or_default();
+
+    // Allow users to override __XCHA and __XCHS
+    let ahasher = top_args.get_ident_arg("ahasher", "__XCHA");
+    let shasher = top_args.get_ident_arg("shasher", "__XCHS");
+
+    let field_salt = top_args.contains_key("field_salt");
+
+    let field_groups = top_args.get("field_groups")
+        .map_or(vec![], |arg| parse_field_groups(arg.as_str()));
+    if !field_groups.is_empty() && s.variants().len() != 1 {
+        panic!("field_groups is only valid for structures");
+    }
+
+    // Iterate through all fields, inserting the hash computation for each field
+    let field_order = top_args.get("field_order").map_or("declaration", |arg| arg.as_str());
+    let hash_fields = match field_order {
+        "declaration" if field_groups.is_empty() =>
+            s.each(|f| hash_field(f, &ahasher, &shasher, field_salt)),
+        "declaration" | "alphabetical" => {
+            // For the alphabetical order, hash the fields sorted by name, so
+            // that the hash does not depend on the order they're declared in.
+            // Tuple fields have no names, so the stable sort keeps them in
+            // their original order.
+            let arms = s.variants().iter().map(|v| {
+                let pat = v.pat();
+                let mut bindings = v.bindings().iter().collect::<Vec<_>>();
+                if field_order == "alphabetical" {
+                    bindings.sort_by_key(|f| f.ast().ident.as_ref().map(|id| id.to_string()));
+                }
+                let find_binding = |name: &str| {
+                    v.bindings().iter()
+                        .find(|f| f.ast().ident.as_ref().map_or(false, |id| id.as_ref() == name))
+                        .unwrap_or_else(|| panic!("unknown field in field_groups: {}", name))
+                };
+                let groups = field_groups.iter().map(|group| {
+                    group.iter().map(|name| find_binding(name)).collect::<Vec<_>>()
+                }).collect::<Vec<_>>();
+                // Each group gets hashed in place of its first field
+                // (in hashing order), and its other fields are skipped
+                let mut seen_groups = vec![false; groups.len()];
+                let field_hashes = bindings.into_iter().filter_map(|f| {
+                    let group_idx = groups.iter().position(|group| {
+                        group.iter().any(|gf| gf.binding == f.binding)
+                    });
+                    match group_idx {
+                        Some(idx) if seen_groups[idx] => None,
+                        Some(idx) => {
+                            seen_groups[idx] = true;
+                            Some(hash_field_group(&groups[idx], &ahasher, &shasher, field_salt))
+                        }
+                        None => Some(hash_field(f, &ahasher, &shasher, field_salt))
+                    }
+                }).collect::<Vec<_>>();
+                quote! { #pat => { #({ #field_hashes })* } }
+            });
+            quote! { #(#arms)* }
+        }
+        _ => panic!("unknown field_order: {}", field_order)
+    };
 
     let hash_code = top_args.get("custom_hash").map(|sub_arg| {
         // Hash this value by calling the specified function
         let id = sub_arg.get_str_ident();
         quote! { #id::<#ahasher, #shasher>(&self, _depth) }
+    }).or_else(|| {
+        if top_args.contains_key("discriminant_only") {
+            // Only hash the enum discriminant, ignoring the variant payloads
+            Some(discriminant_hash(&s, &ahasher, &shasher))
+        } else if top_args.contains_key("hash_display") {
+            // Hash the string produced by the `Display` implementation,
+            // for types that have no better way of being hashed
+            Some(quote! {
+                ::cross_check_runtime::hash::djb2::djb2_str(&format!("{}", self))
+            })
+        } else if top_args.contains_key("hash_debug") {
+            // Same as above, but using the `Debug` implementation
+            Some(quote! {
+                ::cross_check_runtime::hash::djb2::djb2_str(&format!("{:?}", self))
+            })
+        } else if top_args.contains_key("ignore_padding") {
+            // Hash the raw bytes of the value like `bitfield_hash` below,
+            // but with the padding bytes zeroed, since their contents
+            // can differ between C and Rust
+            let hasher = top_args.get_ident_arg("field_hasher", ahasher.clone());
+            let bytes = padding_zeroed_bytes(&s);
+            Some(quote! {
+                let mut h = #hasher::default();
+                h.write(&#bytes);
+                h.finish()
+            })
+        } else if top_args.contains_key("bitfield_hash") {
+            // Hash the raw bytes of the value, which matches the memory
+            // layout of C structures with bit fields. SAFETY: this reads
+            // every byte of the value, including the padding bytes, whose
+            // contents are unspecified; this is only valid for structures
+            // that have no padding, or whose padding is always zeroed
+            // (on both the C and Rust sides)
+            let hasher = top_args.get_ident_arg("field_hasher", ahasher.clone());
+            Some(quote! {
+                let mut h = #hasher::default();
+                h.write(::cross_check_runtime::hash::value_bytes(self));
+                h.finish()
+            })
+        } else if top_args.contains_key("transparent_hash") {
+            // Hash a `#[repr(transparent)]` structure as its inner field
+            Some(transparent_hash(&s, &ahasher, &shasher))
+        } else {
+            None
+        }
     }).unwrap_or_else(|| {
         // Hash this value using the default algorithm
-        let hasher = top_args.get_ident_arg("field_hasher", ahasher);
+        let hasher = top_args.get_ident_arg("field_hasher", ahasher.clone());
+        if top_args.contains_key("parallel_hash") {
+            if !field_groups.is_empty() {
+                panic!("field_groups is not supported with parallel_hash");
+            }
+            return parallel_hash(&s, &hasher, &ahasher, &shasher, field_salt);
+        }
+        if top_args.contains_key("field_hasher") {
+            // The replacement hasher is a concrete type, so we can keep
+            // one instance of it per thread and reset it between values,
+            // instead of creating a new one for each value
+            return quote! {
+                if _depth == 0 {
+                    ::cross_check_runtime::hash::LEAF_RECORD_HASH
+                } else {
+                    thread_local! {
+                        static __C2RUST_HASHER: ::std::cell::RefCell<Option<#hasher>> =
+                            ::std::cell::RefCell::new(None);
+                    }
+                    // Nested values of the same type find the slot empty
+                    // while we hold the hasher, and create their own
+                    #[allow(unused_mut)]
+                    let mut h = __C2RUST_HASHER.with(|slot| slot.borrow_mut().take())
+                        .unwrap_or_default();
+                    match *self { #hash_fields }
+                    let hash = h.finish();
+                    ::cross_check_runtime::hash::CrossCheckHasher::reset(&mut h);
+                    __C2RUST_HASHER.with(|slot| *slot.borrow_mut() = Some(h));
+                    hash
+                }
+            };
+        }
         quote! {
             if _depth == 0 {
                 ::cross_check_runtime::hash::LEAF_RECORD_HASH
@@ -65,6 +481,28 @@ fn xcheck_hash_derive(s: synstructure::Structure) -> quote::Tokens {
             }
         }
     });
+    let hash_code = if top_args.contains_key("include_size") {
+        // Mix the size of the type into the hash, so that layout
+        // changes get caught even if the field values still match
+        quote! {
+            let __c2rust_hash: u64 = { #hash_code };
+            __c2rust_hash ^ (::std::mem::size_of::<Self>() as u64)
+        }
+    } else {
+        hash_code
+    };
+    let hash_code = top_args.get("timeout_ms").map(|sub_arg| {
+        // Limit the time spent hashing this value, e.g., for cyclic data
+        let timeout_ms = match *sub_arg {
+            xcfg::attr::ArgValue::Int(n) => n as u64,
+            _ => panic!("invalid timeout_ms: {:?}", sub_arg)
+        };
+        quote! {
+            ::cross_check_runtime::hash::with_hash_deadline(
+                ::std::time::Duration::from_millis(#timeout_ms),
+                || { #hash_code })
+        }
+    }).unwrap_or(hash_code);
     s.bound_impl("::cross_check_runtime::hash::CrossCheckHash", quote! {
         fn cross_check_hash_depth<__XCHA, __XCHS>(&self, _depth: usize) -> u64
                 where __XCHA: ::cross_check_runtime::hash::CrossCheckHasher,
@@ -72,6 +72,10 @@ fn test_field_hasher() {
         assert_eq!(
             XCH::cross_check_hash::<Djb2Hasher, SimpleHasher>(&ts),
             Some(0x12345678_u64));
+        // The second computation reuses the reset hasher
+        assert_eq!(
+            XCH::cross_check_hash::<Djb2Hasher, SimpleHasher>(&ts),
+            Some(0x12345678_u64));
     });
 }
 
@@ -201,3 +205,318 @@ fn test_multi_field_hash() {
     });
 
 }
+
+#[test]
+fn test_field_order() {
+    // Same as test_multi_field_hash, but with the fields declared in reverse
+    test_struct!([field_order="alphabetical"]
+                 { [] y: u64 = 1,
+                   [] x: u16 = 0xa5a5 }
+                 |ts| {
+        assert_eq!(
+            XCH::cross_check_hash::<Djb2Hasher, SimpleHasher>(&ts),
+            Some(0x3d17c937_u64));
+    });
+}
+
+#[test]
+fn test_discriminant_only() {
+    #[derive(CrossCheckHash)]
+    #[cross_check_hash(discriminant_only)]
+    #[allow(dead_code)]
+    enum TestEnum {
+        A(u64),
+        B { x: u16 },
+        C,
+    }
+
+    #[derive(CrossCheckHash)]
+    #[cross_check_hash(discriminant_only)]
+    #[allow(dead_code)]
+    enum TestCEnum {
+        A = 5,
+        B,
+        C = 10,
+    }
+
+    assert_eq!(
+        XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&TestEnum::A(0x1234)),
+        XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&0u64));
+    assert_eq!(
+        XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&TestEnum::B { x: 7 }),
+        XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&1u64));
+    assert_eq!(
+        XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&TestCEnum::B),
+        XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&6u64));
+    assert_eq!(
+        XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&TestCEnum::C),
+        XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&10u64));
+}
+
+#[test]
+fn test_crc32_field() {
+    test_struct!([]
+                 { [crc32] x: [u8; 9] = *b"123456789" }
+                 |ts| {
+        assert_eq!(
+            XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&ts),
+            Some(0x0f0f0f0f_c4fb3628_u64));
+    });
+}
+
+#[test]
+fn test_adler32_field() {
+    use cross_check_runtime::hash::adler32::adler32_value;
+    test_struct!([]
+                 { [adler32] x: [u8; 4] = *b"abcd" }
+                 |ts| {
+        // SimpleHasher mixes the u64 type into the value
+        assert_eq!(
+            XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&ts),
+            Some(adler32_value(b"abcd") as u64 ^ 0x0f0f0f0f_0f0f0f0e_u64));
+    });
+}
+
+#[test]
+fn test_siphash_field() {
+    use cross_check_runtime::hash::siphash::siphash_value;
+    test_struct!([]
+                 { [siphash] x: String = String::from("foo") }
+                 |ts| {
+        // SimpleHasher mixes the u64 type into the value
+        assert_eq!(
+            XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&ts),
+            Some(siphash_value("foo") ^ 0x0f0f0f0f_0f0f0f0e_u64));
+    });
+}
+
+#[test]
+fn test_timeout() {
+    use cross_check_runtime::hash::{CrossCheckHasher, TIMEOUT_HASH};
+    use std::borrow::Borrow;
+    use std::thread;
+    use std::time::Duration;
+
+    #[derive(CrossCheckHash)]
+    #[cross_check_hash(timeout_ms=1)]
+    struct Inner {
+        x: u64,
+    }
+
+    fn slow_hash<XCHA, XCHS, S, F>(h: &mut XCHA, _: &S, field: F, depth: usize)
+        where XCHA: CrossCheckHasher, XCHS: CrossCheckHasher,
+              F: Borrow<Inner> {
+        thread::sleep(Duration::from_millis(5));
+        h.write_u64(XCH::cross_check_hash_depth::<XCHA, XCHS>(field.borrow(), depth))
+    }
+
+    assert_eq!(
+        XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&Inner { x: 0x12345678 }),
+        Some(0x12345678_u64));
+    test_struct!([timeout_ms=1]
+                 { [custom_hash="slow_hash"] inner: Inner = Inner { x: 0x12345678 } }
+                 |ts| {
+        assert_eq!(
+            XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&ts),
+            Some(TIMEOUT_HASH));
+    });
+}
+
+#[test]
+fn test_bitfield_hash() {
+    use std::hash::Hasher;
+    test_struct!([bitfield_hash]
+                 { [] a: u16 = 0x1234,
+                   [] b: u16 = 0x5678 }
+                 |ts| {
+        let mut h = Djb2Hasher::default();
+        h.write(cross_check_runtime::hash::value_bytes(&ts));
+        assert_eq!(
+            XCH::cross_check_hash::<Djb2Hasher, SimpleHasher>(&ts),
+            Some(h.finish()));
+    });
+}
+
+#[test]
+fn test_ignore_padding() {
+    use std::hash::Hasher;
+    use std::{mem, ptr};
+    test_struct!([ignore_padding]
+                 { [] a: u8 = 0x12,
+                   [] b: u32 = 0x3456789a }
+                 |ts: TestStruct| {
+        // Build copies of the value with all their bytes, including
+        // the padding, set to 0x00 and 0xff before setting the fields;
+        // these are filled in place, since moving a value does not
+        // necessarily preserve its padding
+        let mut zeroed = TestStruct { a: 0, b: 0 };
+        let mut filled = TestStruct { a: 0, b: 0 };
+        unsafe {
+            ptr::write_bytes(&mut zeroed as *mut TestStruct as *mut u8, 0x00,
+                             mem::size_of::<TestStruct>());
+            ptr::write_bytes(&mut filled as *mut TestStruct as *mut u8, 0xff,
+                             mem::size_of::<TestStruct>());
+        }
+        zeroed.a = ts.a;
+        zeroed.b = ts.b;
+        filled.a = ts.a;
+        filled.b = ts.b;
+        let mut h = Djb2Hasher::default();
+        h.write(cross_check_runtime::hash::value_bytes(&zeroed));
+        assert_eq!(
+            XCH::cross_check_hash::<Djb2Hasher, SimpleHasher>(&filled),
+            Some(h.finish()));
+        assert_eq!(
+            XCH::cross_check_hash::<Djb2Hasher, SimpleHasher>(&ts),
+            Some(h.finish()));
+    });
+}
+
+#[test]
+fn test_field_salt() {
+    // djb2("x") == 0x2b61d
+    test_struct!([field_salt]
+                 { [] x: u64 = 1 }
+                 |ts| {
+        assert_eq!(
+            XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&ts),
+            Some(0x2b61c_u64));
+    });
+}
+
+#[test]
+fn test_field_salt_custom_hash() {
+    use std::hash::Hasher;
+    fn custom_hash<XCHA, XCHS, S, F>(h: &mut XCHA, _: &S, _: F, _: usize)
+        where XCHA: ::cross_check_runtime::hash::CrossCheckHasher {
+        h.write_u64(0x0f0f0f0f0f0f0f0f)
+    }
+    // The salt applies to the result of the custom function
+    test_struct!([field_salt]
+                 { [custom_hash="custom_hash"] x: u64 = 1 }
+                 |ts| {
+        let mut fh = Djb2Hasher::default();
+        fh.write_u64(0x0f0f0f0f0f0f0f0f);
+        let mut h = Djb2Hasher::default();
+        h.write_u64(fh.finish() ^ 0x2b61d);
+        assert_eq!(
+            XCH::cross_check_hash::<Djb2Hasher, Djb2Hasher>(&ts),
+            Some(h.finish()));
+    });
+}
+
+#[test]
+fn test_parallel_hash() {
+    // SimpleHasher mixes the u64 type into each field hash twice,
+    // which cancels out, so we get the XOR of the values
+    test_struct!([parallel_hash]
+                 { [] x: u64 = 1,
+                   [] y: u64 = 2,
+                   [none] z: u64 = 8,
+                   [] w: u64 = 4 }
+                 |ts| {
+        assert_eq!(
+            XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&ts),
+            Some(7));
+    });
+}
+
+#[test]
+fn test_include_size() {
+    test_struct!([include_size]
+                 { [] x: u64 = 1 }
+                 |ts| {
+        assert_eq!(
+            XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&ts),
+            Some(1 ^ 8));
+    });
+}
+
+#[test]
+fn test_transparent_hash() {
+    use std::marker::PhantomData;
+    // The structure hashes exactly like its inner field, without
+    // mixing in another level of aggregate hashing
+    test_struct!([transparent_hash]
+                 { [] x: u32 = 0x1234,
+                   [none] marker: PhantomData<u8> = PhantomData }
+                 |ts| {
+        assert_eq!(
+            XCH::cross_check_hash::<Djb2Hasher, Djb2Hasher>(&ts),
+            XCH::cross_check_hash::<Djb2Hasher, Djb2Hasher>(&0x1234u32));
+    });
+}
+
+#[test]
+fn test_field_groups() {
+    // The sign extension of `lo` should get masked out
+    test_struct!([field_groups="hi:lo"]
+                 { [] hi: u16 = 0x1234,
+                   [] lo: i16 = -1 }
+                 |ts| {
+        assert_eq!(
+            XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&ts),
+            Some(0x1234ffff_u64));
+    });
+}
+
+#[test]
+fn test_hash_if() {
+    use std::hash::Hasher;
+    // Only the `kind` field gets hashed, since the condition is false
+    test_struct!([]
+                 { [] kind: u32 = 2,
+                   [hash_if="self.kind == 1"] data: u64 = 0x12345678 }
+                 |ts| {
+        let mut h = Djb2Hasher::default();
+        h.write_u64(XCH::cross_check_hash::<Djb2Hasher, SimpleHasher>(&2u32).unwrap());
+        assert_eq!(
+            XCH::cross_check_hash::<Djb2Hasher, SimpleHasher>(&ts),
+            Some(h.finish()));
+    });
+}
+
+#[test]
+fn test_hash_display() {
+    use std::fmt;
+
+    impl fmt::Display for TestStruct {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "abcd")
+        }
+    }
+
+    test_struct!([hash_display]
+                 { [] x: u64 = 1 }
+                 |ts| {
+        assert_eq!(
+            XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&ts),
+            Some(0x7c93ee4f_u64));
+    });
+}
+
+#[test]
+fn test_remote_hash() {
+    use std::hash::Hasher;
+    use std::time::Duration;
+    use cross_check_runtime::hash::CrossCheckHasher;
+
+    // Stand-in for the wrapper that the plugin generates for remote types
+    struct RemoteDuration<'a>(&'a Duration);
+    impl<'a> XCH for RemoteDuration<'a> {
+        fn cross_check_hash_depth<HA, HS>(&self, _depth: usize) -> u64
+                where HA: CrossCheckHasher, HS: CrossCheckHasher {
+            self.0.as_secs()
+        }
+    }
+
+    test_struct!([]
+                 { [remote_hash="RemoteDuration"] d: Duration = Duration::from_secs(7) }
+                 |ts| {
+        let mut h = Djb2Hasher::default();
+        h.write_u64(7);
+        assert_eq!(
+            XCH::cross_check_hash::<Djb2Hasher, SimpleHasher>(&ts),
+            Some(h.finish()));
+    });
+}
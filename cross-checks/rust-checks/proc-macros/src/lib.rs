@@ -0,0 +1,91 @@
+#![feature(proc_macro)]
+
+extern crate proc_macro;
+extern crate syn;
+#[macro_use]
+extern crate quote;
+
+extern crate cross_check_config as xcfg;
+
+use proc_macro::TokenStream;
+
+// Same djb2 hash function that the compiler plugin uses for names
+fn djb2_hash(s: &str) -> u32 {
+    s.bytes().fold(5381u32, |h, c| h.wrapping_mul(33).wrapping_add(c as u32))
+}
+
+// Add the entry and exit cross-checks to a function body, the same way
+// the compiler plugin does it: the original body runs inside a closure,
+// so that the exit cross-check runs even if the body returns early
+fn build_function_xchecks(item: &syn::Item, args: &xcfg::attr::ArgList,
+                          decl: &syn::FnDecl, block: &syn::Block) -> syn::Block {
+    let id = match args.get("id") {
+        Some(&xcfg::attr::ArgValue::Int(id)) => id as u64,
+        Some(arg) => panic!("invalid id: {:?}", arg),
+        None => {
+            let name = args.get("name").map_or(item.ident.as_ref(), |arg| arg.as_str());
+            djb2_hash(name) as u64
+        }
+    };
+    let result_ty = match decl.output {
+        syn::FunctionRetTy::Default => quote! { () },
+        syn::FunctionRetTy::Ty(ref ty) => quote! { #ty },
+    };
+    let stmts = &block.stmts;
+    let wrapper = quote! {
+        fn __c2rust_wrapper() {
+            cross_check_raw!(FUNCTION_ENTRY_TAG, #id);
+            #[allow(unused_mut)]
+            let mut __c2rust_fn_body = || -> #result_ty { #(#stmts)* };
+            let __c2rust_fn_result = __c2rust_fn_body();
+            cross_check_raw!(FUNCTION_EXIT_TAG, #id);
+            __c2rust_fn_result
+        }
+    };
+    match syn::parse_item(wrapper.as_str()).expect("could not parse checked function").node {
+        syn::ItemKind::Fn(_, _, _, _, _, block) => *block,
+        _ => unreachable!()
+    }
+}
+
+fn cross_check_item(args: &xcfg::attr::ArgList, mut item: syn::Item) -> quote::Tokens {
+    for (name, _) in args.iter() {
+        match *name {
+            "yes" | "enabled" | "none" | "disabled" | "name" | "id" => (),
+            _ => panic!("unknown cross_check item: {}", name)
+        }
+    }
+    let disabled = ["none", "disabled"].iter().any(|arg| args.contains_key(arg));
+    if disabled {
+        return quote! { #item };
+    }
+    let checked_block = match item.node {
+        syn::ItemKind::Fn(ref decl, _, _, _, _, ref block) =>
+            Some(build_function_xchecks(&item, args, decl, block)),
+        _ => None
+    };
+    match item.node {
+        syn::ItemKind::Fn(_, _, _, _, _, ref mut block) => {
+            *block = Box::new(checked_block.unwrap());
+            quote! { #item }
+        }
+        syn::ItemKind::Struct(..) |
+        syn::ItemKind::Enum(..) => {
+            quote! {
+                #[derive(CrossCheckHash)]
+                #item
+            }
+        }
+        _ => quote! { #item }
+    }
+}
+
+#[proc_macro_attribute]
+pub fn cross_check(args: TokenStream, input: TokenStream) -> TokenStream {
+    let attr = syn::parse_outer_attr(&format!("#[cross_check({})]", args))
+        .expect("could not parse cross_check arguments");
+    let args = xcfg::attr::get_syn_item_args(&attr.value);
+    let item = syn::parse_item(&input.to_string())
+        .expect("cross_check can only be applied to items");
+    cross_check_item(&args, item).parse().expect("could not parse checked item")
+}
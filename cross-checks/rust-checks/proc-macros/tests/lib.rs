@@ -0,0 +1,94 @@
+#![feature(proc_macro)]
+
+#[macro_use]
+extern crate cross_check_derive;
+#[macro_use]
+extern crate cross_check_runtime;
+extern crate cross_check_proc_macros;
+
+use cross_check_proc_macros::cross_check;
+use cross_check_runtime::hash::CrossCheckHash as XCH;
+use cross_check_runtime::hash::simple::SimpleHasher;
+use cross_check_runtime::xcheck::{FUNCTION_ENTRY_TAG, FUNCTION_EXIT_TAG};
+
+use std::cell::RefCell;
+
+thread_local! {
+    static XCHECKS: RefCell<Vec<(u8, u64)>> = RefCell::new(vec![]);
+}
+
+// Record all cross-checks from the current thread, so each test can
+// check its own cross-checks while the other tests run in parallel
+#[no_mangle]
+pub extern "C" fn rb_xcheck(tag: u8, val: u64) {
+    XCHECKS.with(|xchecks| xchecks.borrow_mut().push((tag, val)));
+}
+
+fn take_xchecks() -> Vec<(u8, u64)> {
+    XCHECKS.with(|xchecks| xchecks.borrow_mut().drain(..).collect())
+}
+
+#[test]
+fn test_function() {
+    #[cross_check]
+    fn foo(x: u32) -> u32 {
+        if x == 0 {
+            return 1;
+        }
+        x + 1
+    }
+
+    // djb2("foo") == 0x0b887389
+    assert_eq!(foo(0), 1);
+    assert_eq!(foo(1), 2);
+    assert_eq!(take_xchecks(), vec![(FUNCTION_ENTRY_TAG, 0x0b887389),
+                                    (FUNCTION_EXIT_TAG, 0x0b887389),
+                                    (FUNCTION_ENTRY_TAG, 0x0b887389),
+                                    (FUNCTION_EXIT_TAG, 0x0b887389)]);
+}
+
+#[test]
+fn test_function_name_id() {
+    #[cross_check(name = "foo")]
+    fn bar() {}
+
+    #[cross_check(id = 0x1234)]
+    fn baz() {}
+
+    bar();
+    baz();
+    assert_eq!(take_xchecks(), vec![(FUNCTION_ENTRY_TAG, 0x0b887389),
+                                    (FUNCTION_EXIT_TAG, 0x0b887389),
+                                    (FUNCTION_ENTRY_TAG, 0x1234),
+                                    (FUNCTION_EXIT_TAG, 0x1234)]);
+}
+
+#[test]
+fn test_function_disabled() {
+    #[cross_check(none)]
+    fn foo() {}
+
+    #[cross_check(disabled)]
+    fn bar() {}
+
+    #[cross_check(enabled)]
+    fn baz() {}
+
+    foo();
+    bar();
+    baz();
+    assert_eq!(take_xchecks(), vec![(FUNCTION_ENTRY_TAG, 0x0b8860c2),
+                                    (FUNCTION_EXIT_TAG, 0x0b8860c2)]);
+}
+
+#[test]
+fn test_struct() {
+    #[cross_check]
+    struct TestStruct {
+        x: u64,
+    }
+
+    let ts = TestStruct { x: 0x12345678 };
+    assert_eq!(XCH::cross_check_hash::<SimpleHasher, SimpleHasher>(&ts),
+               Some(0x12345678));
+}
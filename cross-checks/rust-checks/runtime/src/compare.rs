@@ -0,0 +1,111 @@
+// Backend that compares all cross-checks against a previously
+// recorded reference log (in the format used by `replay`),
+// and reports each divergence according to the current `Mode`
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Mutex;
+
+use replay::LogReader;
+use xcheck::{self, Backend, Mode};
+
+struct CompareState<R: Read> {
+    reference: LogReader<R>,
+    index: usize,
+    tolerances: HashMap<u8, f64>,
+}
+
+pub struct CompareBackend<R: Read + Send> {
+    state: Mutex<CompareState<R>>,
+}
+
+impl<R: Read + Send> CompareBackend<R> {
+    pub fn new(reference: R) -> CompareBackend<R> {
+        CompareBackend {
+            state: Mutex::new(CompareState {
+                reference: LogReader::new(reference),
+                index: 0,
+                tolerances: HashMap::new(),
+            })
+        }
+    }
+
+    // Compare the values for the given tag as floating-point
+    // values (as sent in the `eq` check mode), and accept
+    // values that differ by at most `tolerance`
+    pub fn with_tolerance(self, tag: u8, tolerance: f64) -> CompareBackend<R> {
+        self.state.lock().unwrap().tolerances.insert(tag, tolerance);
+        self
+    }
+}
+
+impl<R: Read> CompareState<R> {
+    fn values_match(&self, tag: u8, expected: u64, val: u64) -> bool {
+        if expected == val {
+            return true;
+        }
+        self.tolerances.get(&tag).map_or(false, |tolerance| {
+            let diff = f64::from_bits(expected) - f64::from_bits(val);
+            diff.abs() <= *tolerance
+        })
+    }
+}
+
+impl<R: Read + Send> Backend for CompareBackend<R> {
+    fn xcheck(&self, tag: u8, val: u64) {
+        let mut state = self.state.lock().unwrap();
+        let index = state.index;
+        state.index += 1;
+        let expected = match state.reference.next() {
+            Some(Ok((exp_tag, exp_val))) => {
+                if exp_tag == tag && state.values_match(tag, exp_val, val) {
+                    return;
+                }
+                format!("expected tag={} hash={}", exp_tag, exp_val)
+            }
+            Some(Err(e)) => format!("error reading reference log: {}", e),
+            None => String::from("reference log ended"),
+        };
+        // Release the lock, so that a panic does not poison it
+        drop(state);
+        let msg = format!("cross-check divergence: tag={} hash={} ({}, event {})",
+                          tag, val, expected, index);
+        match xcheck::mode() {
+            Mode::Assert => panic!("{}", msg),
+            Mode::Log => eprintln!("{}", msg),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use replay::{write_header, write_record};
+    use xcheck::test_backend::ModeGuard;
+
+    fn build_log(events: &[(u8, u64)]) -> Vec<u8> {
+        let mut log = vec![];
+        write_header(&mut log).unwrap();
+        for &(tag, val) in events.iter() {
+            write_record(&mut log, tag, val).unwrap();
+        }
+        log
+    }
+
+    #[test]
+    fn test_compare() {
+        let log = build_log(&[(1, 0x7c93ee4f), (4, 1.0_f64.to_bits())]);
+        let backend = CompareBackend::new(&log[..]).with_tolerance(4, 1e-6);
+        backend.xcheck(1, 0x7c93ee4f);
+        backend.xcheck(4, (1.0_f64 + 1e-9).to_bits());
+    }
+
+    #[test]
+    #[should_panic(expected = "cross-check divergence: tag=1 hash=1234")]
+    fn test_assert_divergence() {
+        let _mode = ModeGuard::new(Mode::Assert);
+        let log = build_log(&[(1, 0x7c93ee4f)]);
+        let backend = CompareBackend::new(&log[..]);
+        backend.xcheck(1, 1234);
+    }
+}
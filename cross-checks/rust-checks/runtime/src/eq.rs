@@ -0,0 +1,93 @@
+// Support for cross-checking values by equality instead of hashing:
+// instead of a hash, the cross-check sends a canonical 64-bit encoding
+// of the value itself, so that the Rust and C values get compared directly.
+// This is mostly useful for floating-point values, where values that
+// compare equal (e.g., 0.0 and -0.0) have different bit patterns.
+
+use std::mem;
+
+pub trait CrossCheckEq {
+    fn cross_check_eq_value(&self) -> Option<u64>;
+}
+
+impl CrossCheckEq for () {
+    #[inline]
+    fn cross_check_eq_value(&self) -> Option<u64> {
+        None
+    }
+}
+
+macro_rules! impl_primitive_eq {
+    ($($in_ty:ident)*) => { $(
+        impl CrossCheckEq for $in_ty {
+            #[inline]
+            fn cross_check_eq_value(&self) -> Option<u64> {
+                Some(*self as u64)
+            }
+        }
+    )* };
+}
+
+impl_primitive_eq!(u8 u16 u32 u64 usize i8 i16 i32 i64 isize bool char);
+
+// Canonical NaN value, used for all NaNs regardless of sign or payload
+const CANONICAL_NAN: u64 = 0x7ff8000000000000_u64;
+
+impl CrossCheckEq for f64 {
+    #[inline]
+    fn cross_check_eq_value(&self) -> Option<u64> {
+        if self.is_nan() {
+            Some(CANONICAL_NAN)
+        } else if *self == 0.0 {
+            // Positive and negative zero compare equal
+            Some(0)
+        } else {
+            Some(unsafe { mem::transmute::<f64, u64>(*self) })
+        }
+    }
+}
+
+impl CrossCheckEq for f32 {
+    #[inline]
+    fn cross_check_eq_value(&self) -> Option<u64> {
+        // Promote to f64, so that floats and doubles
+        // holding the same value produce the same result
+        (*self as f64).cross_check_eq_value()
+    }
+}
+
+impl<'a, T: ?Sized + CrossCheckEq> CrossCheckEq for &'a T {
+    #[inline]
+    fn cross_check_eq_value(&self) -> Option<u64> {
+        (**self).cross_check_eq_value()
+    }
+}
+
+impl<'a, T: ?Sized + CrossCheckEq> CrossCheckEq for &'a mut T {
+    #[inline]
+    fn cross_check_eq_value(&self) -> Option<u64> {
+        (**self).cross_check_eq_value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64;
+
+    #[test]
+    fn test_floats() {
+        assert_eq!(0.0_f64.cross_check_eq_value(), (-0.0_f64).cross_check_eq_value());
+        assert_eq!(f64::NAN.cross_check_eq_value(), (-f64::NAN).cross_check_eq_value());
+        assert_eq!(1.5_f32.cross_check_eq_value(), 1.5_f64.cross_check_eq_value());
+        assert!(1.0_f64.cross_check_eq_value() != 2.0_f64.cross_check_eq_value());
+    }
+
+    #[test]
+    fn test_integers() {
+        assert_eq!(0x1234_u16.cross_check_eq_value(), Some(0x1234));
+        assert_eq!(true.cross_check_eq_value(), Some(1));
+        assert_eq!((&7_i32).cross_check_eq_value(), Some(7));
+        assert_eq!(().cross_check_eq_value(), None);
+    }
+}
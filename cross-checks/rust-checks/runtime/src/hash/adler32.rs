@@ -0,0 +1,102 @@
+// Adler-32 checksum, used by the `adler32` cross-check type for
+// large fields. It only needs two running sums, so it is cheap to
+// compute, especially on embedded targets without CRC hardware, but
+// it has weak collision resistance for short inputs. The 32-bit
+// checksum is zero-extended to 64 bits.
+
+use std::hash::Hasher;
+use super::{CrossCheckHasher, value_bytes};
+
+const ADLER32_MOD: u32 = 65521;
+
+// Largest number of bytes we can add up before the sums can overflow
+const ADLER32_NMAX: usize = 5552;
+
+#[derive(Debug)]
+pub struct Adler32Hasher {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32Hasher {
+    #[inline]
+    pub fn new() -> Adler32Hasher {
+        Adler32Hasher { a: 1, b: 0 }
+    }
+
+    #[inline]
+    pub fn get_hash(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+impl Default for Adler32Hasher {
+    #[inline]
+    fn default() -> Adler32Hasher {
+        Adler32Hasher::new()
+    }
+}
+
+impl Hasher for Adler32Hasher {
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.get_hash() as u64
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // Defer the modulo until the sums could overflow
+        for chunk in bytes.chunks(ADLER32_NMAX) {
+            for b in chunk {
+                self.a += *b as u32;
+                self.b += self.a;
+            }
+            self.a %= ADLER32_MOD;
+            self.b %= ADLER32_MOD;
+        }
+    }
+}
+
+impl CrossCheckHasher for Adler32Hasher {}
+
+#[inline]
+pub fn adler32(bytes: &[u8]) -> u32 {
+    let mut h = Adler32Hasher::new();
+    h.write(bytes);
+    h.get_hash()
+}
+
+// Compute the Adler-32 of the bytes of a value; like `crc32_value`,
+// this should only be used for values without padding or pointers
+#[inline]
+pub fn adler32_value<T: ?Sized>(val: &T) -> u32 {
+    adler32(value_bytes(val))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        assert_eq!(adler32_value(b"Wikipedia"), 0x11e60398);
+    }
+
+    #[test]
+    fn test_long_input() {
+        // Check that the deferred modulo gives the same
+        // result as reducing the sums after every byte
+        let bytes = vec![0xff_u8; 3 * ADLER32_NMAX + 17];
+        let (a, b) = bytes.iter().fold((1u32, 0u32), |(a, b), c| {
+            let a = (a + *c as u32) % ADLER32_MOD;
+            (a, (b + a) % ADLER32_MOD)
+        });
+        assert_eq!(adler32(&bytes), (b << 16) | a);
+
+        let mut h = Adler32Hasher::new();
+        h.write(&bytes[..100]);
+        h.write(&bytes[100..]);
+        assert_eq!(h.finish(), ((b << 16) | a) as u64);
+    }
+}
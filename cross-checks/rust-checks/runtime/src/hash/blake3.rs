@@ -0,0 +1,31 @@
+// BLAKE3 hash of the in-memory representation of a value, used by
+// the `blake3` cross-check type for data where hash collisions
+// must be avoided. The 256-bit hash gets truncated to 64 bits.
+
+use blake3;
+
+use super::value_bytes;
+
+pub fn blake3_hash(bytes: &[u8]) -> u64 {
+    let hash = blake3::hash(bytes);
+    hash.as_bytes()[..8].iter().rev().fold(0u64, |acc, b| (acc << 8) | (*b as u64))
+}
+
+// Same caveats as for `crc32_value`: this should only be used
+// for values without padding or pointers
+#[inline]
+pub fn blake3_value<T: ?Sized>(val: &T) -> u64 {
+    blake3_hash(value_bytes(val))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blake3() {
+        // First 8 bytes of BLAKE3(""), in little-endian order
+        assert_eq!(blake3_hash(b""), 0xa6a1f9f5b94913af);
+        assert_eq!(blake3_value(b"abc"), blake3_hash(b"abc"));
+    }
+}
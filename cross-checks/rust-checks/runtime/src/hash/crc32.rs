@@ -0,0 +1,65 @@
+// CRC-32 (IEEE) hash of the in-memory representation of a value,
+// used by the `crc32` cross-check type for large array fields.
+// With the `crc32fast` feature, we use the `crc32fast` crate, which
+// uses hardware acceleration when available; otherwise, we fall
+// back to a table-driven software implementation.
+
+use super::value_bytes;
+
+#[cfg(feature="crc32fast")]
+use crc32fast;
+
+#[cfg(not(feature="crc32fast"))]
+const CRC32_POLY: u32 = 0xedb88320;
+
+#[cfg(not(feature="crc32fast"))]
+fn crc32_table() -> &'static [u32; 256] {
+    use std::sync::{Once, ONCE_INIT};
+    static mut CRC32_TABLE: [u32; 256] = [0; 256];
+    static CRC32_TABLE_INIT: Once = ONCE_INIT;
+    unsafe {
+        CRC32_TABLE_INIT.call_once(|| {
+            for (i, entry) in CRC32_TABLE.iter_mut().enumerate() {
+                *entry = (0..8).fold(i as u32, |crc, _| {
+                    if crc & 1 != 0 { (crc >> 1) ^ CRC32_POLY } else { crc >> 1 }
+                });
+            }
+        });
+        &CRC32_TABLE
+    }
+}
+
+#[cfg(not(feature="crc32fast"))]
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let table = crc32_table();
+    !bytes.iter().fold(!0u32, |crc, b| {
+        table[((crc ^ (*b as u32)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(feature="crc32fast")]
+#[inline]
+pub fn crc32(bytes: &[u8]) -> u32 {
+    crc32fast::hash(bytes)
+}
+
+// Compute the CRC-32 of the bytes of a value. This should only be used
+// for values without padding or pointers, e.g., arrays of integers,
+// since their contents are not deterministic.
+#[inline]
+pub fn crc32_value<T: ?Sized>(val: &T) -> u32 {
+    crc32(value_bytes(val))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32_value(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32_value(&[0x34333231_u32, 0x38373635][..]), crc32(b"12345678"));
+    }
+}
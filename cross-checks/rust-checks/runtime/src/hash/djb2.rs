@@ -67,9 +67,17 @@ impl Hasher for Djb2Hasher {
 
 impl CrossCheckHasher for Djb2Hasher {}
 
+// Hash a string using djb2, e.g., the `Display` output of a value
+#[inline]
+pub fn djb2_str(s: &str) -> u64 {
+    let mut h = Djb2Hasher::new();
+    h.write(s.as_bytes());
+    h.finish()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Hasher, Djb2Hasher};
+    use super::{Hasher, Djb2Hasher, djb2_str};
 
     fn djb2_string(s: &str) -> u32 {
         let mut h = Djb2Hasher::new();
@@ -88,5 +96,6 @@ mod tests {
         assert_eq!(djb2_string("abcdefg"),  0x1a623b21u32);
         assert_eq!(djb2_string("abcdefgh"), 0x66a99fa9u32);
         assert_eq!(djb2_string("djb2"),     0x7c95b527u32);
+        assert_eq!(djb2_str("abcd"),        0x7c93ee4fu64);
     }
 }
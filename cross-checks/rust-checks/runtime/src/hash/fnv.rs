@@ -0,0 +1,97 @@
+// 32-bit FNV-1a hasher, which only uses 32-bit arithmetic
+// and is therefore fast on 32-bit targets
+
+use std::hash::Hasher;
+use super::CrossCheckHasher;
+
+const FNV32_OFFSET_BASIS: u32 = 0x811c9dc5u32;
+const FNV32_PRIME: u32 = 0x01000193u32;
+
+#[derive(Debug)]
+pub struct Fnv32Hasher(u32);
+
+impl Fnv32Hasher {
+    #[inline]
+    pub fn new() -> Fnv32Hasher {
+        Fnv32Hasher(FNV32_OFFSET_BASIS)
+    }
+
+    #[inline]
+    pub fn get_hash(&self) -> u32 {
+        self.0
+    }
+}
+
+impl Default for Fnv32Hasher {
+    #[inline]
+    fn default() -> Fnv32Hasher {
+        Fnv32Hasher::new()
+    }
+}
+
+impl Hasher for Fnv32Hasher {
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.0 as u64
+    }
+
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        self.0 = bytes.iter().fold(self.0,
+            |h, c| (h ^ (*c as u32)).wrapping_mul(FNV32_PRIME));
+    }
+
+    // Integers are hashed a whole 32-bit word at a time,
+    // instead of byte by byte
+    #[inline]
+    fn write_u8(&mut self, i: u8) {
+        self.write_u32(i as u32);
+    }
+
+    #[inline]
+    fn write_u16(&mut self, i: u16) {
+        self.write_u32(i as u32);
+    }
+
+    #[inline]
+    fn write_u32(&mut self, i: u32) {
+        self.0 = (self.0 ^ i).wrapping_mul(FNV32_PRIME);
+    }
+
+    #[inline]
+    fn write_u64(&mut self, i: u64) {
+        self.write_u32(i as u32);
+        self.write_u32((i >> 32) as u32);
+    }
+}
+
+impl CrossCheckHasher for Fnv32Hasher {}
+
+#[cfg(test)]
+mod tests {
+    use super::{Hasher, Fnv32Hasher};
+
+    fn fnv32_string(s: &str) -> u32 {
+        let mut h = Fnv32Hasher::new();
+        h.write(s.as_bytes());
+        h.get_hash()
+    }
+
+    #[test]
+    fn test_fnv32() {
+        assert_eq!(fnv32_string(""),       0x811c9dc5u32);
+        assert_eq!(fnv32_string("a"),      0xe40c292cu32);
+        assert_eq!(fnv32_string("foobar"), 0xbf9cf968u32);
+    }
+
+    #[test]
+    fn test_words() {
+        let mut h1 = Fnv32Hasher::new();
+        h1.write_u64(0x0123456789abcdef);
+        let mut h2 = Fnv32Hasher::new();
+        h2.write_u32(0x89abcdef);
+        h2.write_u32(0x01234567);
+        assert_eq!(h1.finish(), h2.finish());
+        assert!(h1.finish() <= 0xffffffff);
+    }
+}
@@ -42,11 +42,17 @@ impl Hasher for JodyHasher {
     }
 }
 
-impl CrossCheckHasher for JodyHasher {}
+impl CrossCheckHasher for JodyHasher {
+    #[inline]
+    fn reset(&mut self) {
+        self.0 = 0;
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::{Hasher, JodyHasher};
+    use super::super::CrossCheckHasher;
 
     fn jodyhash_string(s: &str) -> u64 {
         assert!(s.len() % 8 == 0);
@@ -71,4 +77,14 @@ mod tests {
         assert_eq!(jodyhash_string("abcdefghijklmnop"), 0xcef9115bbc6c0f84u64);
         assert_eq!(jodyhash_string("zyxwvutsrqponmlk"), 0xe408a517f5c12d07u64);
     }
+
+    #[test]
+    fn test_combine() {
+        let mut h1 = JodyHasher::default();
+        h1.write_u64(1);
+        h1.write_u64(2);
+        let mut h2 = JodyHasher::default();
+        h2.combine(1).combine(2);
+        assert_eq!(h1.finish(), h2.finish());
+    }
 }
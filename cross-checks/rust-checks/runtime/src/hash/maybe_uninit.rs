@@ -0,0 +1,55 @@
+// Hashing for `std::mem::MaybeUninit`, which needs a newer
+// compiler than the rest of the runtime, hence the feature
+//
+// A `MaybeUninit` does not know whether it holds an initialized value,
+// and reading it if it does not is undefined behavior, so its hash is
+// always UNINIT_HASH, even if the value is initialized; code that tracks
+// the initialization state elsewhere, e.g., in a companion `bool` field,
+// needs to hash the value using `hash_maybe_uninit` instead
+
+use std::mem::MaybeUninit;
+
+use super::{CrossCheckHash, CrossCheckHasher, UNINIT_HASH};
+
+impl<T: CrossCheckHash> CrossCheckHash for MaybeUninit<T> {
+    #[inline]
+    fn cross_check_hash_depth<HA, HS>(&self, _depth: usize) -> u64
+            where HA: CrossCheckHasher, HS: CrossCheckHasher {
+        UNINIT_HASH
+    }
+}
+
+// Hash the value in a `MaybeUninit` if `initialized` is true,
+// and return UNINIT_HASH otherwise. This is unsafe because
+// the caller needs to guarantee that the value is initialized
+// whenever `initialized` is true.
+#[inline]
+pub unsafe fn hash_maybe_uninit<T, HA, HS>(val: &MaybeUninit<T>, initialized: bool,
+                                           depth: usize) -> u64
+        where T: CrossCheckHash, HA: CrossCheckHasher, HS: CrossCheckHasher {
+    if initialized {
+        (*val.as_ptr()).cross_check_hash_depth::<HA, HS>(depth)
+    } else {
+        UNINIT_HASH
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hash::simple::SimpleHasher;
+
+    #[test]
+    fn test_maybe_uninit() {
+        type H = SimpleHasher;
+        let uninit = MaybeUninit::<u32>::uninit();
+        let init = MaybeUninit::new(0x1234u32);
+        assert_eq!(uninit.cross_check_hash::<H, H>(), Some(UNINIT_HASH));
+        assert_eq!(init.cross_check_hash::<H, H>(), Some(UNINIT_HASH));
+        unsafe {
+            assert_eq!(hash_maybe_uninit::<_, H, H>(&uninit, false, 1), UNINIT_HASH);
+            assert_eq!(hash_maybe_uninit::<_, H, H>(&init, true, 1),
+                       0x1234u32.cross_check_hash_depth::<H, H>(1));
+        }
+    }
+}
@@ -1,6 +1,13 @@
 
+use std::cell::Cell;
+use std::collections::LinkedList;
 use std::hash::Hasher;
+use std::io;
 use std::mem;
+use std::slice;
+use std::sync::{Mutex, RwLock, TryLockError};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 #[cfg(feature="libc-hash")]
 use libc;
@@ -8,11 +15,37 @@ use libc;
 pub mod djb2;
 pub mod simple;
 pub mod jodyhash;
+pub mod fnv;
+pub mod crc32;
+pub mod adler32;
+pub mod siphash;
+pub mod path;
+#[cfg(feature="blake3-hash")]
+pub mod blake3;
+#[cfg(feature="rayon")]
+pub mod parallel;
+#[cfg(feature="maybe-uninit-hash")]
+pub mod maybe_uninit;
 
 const MAX_DEPTH: usize = 8;
 
+// Get the in-memory representation of a value as a byte slice,
+// for the hashes that operate directly on the bytes of the value
+#[inline]
+pub fn value_bytes<T: ?Sized>(val: &T) -> &[u8] {
+    unsafe {
+        slice::from_raw_parts(val as *const T as *const u8, mem::size_of_val(val))
+    }
+}
+
 // Trait alias for Hasher + Default
 pub trait CrossCheckHasher: Hasher + Default {
+    // Reset the hasher to its initial state, so that it can be
+    // reused for another value instead of creating a new one
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+
     fn write_bool(&mut self, i: bool) {
         self.write_u8(i as u8);
     }
@@ -28,6 +61,13 @@ pub trait CrossCheckHasher: Hasher + Default {
     fn write_f64(&mut self, i: f64) {
         self.write_u64(unsafe { mem::transmute(i) });
     }
+
+    // Mix a previously computed hash, e.g., a cached hash of a field,
+    // into the current state, without hashing the value again
+    fn combine(&mut self, other_hash: u64) -> &mut Self {
+        self.write_u64(other_hash);
+        self
+    }
 }
 
 // Trait for our cross-check hash function
@@ -45,6 +85,23 @@ pub trait CrossCheckHash {
             where HA: CrossCheckHasher, HS: CrossCheckHasher;
 }
 
+// Variant of CrossCheckHash that produces 32-bit hashes, which is useful
+// on 32-bit targets where emitting 64-bit values is expensive. Combine it
+// with 32-bit hashers, e.g., `fnv::Fnv32Hasher`, to avoid 64-bit arithmetic
+// altogether; for other hashers, the 64-bit hash gets truncated.
+pub trait CrossCheckHash32 {
+    fn cross_check_hash32<HA, HS>(&self) -> Option<u32>
+            where HA: CrossCheckHasher, HS: CrossCheckHasher;
+}
+
+impl<T: ?Sized + CrossCheckHash> CrossCheckHash32 for T {
+    #[inline]
+    fn cross_check_hash32<HA, HS>(&self) -> Option<u32>
+            where HA: CrossCheckHasher, HS: CrossCheckHasher {
+        self.cross_check_hash::<HA, HS>().map(|hash| hash as u32)
+    }
+}
+
 impl CrossCheckHash for ! {
     #[inline]
     fn cross_check_hash<HA, HS>(&self) -> Option<u64>
@@ -118,8 +175,126 @@ impl_primitive_hash!(char,  write_char);
 impl_primitive_hash!(f32,   write_f32);
 impl_primitive_hash!(f64,   write_f64);
 
+// Integer fields that can be packed together into a single `u64` value
+// for the `field_groups` option of the derive macro, along with their
+// width in bits; signed values contribute their bits without the sign
+// extension, so that -1_i16 becomes 0xffff
+pub trait FieldGroupBits {
+    const BITS: u32;
+
+    fn group_bits(&self) -> u64;
+}
+
+macro_rules! impl_field_group_bits {
+    ($($in_ty:ident => $bits:expr),*) => { $(
+        impl FieldGroupBits for $in_ty {
+            const BITS: u32 = $bits;
+
+            #[inline]
+            fn group_bits(&self) -> u64 {
+                (*self as u64) & (!0u64 >> (64 - $bits))
+            }
+        }
+    )* };
+}
+
+impl_field_group_bits!(u8 => 8, u16 => 16, u32 => 32, u64 => 64,
+                       i8 => 8, i16 => 16, i32 => 32, i64 => 64,
+                       bool => 8, char => 32);
+
+#[cfg(target_pointer_width = "32")]
+impl_field_group_bits!(usize => 32, isize => 32);
+
+#[cfg(target_pointer_width = "64")]
+impl_field_group_bits!(usize => 64, isize => 64);
+
 // TODO: hash for strings (str type)
 
+// Time values are usually represented in C as a `struct timeval`,
+// `struct timespec` or a plain `uint64_t`, so we cannot match the C
+// hash exactly; instead, we hash the sum of the seconds and nanoseconds.
+// This means that these are only approximate equality checks.
+impl CrossCheckHash for Duration {
+    #[inline]
+    fn cross_check_hash_depth<HA, HS>(&self, depth: usize) -> u64
+            where HA: CrossCheckHasher, HS: CrossCheckHasher {
+        let val = self.as_secs().wrapping_add(self.subsec_nanos() as u64);
+        val.cross_check_hash_depth::<HA, HS>(depth)
+    }
+}
+
+impl CrossCheckHash for SystemTime {
+    #[inline]
+    fn cross_check_hash_depth<HA, HS>(&self, depth: usize) -> u64
+            where HA: CrossCheckHasher, HS: CrossCheckHasher {
+        // Hash the offset from the UNIX epoch, which is negative
+        // for times before the epoch
+        let val = match self.duration_since(UNIX_EPOCH) {
+            Ok(d)  => d.as_secs().wrapping_add(d.subsec_nanos() as u64),
+            Err(e) => {
+                let d = e.duration();
+                d.as_secs().wrapping_add(d.subsec_nanos() as u64).wrapping_neg()
+            }
+        };
+        val.cross_check_hash_depth::<HA, HS>(depth)
+    }
+}
+
+// IP addresses are hashed as integers built from their octets in network
+// order, i.e., the first octet is the most significant byte, so 127.0.0.1
+// is hashed as 0x7f000001_u32. C code usually stores addresses in network
+// order, e.g., in `struct in_addr`, so the C side needs to convert them
+// to host order (using `ntohl` for IPv4) before hashing. IPv6 addresses
+// are hashed as a pair of `u64` values: the high half, then the low half.
+impl CrossCheckHash for Ipv4Addr {
+    #[inline]
+    fn cross_check_hash_depth<HA, HS>(&self, depth: usize) -> u64
+            where HA: CrossCheckHasher, HS: CrossCheckHasher {
+        u32::from(*self).cross_check_hash_depth::<HA, HS>(depth)
+    }
+}
+
+impl CrossCheckHash for Ipv6Addr {
+    #[inline]
+    fn cross_check_hash_depth<HA, HS>(&self, depth: usize) -> u64
+            where HA: CrossCheckHasher, HS: CrossCheckHasher {
+        let octets = self.octets();
+        let half = |octets: &[u8]| octets.iter().fold(0u64, |val, &octet| (val << 8) | octet as u64);
+        let mut h = HA::default();
+        h.write_u64(half(&octets[..8]).cross_check_hash_depth::<HA, HS>(depth));
+        h.write_u64(half(&octets[8..]).cross_check_hash_depth::<HA, HS>(depth));
+        h.finish()
+    }
+}
+
+impl CrossCheckHash for IpAddr {
+    #[inline]
+    fn cross_check_hash_depth<HA, HS>(&self, depth: usize) -> u64
+            where HA: CrossCheckHasher, HS: CrossCheckHasher {
+        match *self {
+            IpAddr::V4(ref addr) => addr.cross_check_hash_depth::<HA, HS>(depth),
+            IpAddr::V6(ref addr) => addr.cross_check_hash_depth::<HA, HS>(depth),
+        }
+    }
+}
+
+// I/O errors from the OS hash as their `errno` value, which the C side
+// can compare directly; all other errors hash as -1, e.g., the errors
+// created by Rust code, since C reports those through `errno` as well
+impl CrossCheckHash for io::Error {
+    #[inline]
+    fn cross_check_hash_depth<HA, HS>(&self, _depth: usize) -> u64
+            where HA: CrossCheckHasher, HS: CrossCheckHasher {
+        self.raw_os_error().unwrap_or(-1) as u64
+    }
+}
+
+// Version of the hash functions in this runtime, which gets recorded
+// in the header of cross-check logs; it needs to be incremented every
+// time the hash of any value changes, since the logs recorded
+// using different versions cannot be compared against each other
+pub const HASH_VERSION: u32 = 1;
+
 // Placeholder values for reference/pointers to use when
 // we reach depth == 0 and cannot descend any further
 const LEAF_REFERENCE_VALUE: u32 = 0xDEADBEEFu32;
@@ -132,6 +307,57 @@ pub const LEAF_POINTER_HASH: u64 = 0x726174536661654c_u64; // "LeafStar" in ASCI
 pub const VOID_POINTER_HASH: u64 = 0x7261745364696f56_u64; // "VoidStar" in ASCII
 pub const FUNC_POINTER_HASH: u64 = 0x72617453636e7546_u64; // "FuncStar" in ASCII
 pub const ANY_UNION_HASH:    u64 = 0x6e6f696e55796e41_u64; // "AnyUnion" in ASCII
+pub const TIMEOUT_HASH:      u64 = 0x74756f656d69546c_u64; // "lTimeout" in ASCII
+pub const CONTENDED_HASH:    u64 = 0x646e65746e6f436c_u64; // "lContend" in ASCII
+pub const UNINIT_HASH:       u64 = 0x6c5674696e696e55_u64; // "UninitVl" in ASCII
+
+thread_local! {
+    // Deadline for the outermost hash computation that has a timeout,
+    // and whether any of the nested computations have exceeded it
+    static HASH_DEADLINE: Cell<Option<Instant>> = Cell::new(None);
+    static HASH_TIMED_OUT: Cell<bool> = Cell::new(false);
+}
+
+// Resets the deadline when the outermost computation finishes,
+// even if one of the nested ones panics
+struct HashDeadlineGuard;
+
+impl Drop for HashDeadlineGuard {
+    fn drop(&mut self) {
+        HASH_DEADLINE.with(|deadline| deadline.set(None));
+        HASH_TIMED_OUT.with(|timed_out| timed_out.set(false));
+    }
+}
+
+// Run a hash computation with a time limit, which prevents hangs when
+// hashing cyclic data structures without a depth limit. The outermost call
+// sets the deadline, and each nested call checks it and returns early
+// once it has passed. If the deadline is exceeded anywhere, the outermost
+// call returns TIMEOUT_HASH and prints a warning.
+pub fn with_hash_deadline<F>(timeout: Duration, f: F) -> u64
+        where F: FnOnce() -> u64 {
+    match HASH_DEADLINE.with(|deadline| deadline.get()) {
+        Some(deadline) => {
+            if Instant::now() > deadline {
+                HASH_TIMED_OUT.with(|timed_out| timed_out.set(true));
+                TIMEOUT_HASH
+            } else {
+                f()
+            }
+        }
+        None => {
+            HASH_DEADLINE.with(|deadline| deadline.set(Some(Instant::now() + timeout)));
+            let _guard = HashDeadlineGuard;
+            let hash = f();
+            if HASH_TIMED_OUT.with(|timed_out| timed_out.get()) {
+                eprintln!("cross-check hash computation timed out after {:?}", timeout);
+                TIMEOUT_HASH
+            } else {
+                hash
+            }
+        }
+    }
+}
 
 // Hash implementation for slices
 impl<'a, T: CrossCheckHash> CrossCheckHash for [T] {
@@ -151,6 +377,25 @@ impl<'a, T: CrossCheckHash> CrossCheckHash for [T] {
     }
 }
 
+// Hash implementation for linked lists, which get hashed like slices,
+// from head to tail, to match the traversal order of the C lists
+impl<T: CrossCheckHash> CrossCheckHash for LinkedList<T> {
+    #[inline]
+    fn cross_check_hash_depth<HA, HS>(&self, depth: usize) -> u64
+            where HA: CrossCheckHasher, HS: CrossCheckHasher {
+        if depth == 0 {
+            LEAF_ARRAY_HASH
+        } else {
+            let mut h = HA::default();
+            for elem in self {
+                let elem_hash = elem.cross_check_hash_depth::<HA, HS>(depth - 1);
+                h.write_u64(elem_hash);
+            }
+            h.finish()
+        }
+    }
+}
+
 // Hash implementation for references
 impl<'a, T: ?Sized + CrossCheckHash> CrossCheckHash for &'a T {
     #[inline]
@@ -178,6 +423,54 @@ impl<'a, T: ?Sized + CrossCheckHash> CrossCheckHash for &'a mut T {
     }
 }
 
+// Hash implementations for locks: we hash the protected value if we can
+// acquire the lock without blocking, and return CONTENDED_HASH otherwise.
+// This makes the hash non-deterministic if another thread (or the current
+// one, since these locks are not reentrant) holds the lock while we hash.
+// Poisoned locks still get hashed, since the value is still accessible.
+impl<T: ?Sized + CrossCheckHash> CrossCheckHash for Mutex<T> {
+    #[inline]
+    fn cross_check_hash_depth<HA, HS>(&self, depth: usize) -> u64
+            where HA: CrossCheckHasher, HS: CrossCheckHasher {
+        match self.try_lock() {
+            Ok(guard) => (*guard).cross_check_hash_depth::<HA, HS>(depth),
+            Err(TryLockError::Poisoned(e)) =>
+                (*e.into_inner()).cross_check_hash_depth::<HA, HS>(depth),
+            Err(TryLockError::WouldBlock) => CONTENDED_HASH,
+        }
+    }
+}
+
+impl<T: ?Sized + CrossCheckHash> CrossCheckHash for RwLock<T> {
+    #[inline]
+    fn cross_check_hash_depth<HA, HS>(&self, depth: usize) -> u64
+            where HA: CrossCheckHasher, HS: CrossCheckHasher {
+        match self.try_read() {
+            Ok(guard) => (*guard).cross_check_hash_depth::<HA, HS>(depth),
+            Err(TryLockError::Poisoned(e)) =>
+                (*e.into_inner()).cross_check_hash_depth::<HA, HS>(depth),
+            Err(TryLockError::WouldBlock) => CONTENDED_HASH,
+        }
+    }
+}
+
+// Hash implementation for optional values, which translated C code uses
+// for nullable pointers, e.g., `Option<&T>` and `Option<fn(...)>`;
+// `None` hashes the same as a NULL pointer, and `Some(x)` hashes as `x`.
+// We don't decrease the depth here, since the inner value does it
+// if it's a reference, and function values can decay to function
+// pointers due to C's decay rules, so they're basically equivalent
+impl<T: CrossCheckHash> CrossCheckHash for Option<T> {
+    #[inline]
+    fn cross_check_hash_depth<HA, HS>(&self, depth: usize) -> u64
+            where HA: CrossCheckHasher, HS: CrossCheckHasher {
+        match *self {
+            Some(ref x) => x.cross_check_hash_depth::<HA, HS>(depth),
+            None => NULL_POINTER_HASH,
+        }
+    }
+}
+
 // Hash implementation for raw pointers
 impl<T: ?Sized + CrossCheckHash> CrossCheckHash for *const T {
     #[inline]
@@ -220,21 +513,6 @@ macro_rules! impl_fnopt_hash {
                 }
             }
         }
-
-        impl <Ret, $($arg),*> CrossCheckHash for Option<$($pfx)* fn($($arg),*) -> Ret> {
-            #[inline]
-            fn cross_check_hash_depth<HA, HS>(&self, depth: usize) -> u64
-                    where HA: CrossCheckHasher, HS: CrossCheckHasher {
-                if let &Some(ref func) = self {
-                    // Due to C's decay rules, we don't decrease the depth here,
-                    // since function values can decay to function pointers,
-                    // so they're basically equivalent
-                    (*func).cross_check_hash_depth::<HA, HS>(depth)
-                } else {
-                    NULL_POINTER_HASH
-                }
-            }
-        }
     };
     ($($arg:ident),*) => {
         impl_fnopt_hash!(<$($arg),*> + unsafe extern "C");
@@ -317,3 +595,131 @@ impl CrossCheckHash for libc::c_void {
         VOID_POINTER_HASH
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::simple::SimpleHasher;
+    use std::thread;
+
+    #[test]
+    fn test_time() {
+        let d = Duration::new(5, 7);
+        assert_eq!(d.cross_check_hash::<SimpleHasher, SimpleHasher>(),
+                   12u64.cross_check_hash::<SimpleHasher, SimpleHasher>());
+        assert_eq!((UNIX_EPOCH + d).cross_check_hash::<SimpleHasher, SimpleHasher>(),
+                   d.cross_check_hash::<SimpleHasher, SimpleHasher>());
+        assert_eq!((UNIX_EPOCH - d).cross_check_hash::<SimpleHasher, SimpleHasher>(),
+                   12u64.wrapping_neg().cross_check_hash::<SimpleHasher, SimpleHasher>());
+    }
+
+    #[test]
+    fn test_ip_addr() {
+        use super::djb2::Djb2Hasher;
+
+        let v4 = Ipv4Addr::new(127, 0, 0, 1);
+        assert_eq!(v4.cross_check_hash::<SimpleHasher, SimpleHasher>(),
+                   0x7f000001_u32.cross_check_hash::<SimpleHasher, SimpleHasher>());
+        assert_eq!(IpAddr::V4(v4).cross_check_hash::<SimpleHasher, SimpleHasher>(),
+                   v4.cross_check_hash::<SimpleHasher, SimpleHasher>());
+
+        let v6 = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        let halves = [0x20010db800000000_u64, 1];
+        assert_eq!(v6.cross_check_hash::<Djb2Hasher, SimpleHasher>(),
+                   halves[..].cross_check_hash::<Djb2Hasher, SimpleHasher>());
+    }
+
+    #[test]
+    fn test_io_error() {
+        let err = io::Error::from_raw_os_error(2);
+        assert_eq!(err.cross_check_hash::<SimpleHasher, SimpleHasher>(), Some(2));
+        let err = io::Error::new(io::ErrorKind::Other, "custom error");
+        assert_eq!(err.cross_check_hash::<SimpleHasher, SimpleHasher>(), Some(!0));
+    }
+
+    #[test]
+    fn test_hash32() {
+        let x = 0x12345678_u32;
+        assert_eq!(x.cross_check_hash32::<SimpleHasher, SimpleHasher>(),
+                   x.cross_check_hash::<SimpleHasher, SimpleHasher>().map(|h| h as u32));
+        assert_eq!(().cross_check_hash32::<SimpleHasher, SimpleHasher>(), None);
+    }
+
+    #[test]
+    fn test_hash_deadline() {
+        let timeout = Duration::from_millis(1);
+        assert_eq!(with_hash_deadline(timeout, || 1234), 1234);
+        let hash = with_hash_deadline(timeout, || {
+            thread::sleep(Duration::from_millis(5));
+            with_hash_deadline(timeout, || 1234)
+        });
+        assert_eq!(hash, TIMEOUT_HASH);
+        // The deadline gets reset after the outermost computation finishes
+        assert_eq!(with_hash_deadline(timeout, || 1234), 1234);
+    }
+
+    #[test]
+    fn test_locks() {
+        let m = Mutex::new(0x1234_u32);
+        assert_eq!(m.cross_check_hash::<SimpleHasher, SimpleHasher>(),
+                   0x1234_u32.cross_check_hash::<SimpleHasher, SimpleHasher>());
+        {
+            let _guard = m.lock().unwrap();
+            assert_eq!(m.cross_check_hash::<SimpleHasher, SimpleHasher>(),
+                       Some(CONTENDED_HASH));
+        }
+
+        let rw = RwLock::new(0x1234_u32);
+        {
+            // Readers do not block each other
+            let _guard = rw.read().unwrap();
+            assert_eq!(rw.cross_check_hash::<SimpleHasher, SimpleHasher>(),
+                       0x1234_u32.cross_check_hash::<SimpleHasher, SimpleHasher>());
+        }
+        let _guard = rw.write().unwrap();
+        assert_eq!(rw.cross_check_hash::<SimpleHasher, SimpleHasher>(),
+                   Some(CONTENDED_HASH));
+    }
+
+    #[test]
+    fn test_hasher_reset() {
+        use super::jodyhash::JodyHasher;
+
+        let mut h = JodyHasher::default();
+        h.write_u64(0x1234);
+        let hash = h.finish();
+        // The reset hasher is back in its initial state
+        h.reset();
+        assert_eq!(h.finish(), JodyHasher::default().finish());
+        h.write_u64(0x1234);
+        assert_eq!(h.finish(), hash);
+    }
+
+    #[test]
+    fn test_linked_list() {
+        use super::djb2::Djb2Hasher;
+
+        let list = [1u32, 2, 3].iter().cloned().collect::<LinkedList<_>>();
+        let rev = [3u32, 2, 1].iter().cloned().collect::<LinkedList<_>>();
+        assert_eq!(list.cross_check_hash::<Djb2Hasher, SimpleHasher>(),
+                   [1u32, 2, 3][..].cross_check_hash::<Djb2Hasher, SimpleHasher>());
+        assert_ne!(list.cross_check_hash::<Djb2Hasher, SimpleHasher>(),
+                   rev.cross_check_hash::<Djb2Hasher, SimpleHasher>());
+        assert_eq!(list.cross_check_hash_depth::<Djb2Hasher, SimpleHasher>(0), LEAF_ARRAY_HASH);
+    }
+
+    #[test]
+    fn test_option() {
+        type H = SimpleHasher;
+        let none: Option<u64> = None;
+        let some: Option<u64> = Some(0);
+        assert_eq!(none.cross_check_hash::<H, H>(), Some(NULL_POINTER_HASH));
+        assert_eq!(some.cross_check_hash::<H, H>(), 0u64.cross_check_hash::<H, H>());
+        assert_ne!(none.cross_check_hash::<H, H>(), some.cross_check_hash::<H, H>());
+
+        let x = 0x1234u32;
+        let none_ref: Option<&u32> = None;
+        assert_eq!(none_ref.cross_check_hash::<H, H>(), (0 as *const u32).cross_check_hash::<H, H>());
+        assert_eq!(Some(&x).cross_check_hash::<H, H>(), (&x as *const u32).cross_check_hash::<H, H>());
+    }
+}
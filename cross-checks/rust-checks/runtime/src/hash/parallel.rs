@@ -0,0 +1,34 @@
+// Parallel hashing for structures with the `parallel_hash` option:
+// the hashes of the fields get computed on the `rayon` thread pool,
+// and combined using XOR. Since the fields get hashed on other
+// threads, the `timeout_ms` deadline of the current thread does
+// not apply to them.
+
+use rayon;
+
+// Call all the hash functions, splitting them recursively
+// between `rayon` tasks, and XOR their results
+pub fn xor_join(fns: &[&(Fn() -> u64 + Sync)]) -> u64 {
+    match fns.len() {
+        0 => 0,
+        1 => fns[0](),
+        n => {
+            let (left, right) = fns.split_at(n / 2);
+            let (left_hash, right_hash) = rayon::join(|| xor_join(left), || xor_join(right));
+            left_hash ^ right_hash
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xor_join() {
+        assert_eq!(xor_join(&[]), 0);
+        assert_eq!(xor_join(&[&|| 0x12]), 0x12);
+        let fns: &[&(Fn() -> u64 + Sync)] = &[&|| 0x1, &|| 0x2, &|| 0x4, &|| 0x8, &|| 0x10];
+        assert_eq!(xor_join(fns), 0x1f);
+    }
+}
@@ -0,0 +1,123 @@
+// Paths are hashed by passing their bytes to the aggregate hasher, which
+// matches hashing the bytes of the corresponding C string. Since the same
+// path can be spelled differently on the two sides, the runtime can also
+// normalize paths before hashing them: trailing separators get stripped,
+// and on systems with case-insensitive file systems (Windows and macOS),
+// ASCII letters get converted to lowercase. Normalization is disabled by
+// default, and gets enabled using `set_normalize_paths(true)`, which the
+// rustc plugin calls at the start of `main` for `normalize_paths: true`.
+
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, ATOMIC_BOOL_INIT, Ordering};
+
+use super::{CrossCheckHash, CrossCheckHasher};
+
+static NORMALIZE_PATHS: AtomicBool = ATOMIC_BOOL_INIT;
+
+pub fn set_normalize_paths(normalize: bool) {
+    NORMALIZE_PATHS.store(normalize, Ordering::SeqCst);
+}
+
+pub fn normalize_paths() -> bool {
+    NORMALIZE_PATHS.load(Ordering::SeqCst)
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Cow<[u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::from(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Cow<[u8]> {
+    match path.to_string_lossy() {
+        Cow::Borrowed(s) => Cow::from(s.as_bytes()),
+        Cow::Owned(s) => Cow::from(s.into_bytes()),
+    }
+}
+
+#[cfg(not(windows))]
+fn is_separator(b: u8) -> bool {
+    b == b'/'
+}
+
+#[cfg(windows)]
+fn is_separator(b: u8) -> bool {
+    b == b'/' || b == b'\\'
+}
+
+// Normalize the bytes of a path, keeping at least one byte,
+// so that the root directory does not become an empty path
+fn normalize_path_bytes(bytes: &[u8]) -> Cow<[u8]> {
+    let len = bytes.iter().rposition(|b| !is_separator(*b)).map_or(1, |pos| pos + 1);
+    let bytes = &bytes[..len.min(bytes.len())];
+    if cfg!(any(windows, target_os = "macos")) {
+        Cow::from(bytes.to_ascii_lowercase())
+    } else {
+        Cow::from(bytes)
+    }
+}
+
+impl CrossCheckHash for Path {
+    #[inline]
+    fn cross_check_hash_depth<HA, HS>(&self, _depth: usize) -> u64
+            where HA: CrossCheckHasher, HS: CrossCheckHasher {
+        let bytes = path_bytes(self);
+        let mut h = HA::default();
+        if normalize_paths() {
+            h.write(&normalize_path_bytes(&bytes));
+        } else {
+            h.write(&bytes);
+        }
+        h.finish()
+    }
+}
+
+impl CrossCheckHash for PathBuf {
+    #[inline]
+    fn cross_check_hash_depth<HA, HS>(&self, depth: usize) -> u64
+            where HA: CrossCheckHasher, HS: CrossCheckHasher {
+        self.as_path().cross_check_hash_depth::<HA, HS>(depth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::djb2::Djb2Hasher;
+    use std::hash::Hasher;
+
+    type H = Djb2Hasher;
+
+    fn path_hash(path: &str) -> Option<u64> {
+        Path::new(path).cross_check_hash::<H, H>()
+    }
+
+    #[test]
+    fn test_paths() {
+        let bytes_hash = |bytes: &[u8]| {
+            let mut h = H::default();
+            h.write(bytes);
+            Some(h.finish())
+        };
+        assert_eq!(path_hash("/tmp/foo"), bytes_hash(b"/tmp/foo"));
+        assert_eq!(PathBuf::from("/tmp/foo").cross_check_hash::<H, H>(),
+                   path_hash("/tmp/foo"));
+        assert_ne!(path_hash("/tmp/foo/"), path_hash("/tmp/foo"));
+
+        // All tests that depend on the global setting are here,
+        // so they cannot race with each other
+        set_normalize_paths(true);
+        assert_eq!(path_hash("/tmp/foo//"), path_hash("/tmp/foo"));
+        assert_eq!(path_hash("/"), bytes_hash(b"/"));
+        assert_eq!(path_hash("//"), bytes_hash(b"/"));
+        assert_eq!(path_hash(""), bytes_hash(b""));
+        if cfg!(any(windows, target_os = "macos")) {
+            assert_eq!(path_hash("/TMP/Foo"), path_hash("/tmp/foo"));
+        } else {
+            assert_ne!(path_hash("/TMP/Foo"), path_hash("/tmp/foo"));
+        }
+        set_normalize_paths(false);
+    }
+}
@@ -74,6 +74,22 @@ impl Hasher for SimpleHasher {
 }
 
 impl CrossCheckHasher for SimpleHasher {
+    #[inline]
+    fn reset(&mut self) {
+        self.0 = None;
+    }
+
+    // The sub-hash is already a finished hash, so we use
+    // it as our own hash without mixing in any type
+    #[inline]
+    fn combine(&mut self, other_hash: u64) -> &mut Self {
+        if self.0.is_some() {
+            panic!("Tried to add hash multiple values with SimpleHasher");
+        }
+        self.0 = Some(other_hash);
+        self
+    }
+
     impl_primitive_hash!(bool,  write_bool,  Bool);
     impl_primitive_hash!(char,  write_char,  Char);
 
@@ -116,6 +132,20 @@ mod tests {
         h.finish();
     }
 
+    #[test]
+    fn test_combine() {
+        let mut h = SimpleHasher::default();
+        assert_eq!(h.combine(0x1234).finish(), 0x1234);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_double_combine() {
+        let mut h = SimpleHasher::default();
+        h.write_u64(0u64);
+        h.combine(1u64);
+    }
+
     #[test]
     fn test_values() {
         macro_rules! test_value {
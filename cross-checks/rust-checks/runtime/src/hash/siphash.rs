@@ -0,0 +1,29 @@
+// SipHash of a value, computed from its `Hash` implementation using
+// the standard library's `DefaultHasher`, used by the `siphash`
+// cross-check type. Unlike `crc32` and `blake3`, this hashes the
+// logical value instead of its in-memory representation, so it
+// also works for values with padding or pointers, e.g., `String`.
+// `DefaultHasher::new` always uses the same keys, so the hashes are
+// deterministic, but the standard library does not guarantee that
+// the algorithm stays the same between Rust releases.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+#[inline]
+pub fn siphash_value<T: ?Sized + Hash>(val: &T) -> u64 {
+    let mut h = DefaultHasher::new();
+    val.hash(&mut h);
+    h.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_siphash() {
+        assert_eq!(siphash_value("abc"), siphash_value(&String::from("abc")));
+        assert_ne!(siphash_value("abc"), siphash_value("abd"));
+    }
+}
@@ -5,14 +5,57 @@
 #![cfg_attr(feature="xcheck-with-dlsym", feature(libc))]
 #![cfg_attr(feature="xcheck-with-weak",  feature(linkage))]
 #![cfg_attr(feature="libc-hash",         feature(libc))]
+#![cfg_attr(feature="shm-backend",       feature(libc))]
+#![cfg_attr(feature="test-mode",         feature(specialization))]
 
 #[cfg(feature="djb2-ssse3")]
 extern crate simd;
 
-#[cfg(feature="libc-hash")]
+#[cfg(any(feature="libc-hash", feature="shm-backend"))]
 extern crate libc;
 
+#[cfg(feature="crc32fast")]
+extern crate crc32fast;
+
+#[cfg(feature="blake3-hash")]
+extern crate blake3;
+
+#[cfg(feature="rayon")]
+extern crate rayon;
+
+#[cfg(feature="serde_json")]
+#[macro_use]
+extern crate serde_json;
+
 pub mod xcheck;
 pub mod hash;
+pub mod eq;
+pub mod log;
 pub mod macros;
+pub mod replay;
+pub mod compare;
+pub mod monotonic;
+pub mod panic_hook;
+
+#[cfg(feature="test-mode")]
+pub mod test_mode;
+
+#[cfg(not(feature="test-mode"))]
+pub mod test_mode {
+    // Without the `test-mode` feature, cross-checked values are not recorded
+    #[inline(always)]
+    pub fn record_value<T: ?Sized>(_val: &T) {
+    }
+}
+#[cfg(feature="shm-backend")]
+pub mod shm;
+
+pub use xcheck::{Mode, set_mode};
+pub use panic_hook::set_panic_as_divergence;
 
+// Record a call to a Rust function invoked from C code as a callback,
+// e.g., the comparison function passed to `qsort`
+#[inline]
+pub fn callback_entry(fn_hash: u64) {
+    xcheck::xcheck(::std::iter::once((xcheck::CALLBACK_ENTRY_TAG, fn_hash)));
+}
@@ -0,0 +1,75 @@
+// Backend that writes each cross-check event as a JSON object on its own line,
+// e.g., `{"fn":2090069583,"hash":2090069583,"tag":1}`, so that logs can be
+// analyzed using `grep` or `jq`. Since the runtime only ever sees function
+// hashes, the `fn` field holds the entry hash of the innermost function
+// that is currently executing, or `null` outside of all functions.
+
+use std::io::Write;
+use std::sync::Mutex;
+
+use xcheck::{Backend, FUNCTION_ENTRY_TAG, FUNCTION_EXIT_TAG};
+
+struct JsonLog<W: Write> {
+    writer: W,
+    fn_stack: Vec<u64>,
+}
+
+pub struct JsonBackend<W: Write + Send> {
+    log: Mutex<JsonLog<W>>,
+}
+
+impl<W: Write + Send> JsonBackend<W> {
+    pub fn new(writer: W) -> JsonBackend<W> {
+        JsonBackend {
+            log: Mutex::new(JsonLog {
+                writer: writer,
+                fn_stack: vec![],
+            })
+        }
+    }
+
+    // Return the underlying writer
+    pub fn into_inner(self) -> W {
+        self.log.into_inner().unwrap().writer
+    }
+}
+
+impl<W: Write + Send> Backend for JsonBackend<W> {
+    fn xcheck(&self, tag: u8, val: u64) {
+        let mut log = self.log.lock().unwrap();
+        if tag == FUNCTION_ENTRY_TAG {
+            log.fn_stack.push(val);
+        }
+        let event = json!({
+            "tag": tag,
+            "hash": val,
+            "fn": log.fn_stack.last(),
+        });
+        if tag == FUNCTION_EXIT_TAG {
+            log.fn_stack.pop();
+        }
+        serde_json::to_writer(&mut log.writer, &event)
+            .expect("error writing cross-check event");
+        writeln!(log.writer).expect("error writing cross-check event");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_log() {
+        let backend = JsonBackend::new(vec![]);
+        backend.xcheck(FUNCTION_ENTRY_TAG, 0x7c93ee4f);
+        backend.xcheck(FUNCTION_EXIT_TAG, 0x7c93ee4f);
+        backend.xcheck(FUNCTION_EXIT_TAG, 0x7c93ee4f);
+        let log = String::from_utf8(backend.into_inner()).unwrap();
+        let lines = log.lines().collect::<Vec<_>>();
+        assert_eq!(lines, [
+            r#"{"fn":2090069583,"hash":2090069583,"tag":1}"#,
+            r#"{"fn":2090069583,"hash":2090069583,"tag":2}"#,
+            r#"{"fn":null,"hash":2090069583,"tag":2}"#,
+        ]);
+    }
+}
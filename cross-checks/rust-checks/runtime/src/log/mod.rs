@@ -0,0 +1,5 @@
+// Backends that log cross-check events to a file or stream,
+// for later analysis instead of online comparison
+
+#[cfg(feature="serde_json")]
+pub mod json;
@@ -2,6 +2,13 @@
 // FIXME: do we even need this one???
 #[macro_export]
 macro_rules! cross_check_iter {
+    // This form attaches a unique site identifier to the cross-checks,
+    // which backends can retrieve using `xcheck::site_id()`
+    ($iter:expr, site = $site:expr) => {{
+        $crate::xcheck::set_site_id($site);
+        $crate::xcheck::xcheck($iter);
+        $crate::xcheck::set_site_id(0);
+    }};
     ($iter:expr) => { $crate::xcheck::xcheck($iter) };
 }
 
@@ -10,6 +17,10 @@ macro_rules! cross_check_raw {
     ($item:expr) => {
         cross_check_raw!(UNKNOWN_TAG, $item)
     };
+    ($tag:ident, $item:expr, site = $site:expr) => {{
+        use std::iter::once;
+        cross_check_iter!(once(($crate::xcheck::$tag, $item as u64)), site = $site)
+    }};
     ($tag:ident, $item:expr) => {{
         use std::iter::once;
         cross_check_iter!(once(($crate::xcheck::$tag, $item as u64)))
@@ -31,8 +42,19 @@ macro_rules! cross_check_value {
     //   $shasher == the hasher to use for simple values
     ($tag:ident, $value:expr, $ahasher:ty, $shasher:ty) => {{
         use $crate::hash::CrossCheckHash as XCH;
+        $crate::test_mode::record_value(&$value);
         if let Some(hash) = XCH::cross_check_hash::<$ahasher, $shasher>(&$value) {
             cross_check_raw!($tag, hash)
         }
     }}
 }
+
+// Assert that the given function emitted its entry cross-check on the
+// current thread, while running with the `test-mode` backend installed
+#[cfg(feature="test-mode")]
+#[macro_export]
+macro_rules! assert_cross_check_eq {
+    ($fn_name:ident) => {
+        $crate::test_mode::assert_called(stringify!($fn_name))
+    };
+}
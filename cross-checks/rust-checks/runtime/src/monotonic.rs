@@ -0,0 +1,63 @@
+// Runtime support for the `monotonic` function option, which checks
+// that the values returned by a function never decrease, e.g., for
+// counters and other metrics; each function is identified by its
+// cross-check hash, and the last value is tracked per thread
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use xcheck::{self, Mode};
+
+thread_local! {
+    static LAST_VALUES: RefCell<HashMap<u64, u64>> = RefCell::new(HashMap::new());
+}
+
+// Check the new value returned by the function with the given hash
+// against the previous one, and report a decrease according to
+// the current `Mode`
+pub fn check(fn_id: u64, new_value: u64) {
+    let old_value = LAST_VALUES.with(|last| last.borrow_mut().insert(fn_id, new_value));
+    match old_value {
+        Some(old_value) if new_value < old_value => {
+            let msg = format!("monotonic cross-check failed for function {:#x}: \
+                               value decreased from {} to {}",
+                              fn_id, old_value, new_value);
+            match xcheck::mode() {
+                Mode::Assert => panic!("{}", msg),
+                Mode::Log => eprintln!("{}", msg),
+            }
+        }
+        _ => ()
+    }
+}
+
+// Forget the previous value for the given function,
+// e.g., after the counter it returns gets reset
+pub fn reset(fn_id: u64) {
+    LAST_VALUES.with(|last| last.borrow_mut().remove(&fn_id));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcheck::test_backend::ModeGuard;
+
+    #[test]
+    fn test_increasing() {
+        check(1, 10);
+        check(1, 10);
+        check(1, 20);
+        // Other functions are tracked separately
+        check(2, 5);
+        reset(1);
+        check(1, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "value decreased from 20 to 10")]
+    fn test_decreasing() {
+        let _mode = ModeGuard::new(Mode::Assert);
+        check(3, 20);
+        check(3, 10);
+    }
+}
@@ -0,0 +1,84 @@
+// Runtime support for reporting Rust panics as divergences: where the
+// Rust translation panics, e.g., on an out-of-bounds access, the C code
+// usually returns an error code instead, so the two executions diverge
+// without a mismatching cross-check. With `panic_as_divergence` enabled,
+// a panic hook emits a `PANIC_DIVERGENCE_TAG` cross-check holding the
+// djb2 hash of the panic location, so the comparison side can detect
+// and log the divergence, then runs the previously installed hook.
+
+use std::iter;
+use std::panic::{self, Location};
+use std::sync::{Once, ONCE_INIT};
+use std::sync::atomic::{AtomicBool, ATOMIC_BOOL_INIT, Ordering};
+
+use hash::djb2::djb2_str;
+use xcheck::{self, PANIC_DIVERGENCE_TAG};
+
+static ENABLED: AtomicBool = ATOMIC_BOOL_INIT;
+static HOOK_INIT: Once = ONCE_INIT;
+
+// Hash of a panic location, in the `file:line` format
+pub fn location_hash(location: &Location) -> u64 {
+    djb2_str(&format!("{}:{}", location.file(), location.line()))
+}
+
+// Enable or disable the panic divergence cross-checks; the hook is
+// installed the first time this gets enabled, and stays installed
+pub fn set_panic_as_divergence(enabled: bool) {
+    ENABLED.store(enabled, Ordering::SeqCst);
+    if enabled {
+        HOOK_INIT.call_once(|| {
+            let prev_hook = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                if ENABLED.load(Ordering::SeqCst) {
+                    let hash = info.location().map_or(0, location_hash);
+                    xcheck::xcheck(iter::once((PANIC_DIVERGENCE_TAG, hash)));
+                }
+                prev_hook(info);
+            }));
+        });
+    }
+}
+
+pub fn panic_as_divergence() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xcheck::test_backend::collect;
+
+    // Runs the given closure when dropped, even if the test panics
+    struct OnDrop<F: FnMut()>(F);
+
+    impl<F: FnMut()> Drop for OnDrop<F> {
+        fn drop(&mut self) {
+            (self.0)()
+        }
+    }
+
+    #[test]
+    fn test_panic_as_divergence() {
+        // Our hook gets installed on top of the default one, and we put
+        // back the original hook afterwards; the cross-checks go to the
+        // backend of the current thread, which is also the one that
+        // runs the hook, so other tests that panic do not affect them
+        let mut prev_hook = Some(panic::take_hook());
+        let _restore = OnDrop(move || {
+            set_panic_as_divergence(false);
+            if let Some(hook) = prev_hook.take() {
+                panic::set_hook(hook);
+            }
+        });
+        set_panic_as_divergence(true);
+        assert!(panic_as_divergence());
+        let line = line!() + 2;
+        let xchecks = collect(|| {
+            let res = panic::catch_unwind(|| panic!("test panic"));
+            assert!(res.is_err());
+        });
+        let expected = djb2_str(&format!("{}:{}", file!(), line));
+        assert_eq!(xchecks, vec![(PANIC_DIVERGENCE_TAG, expected)]);
+    }
+}
@@ -0,0 +1,225 @@
+// Replay support for previously recorded cross-check logs
+//
+// A cross-check log is a sequence of fixed-size records, one per event:
+// a 1-byte tag followed by the 64-bit value in little-endian byte order.
+// Logs start with a header record, which holds `LOG_HEADER_TAG` as its tag
+// and the `HASH_VERSION` of the runtime that recorded the log in the upper
+// half of its value. Logs without a header are rejected.
+
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use hash::HASH_VERSION;
+use xcheck::{self, xcheck, Backend};
+
+const RECORD_SIZE: usize = 9;
+
+pub const LOG_HEADER_TAG: u8 = 0xff;
+const LOG_MAGIC: u64 = 0x4b484358; // "XCHK" in ASCII
+
+// Iterator over all the (tag, value) records in a log
+pub struct LogReader<R: Read> {
+    inner: R,
+    // Hash version from the header, or `None` until we
+    // read the first record
+    version: Option<u32>,
+}
+
+impl<R: Read> LogReader<R> {
+    pub fn new(inner: R) -> LogReader<R> {
+        LogReader {
+            inner: inner,
+            version: None,
+        }
+    }
+
+    fn read_record(&mut self) -> Option<io::Result<(u8, u64)>> {
+        let mut buf = [0u8; RECORD_SIZE];
+        // Read the tag separately, so we can tell apart
+        // the end of the log from a truncated record
+        loop {
+            match self.inner.read(&mut buf[..1]) {
+                Ok(0) => return None,
+                Ok(_) => break,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        if let Err(e) = self.inner.read_exact(&mut buf[1..]) {
+            return Some(Err(e));
+        }
+        let val = buf[1..].iter().rev().fold(0u64, |acc, b| (acc << 8) | (*b as u64));
+        Some(Ok((buf[0], val)))
+    }
+
+    // Get the version of the hashes in this log, reading its header
+    // if needed; logs that don't start with a header are invalid
+    pub fn hash_version(&mut self) -> io::Result<u32> {
+        if let Some(version) = self.version {
+            return Ok(version);
+        }
+        let version = match self.read_record() {
+            Some(Ok((LOG_HEADER_TAG, val))) if val & 0xffffffff == LOG_MAGIC => {
+                (val >> 32) as u32
+            }
+            Some(Ok(_)) | None => {
+                return Err(io::Error::new(ErrorKind::InvalidData,
+                                          "cross-check log does not start with a header"));
+            }
+            Some(Err(e)) => return Err(e),
+        };
+        self.version = Some(version);
+        Ok(version)
+    }
+}
+
+impl<R: Read> Iterator for LogReader<R> {
+    type Item = io::Result<(u8, u64)>;
+
+    fn next(&mut self) -> Option<io::Result<(u8, u64)>> {
+        if let Err(e) = self.hash_version() {
+            return Some(Err(e));
+        }
+        self.read_record()
+    }
+}
+
+// Write the header of a log recorded using the current hash version;
+// this needs to be the first record in the log
+pub fn write_header<W: Write>(w: &mut W) -> io::Result<()> {
+    write_record(w, LOG_HEADER_TAG, ((HASH_VERSION as u64) << 32) | LOG_MAGIC)
+}
+
+// Append a single (tag, value) record to a log
+pub fn write_record<W: Write>(w: &mut W, tag: u8, val: u64) -> io::Result<()> {
+    let mut buf = [0u8; RECORD_SIZE];
+    buf[0] = tag;
+    for (i, b) in buf[1..].iter_mut().enumerate() {
+        *b = (val >> (8 * i)) as u8;
+    }
+    w.write_all(&buf)
+}
+
+// Backend that records all cross-checks to a log in the format above,
+// so that they can be replayed or compared against later
+pub struct RecordBackend<W: Write + Send> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> RecordBackend<W> {
+    pub fn new(mut writer: W) -> io::Result<RecordBackend<W>> {
+        write_header(&mut writer)?;
+        Ok(RecordBackend {
+            writer: Mutex::new(writer),
+        })
+    }
+}
+
+impl<W: Write + Send> Backend for RecordBackend<W> {
+    fn xcheck(&self, tag: u8, val: u64) {
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(e) = write_record(&mut *writer, tag, val) {
+            eprintln!("cross-check log: could not record event: {}", e);
+        }
+    }
+}
+
+// Record all subsequent cross-checks to a new log at the given path.
+// Installed backends never get dropped, so we write each record
+// straight to the file instead of buffering them.
+pub fn record(path: &Path) -> io::Result<()> {
+    let backend = RecordBackend::new(File::create(path)?)?;
+    xcheck::set_backend(Box::new(backend));
+    Ok(())
+}
+
+// Check that the hashes in the given log can be compared
+// against the ones computed by the current runtime
+pub fn check_hash_version<R: Read>(reader: &mut LogReader<R>) -> io::Result<()> {
+    let version = reader.hash_version()?;
+    if version != HASH_VERSION {
+        return Err(io::Error::new(ErrorKind::InvalidData, format!(
+            "cross-check log uses hash version {}, expected version {}",
+            version, HASH_VERSION)));
+    }
+    Ok(())
+}
+
+// Re-emit all the cross-checks from the given log through
+// the current rb_xcheck backend. The whole log is parsed
+// before any events are emitted, so a corrupted log
+// does not produce a partial replay.
+pub fn run(path: &Path) -> io::Result<()> {
+    let f = File::open(path)?;
+    let mut reader = LogReader::new(BufReader::new(f));
+    check_hash_version(&mut reader)?;
+    let records = reader.collect::<io::Result<Vec<_>>>()?;
+    xcheck(records.into_iter());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let events = [(1u8, 0x7c93ee4f_u64), (3, 0x0123456789abcdef), (2, 0)];
+        let mut log = vec![];
+        write_header(&mut log).unwrap();
+        for &(tag, val) in events.iter() {
+            write_record(&mut log, tag, val).unwrap();
+        }
+        assert_eq!(log.len(), (events.len() + 1) * RECORD_SIZE);
+        assert_eq!(&log[RECORD_SIZE..2 * RECORD_SIZE], &[1, 0x4f, 0xee, 0x93, 0x7c, 0, 0, 0, 0]);
+
+        let mut reader = LogReader::new(&log[..]);
+        assert_eq!(reader.hash_version().unwrap(), HASH_VERSION);
+        let read = reader.collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(&read[..], &events[..]);
+    }
+
+    #[test]
+    fn test_record_replay() {
+        use std::env;
+        use std::fs;
+        use xcheck::test_backend::{collect, with_backend};
+
+        let events = [(1u8, 0x7c93ee4f_u64), (3, 0x0123456789abcdef), (2, 0)];
+        let path = env::temp_dir().join("xcheck-test-record-replay.log");
+        let backend = RecordBackend::new(File::create(&path).unwrap()).unwrap();
+        with_backend(Box::new(backend), || xcheck(events.iter().cloned()));
+        let replayed = collect(|| run(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(&replayed[..], &events[..]);
+    }
+
+    #[test]
+    fn test_missing_header() {
+        let mut log = vec![];
+        write_record(&mut log, 1, 0x7c93ee4f).unwrap();
+        let mut reader = LogReader::new(&log[..]);
+        assert!(reader.hash_version().is_err());
+        assert!(LogReader::new(&log[..]).next().unwrap().is_err());
+        assert!(LogReader::new(&[][..]).hash_version().is_err());
+    }
+
+    #[test]
+    fn test_version_mismatch() {
+        let mut log = vec![];
+        write_record(&mut log, LOG_HEADER_TAG,
+                     (((HASH_VERSION + 1) as u64) << 32) | LOG_MAGIC).unwrap();
+        let mut reader = LogReader::new(&log[..]);
+        assert_eq!(reader.hash_version().unwrap(), HASH_VERSION + 1);
+        assert!(check_hash_version(&mut reader).is_err());
+    }
+
+    #[test]
+    fn test_truncated() {
+        let log = [1u8, 2, 3];
+        let mut reader = LogReader::new(&log[..]);
+        assert!(reader.next().unwrap().is_err());
+    }
+}
@@ -0,0 +1,149 @@
+// Backend that writes all cross-check events to a POSIX shared memory
+// segment, so that a separate monitoring process can compare the events
+// from the C and Rust programs in real time.
+//
+// The segment starts with a `ShmHeader`, followed by `capacity` slots
+// that form a lock-free ring buffer. Writers claim slots by incrementing
+// `write_idx`, then publish each event by storing the slot's index plus 1
+// into its `seq` field. Readers should read each slot only after its `seq`
+// matches the expected value; a larger value means that the writer has
+// wrapped around and overwritten events that the reader had not read yet.
+
+use libc;
+
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use xcheck::Backend;
+
+// "c2xcheck" in little-endian byte order
+pub const SHM_MAGIC: u64 = 0x6b63_6568_6378_3263;
+pub const DEFAULT_CAPACITY: usize = 1 << 16;
+
+#[repr(C)]
+pub struct ShmHeader {
+    pub magic: u64,
+    pub capacity: u64,
+    pub write_idx: AtomicUsize,
+}
+
+#[repr(C)]
+pub struct ShmSlot {
+    pub seq: AtomicUsize,
+    pub tag: u64,
+    pub val: u64,
+}
+
+pub struct ShmBackend {
+    header: *mut ShmHeader,
+    slots: *mut ShmSlot,
+    capacity: usize,
+    map_size: usize,
+}
+
+// The shared memory is only ever accessed through atomics
+// or slots that the current thread claimed for itself
+unsafe impl Send for ShmBackend {}
+unsafe impl Sync for ShmBackend {}
+
+impl ShmBackend {
+    // Create the shared memory segment with the given name, e.g.,
+    // "/cross_check_1234", which appears as "/dev/shm/cross_check_1234"
+    pub fn new(name: &str) -> io::Result<ShmBackend> {
+        ShmBackend::with_capacity(name, DEFAULT_CAPACITY)
+    }
+
+    // Create the segment for the current process, named "/cross_check_<pid>"
+    pub fn for_current_process() -> io::Result<ShmBackend> {
+        ShmBackend::new(&format!("/cross_check_{}", unsafe { libc::getpid() }))
+    }
+
+    pub fn with_capacity(name: &str, capacity: usize) -> io::Result<ShmBackend> {
+        assert!(capacity > 0, "shared memory ring buffer must not be empty");
+        let name = CString::new(name)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let map_size = mem::size_of::<ShmHeader>() + capacity * mem::size_of::<ShmSlot>();
+        unsafe {
+            let fd = libc::shm_open(name.as_ptr(), libc::O_CREAT | libc::O_RDWR, 0o600);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::ftruncate(fd, map_size as libc::off_t) < 0 {
+                let err = io::Error::last_os_error();
+                libc::close(fd);
+                return Err(err);
+            }
+            let ptr = libc::mmap(ptr::null_mut(), map_size,
+                                 libc::PROT_READ | libc::PROT_WRITE,
+                                 libc::MAP_SHARED, fd, 0);
+            libc::close(fd);
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+
+            // Reset the segment, in case it already existed
+            ptr::write_bytes(ptr as *mut u8, 0, map_size);
+            let header = ptr as *mut ShmHeader;
+            (*header).magic = SHM_MAGIC;
+            (*header).capacity = capacity as u64;
+            Ok(ShmBackend {
+                header: header,
+                slots: header.offset(1) as *mut ShmSlot,
+                capacity: capacity,
+                map_size: map_size,
+            })
+        }
+    }
+}
+
+impl Backend for ShmBackend {
+    fn xcheck(&self, tag: u8, val: u64) {
+        unsafe {
+            let idx = (*self.header).write_idx.fetch_add(1, Ordering::Relaxed);
+            let slot = self.slots.offset((idx % self.capacity) as isize);
+            (*slot).tag = tag as u64;
+            (*slot).val = val;
+            (*slot).seq.store(idx.wrapping_add(1), Ordering::Release);
+        }
+    }
+}
+
+impl Drop for ShmBackend {
+    fn drop(&mut self) {
+        // We leave the segment itself in place for the
+        // monitoring process, which should unlink it
+        unsafe {
+            libc::munmap(self.header as *mut libc::c_void, self.map_size);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer() {
+        let name = format!("/cross_check_test_{}", unsafe { libc::getpid() });
+        let backend = ShmBackend::with_capacity(&name, 2).unwrap();
+        backend.xcheck(1, 0x1234);
+        backend.xcheck(2, 0x5678);
+        backend.xcheck(3, 0x9abc);
+        unsafe {
+            assert_eq!((*backend.header).magic, SHM_MAGIC);
+            assert_eq!((*backend.header).write_idx.load(Ordering::SeqCst), 3);
+            // The third event overwrote the first one
+            let slot = &*backend.slots;
+            assert_eq!(slot.seq.load(Ordering::SeqCst), 3);
+            assert_eq!((slot.tag, slot.val), (3, 0x9abc));
+            let slot = &*backend.slots.offset(1);
+            assert_eq!(slot.seq.load(Ordering::SeqCst), 2);
+            assert_eq!((slot.tag, slot.val), (2, 0x5678));
+            let name = CString::new(name).unwrap();
+            libc::shm_unlink(name.as_ptr());
+        }
+    }
+}
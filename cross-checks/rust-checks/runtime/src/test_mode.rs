@@ -0,0 +1,138 @@
+// Backend for unit tests that records all cross-checks in memory,
+// along with the `Debug` representation of the cross-checked values
+// where available, so that assertion failures are self-describing.
+// The values are captured by `record_value`, which the cross-check
+// macros call right before hashing each value.
+
+use std::cell::RefCell;
+use std::fmt;
+
+use hash::djb2::djb2_str;
+use xcheck::{self, Backend, FUNCTION_ENTRY_TAG};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub tag: u8,
+    pub hash: u64,
+    pub site: u64,
+    // `Debug` output of the cross-checked value, if it has one
+    pub repr: Option<String>,
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "tag={} hash={:#x} site={}", self.tag, self.hash, self.site)?;
+        if let Some(ref repr) = self.repr {
+            write!(f, " value={}", repr)?;
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    static RECORDS: RefCell<Vec<Record>> = RefCell::new(vec![]);
+    static PENDING_REPR: RefCell<Option<String>> = RefCell::new(None);
+}
+
+// Get the `Debug` output of any value, or `None` if
+// its type does not implement `Debug`
+trait ValueRepr {
+    fn value_repr(&self) -> Option<String>;
+}
+
+impl<T: ?Sized> ValueRepr for T {
+    default fn value_repr(&self) -> Option<String> {
+        None
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> ValueRepr for T {
+    fn value_repr(&self) -> Option<String> {
+        Some(format!("{:?}", self))
+    }
+}
+
+// Remember the value that is about to get cross-checked,
+// so the backend can attach it to the next record
+#[inline]
+pub fn record_value<T: ?Sized>(val: &T) {
+    let repr = val.value_repr();
+    PENDING_REPR.with(|pending| *pending.borrow_mut() = repr);
+}
+
+// Records each cross-check for the current thread
+pub struct TestModeBackend;
+
+impl Backend for TestModeBackend {
+    fn xcheck(&self, tag: u8, val: u64) {
+        let repr = PENDING_REPR.with(|pending| pending.borrow_mut().take());
+        let record = Record {
+            tag: tag,
+            hash: val,
+            site: xcheck::site_id(),
+            repr: repr,
+        };
+        RECORDS.with(|records| records.borrow_mut().push(record));
+    }
+}
+
+// Install the test mode backend for all subsequent cross-checks
+pub fn install() {
+    xcheck::set_backend(Box::new(TestModeBackend));
+}
+
+// Remove and return all cross-checks recorded on the current thread
+pub fn take_records() -> Vec<Record> {
+    RECORDS.with(|records| records.borrow_mut().split_off(0))
+}
+
+// Assert that the function with the given name emitted its entry
+// cross-check on the current thread; on failure, the panic message
+// lists all the recorded cross-checks, along with their values
+pub fn assert_called(fn_name: &str) {
+    let fn_hash = djb2_str(fn_name);
+    RECORDS.with(|records| {
+        let records = records.borrow();
+        if !records.iter().any(|r| r.tag == FUNCTION_ENTRY_TAG && r.hash == fn_hash) {
+            let log = records.iter()
+                .map(|r| format!("  {}", r))
+                .collect::<Vec<_>>()
+                .join("\n");
+            panic!("missing entry cross-check for function `{}` (hash={:#x}), \
+                    recorded cross-checks:\n{}", fn_name, fn_hash, log);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoDebug;
+
+    #[test]
+    fn test_records() {
+        let backend = TestModeBackend;
+        take_records();
+        record_value(&42u32);
+        backend.xcheck(3, 1234);
+        record_value(&NoDebug);
+        backend.xcheck(4, 5678);
+        backend.xcheck(FUNCTION_ENTRY_TAG, djb2_str("foo"));
+        assert_called("foo");
+
+        let records = take_records();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].repr, Some(String::from("42")));
+        assert_eq!(records[1].repr, None);
+        assert_eq!(records[2].repr, None);
+        assert_eq!(format!("{}", records[0]), "tag=3 hash=0x4d2 site=0 value=42");
+    }
+
+    #[test]
+    #[should_panic(expected = "missing entry cross-check for function `bar`")]
+    fn test_missing() {
+        TestModeBackend.xcheck(FUNCTION_ENTRY_TAG, djb2_str("foo"));
+        assert_called("bar");
+    }
+}
@@ -1,9 +1,19 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
 
 pub const UNKNOWN_TAG: u8 = 0;
 pub const FUNCTION_ENTRY_TAG: u8 = 1;
 pub const FUNCTION_EXIT_TAG: u8 = 2;
 pub const FUNCTION_ARG_TAG: u8 = 3;
 pub const FUNCTION_RETURN_TAG: u8 = 4;
+pub const UNSAFE_BLOCK_ENTRY_TAG: u8 = 5;
+pub const UNSAFE_BLOCK_EXIT_TAG: u8 = 6;
+pub const LOOP_COUNT_TAG: u8 = 7;
+pub const CALLBACK_ENTRY_TAG: u8 = 8;
+pub const STATIC_MUT_TAG: u8 = 9;
+pub const PANIC_DIVERGENCE_TAG: u8 = 10;
+pub const ERROR_TAG: u8 = 11;
+pub const MATCH_ARM_TAG: u8 = 12;
 
 #[cfg(any(feature="xcheck-with-dlsym", feature="xcheck-with-weak"))]
 #[inline]
@@ -52,9 +62,158 @@ extern {
     fn rb_xcheck(tag: u8, val: u64);
 }
 
+// Alternative destination for cross-check events, which
+// replaces `rb_xcheck` once installed using `set_backend`
+pub trait Backend: Send + Sync {
+    fn xcheck(&self, tag: u8, val: u64);
+}
+
+// Pointer to a leaked `Box<Box<Backend>>`, or 0 if no backend is installed
+static BACKEND: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// Send all subsequent cross-checks to the given backend instead
+// of `rb_xcheck`. Any previously installed backend is leaked,
+// since other threads might still be using it.
+pub fn set_backend(backend: Box<Backend>) {
+    let backend_ptr = Box::into_raw(Box::new(backend));
+    BACKEND.store(backend_ptr as usize, Ordering::SeqCst);
+}
+
+// How backends that detect divergences should report them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    // Print the divergence and keep going
+    Log,
+
+    // Panic on the first divergence
+    Assert,
+}
+
+static MODE: AtomicUsize = ATOMIC_USIZE_INIT;
+
+pub fn set_mode(mode: Mode) {
+    MODE.store(mode as usize, Ordering::SeqCst);
+}
+
+pub fn mode() -> Mode {
+    match MODE.load(Ordering::SeqCst) {
+        x if x == Mode::Assert as usize => Mode::Assert,
+        _ => Mode::Log
+    }
+}
+
+thread_local! {
+    // Identifier of the cross-check site that is currently emitting
+    // cross-checks, or 0 if the site did not provide one
+    static SITE_ID: Cell<u64> = Cell::new(0);
+}
+
+pub fn set_site_id(site: u64) {
+    SITE_ID.with(|site_id| site_id.set(site));
+}
+
+// Get the identifier of the site of the cross-checks being emitted,
+// which lets backends tell apart the sites that share a function name
+pub fn site_id() -> u64 {
+    SITE_ID.with(|site_id| site_id.get())
+}
+
 #[inline]
 pub fn xcheck<I: Iterator<Item=(u8, u64)>>(checks: I) {
-    for (tag, val) in checks {
-        unsafe { rb_xcheck(tag, val) }
+    let backend_ptr = BACKEND.load(Ordering::SeqCst);
+    if backend_ptr != 0 {
+        let backend = unsafe { &*(backend_ptr as *const Box<Backend>) };
+        for (tag, val) in checks {
+            backend.xcheck(tag, val);
+        }
+    } else {
+        for (tag, val) in checks {
+            unsafe { rb_xcheck(tag, val) }
+        }
+    }
+}
+
+// Backend for the tests of the runtime, which sends the cross-checks
+// from each thread to the backend installed on that thread, so that
+// tests running in parallel do not see each other's cross-checks
+#[cfg(test)]
+pub mod test_backend {
+    use std::cell::RefCell;
+    use std::sync::{Arc, Mutex, Once, ONCE_INIT};
+    use super::{set_backend, set_mode, Backend, Mode};
+
+    thread_local! {
+        static THREAD_BACKEND: RefCell<Option<Box<Backend>>> = RefCell::new(None);
+    }
+
+    struct ThreadBackend;
+
+    impl Backend for ThreadBackend {
+        fn xcheck(&self, tag: u8, val: u64) {
+            THREAD_BACKEND.with(|backend| {
+                if let Some(ref backend) = *backend.borrow() {
+                    backend.xcheck(tag, val);
+                }
+            });
+        }
+    }
+
+    static INSTALL_THREAD_BACKEND: Once = ONCE_INIT;
+
+    // The tests are not linked against a real `rb_xcheck`,
+    // and send all their cross-checks to `ThreadBackend` anyway
+    #[no_mangle]
+    pub extern "C" fn rb_xcheck(_tag: u8, _val: u64) {
+    }
+
+    // Restores the previous backend of the thread, even if the test panics
+    struct RestoreGuard(Option<Box<Backend>>);
+
+    impl Drop for RestoreGuard {
+        fn drop(&mut self) {
+            let prev = self.0.take();
+            THREAD_BACKEND.with(|backend| *backend.borrow_mut() = prev);
+        }
+    }
+
+    // Send the cross-checks emitted by `f` on the current thread to `backend`
+    pub fn with_backend<R, F: FnOnce() -> R>(backend: Box<Backend>, f: F) -> R {
+        INSTALL_THREAD_BACKEND.call_once(|| set_backend(Box::new(ThreadBackend)));
+        let prev = THREAD_BACKEND.with(|tb| tb.borrow_mut().take());
+        THREAD_BACKEND.with(|tb| *tb.borrow_mut() = Some(backend));
+        let _guard = RestoreGuard(prev);
+        f()
+    }
+
+    struct CollectBackend(Arc<Mutex<Vec<(u8, u64)>>>);
+
+    impl Backend for CollectBackend {
+        fn xcheck(&self, tag: u8, val: u64) {
+            self.0.lock().unwrap().push((tag, val));
+        }
+    }
+
+    // Collect all the cross-checks emitted by `f` on the current thread
+    pub fn collect<F: FnOnce()>(f: F) -> Vec<(u8, u64)> {
+        let events = Arc::new(Mutex::new(vec![]));
+        with_backend(Box::new(CollectBackend(Arc::clone(&events))), f);
+        let res = events.lock().unwrap().clone();
+        res
+    }
+    // Switches the reporting mode of the backends until it gets
+    // dropped, then goes back to the default `Mode::Log`
+    pub struct ModeGuard;
+
+    impl ModeGuard {
+        pub fn new(mode: Mode) -> ModeGuard {
+            set_mode(mode);
+            ModeGuard
+        }
+    }
+
+    impl Drop for ModeGuard {
+        fn drop(&mut self) {
+            set_mode(Mode::Log);
+        }
     }
 }
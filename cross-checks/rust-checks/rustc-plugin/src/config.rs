@@ -1,13 +1,16 @@
 
 use syntax::ast;
+use syntax::attr;
 
 use std::rc::Rc;
 
 use syntax::ext::base::ExtCtxt;
 use syntax::ext::quote::rt::ExtParseUtils;
+use syntax::print::pprust;
 use syntax::tokenstream::TokenTree;
 
 use std::collections::HashMap;
+use std::fmt;
 
 use xcfg;
 use xcheck_util;
@@ -26,6 +29,42 @@ pub struct InheritedCheckConfig {
     // Overrides for ahasher/shasher
     pub ahasher: Option<Vec<TokenTree>>,
     pub shasher: Option<Vec<TokenTree>>,
+
+    // Whether to cross-check the entry and exit of `unsafe` blocks
+    pub unsafe_block_check: bool,
+
+    // Whether to cross-check the iteration count of each loop
+    pub loop_count_check: bool,
+
+    // Whether to cross-check the arm taken by each `match`
+    pub check_match_arms: bool,
+
+    // Whether to cross-check values by hash or by equality
+    pub check_mode: xcfg::CheckMode,
+
+    // Skip the argument cross-checks for functions
+    // with more than this many arguments
+    pub max_args: Option<usize>,
+
+    // Skip the cross-checks for functions with
+    // a lower cyclomatic complexity than this
+    pub min_complexity: Option<usize>,
+
+    // Width of the emitted hashes: 32 or 64 bits
+    pub hash_width: u8,
+
+    // Only cross-check public functions
+    pub public_only: bool,
+
+    // Cross-check the `static mut` variables of the current
+    // module at the beginning of each function
+    pub check_static_mut: bool,
+
+    // Only keep the function cross-checks in `#[cfg(test)]` builds
+    pub enabled_for_tests: bool,
+
+    // Prefix to look up the built-in tags in the `tag_map`
+    pub tag_prefix: Option<String>,
 }
 
 impl Default for InheritedCheckConfig {
@@ -38,15 +77,102 @@ impl Default for InheritedCheckConfig {
             ret: xcfg::XCheckType::Default,
             ahasher: None,
             shasher: None,
+            unsafe_block_check: false,
+            loop_count_check: false,
+            check_match_arms: false,
+            check_mode: xcfg::CheckMode::Hash,
+            max_args: None,
+            min_complexity: None,
+            hash_width: 64,
+            public_only: false,
+            check_static_mut: false,
+            enabled_for_tests: false,
+            tag_prefix: None,
         }
     }
 }
 
-#[derive(Debug)]
+// Which of two configurations wins when both of them change the same field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    // The other configuration belongs to a parent scope, so we win
+    Parent,
+    // The other configuration comes from the external configuration,
+    // which overrides the inline attributes, so it wins
+    External,
+}
+
+// Compare two hasher overrides by their tokens, ignoring their spans,
+// so that the same hasher written in two places compares equal
+fn same_tokens(a: &Option<Vec<TokenTree>>, b: &Option<Vec<TokenTree>>) -> bool {
+    match (a, b) {
+        (&Some(ref a), &Some(ref b)) =>
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x.eq_unspanned(y)),
+        (&None, &None) => true,
+        _ => false
+    }
+}
+
+impl InheritedCheckConfig {
+    // Merge two configurations that were both derived from `base`, e.g.,
+    // one from the #[cross_check] attribute and one from the external
+    // configuration. Each field takes the value from the configuration
+    // that changed it; if both of them did, `priority` decides which one wins
+    pub fn merge_with_priority(&self, other: &Self, base: &Self, priority: Priority) -> Self {
+        let mut res = self.clone();
+        macro_rules! merge_field {
+            ($eq:path; $($field:ident),*) => {$(
+                let self_changed = !$eq(&self.$field, &base.$field);
+                let other_changed = !$eq(&other.$field, &base.$field);
+                if other_changed && (!self_changed || priority == Priority::External) {
+                    res.$field = other.$field.clone();
+                }
+            )*}
+        }
+        merge_field!(PartialEq::eq;
+                     enabled, entry, exit, all_args, ret,
+                     unsafe_block_check, loop_count_check, check_match_arms, check_mode,
+                     max_args, min_complexity, hash_width, public_only,
+                     check_static_mut, enabled_for_tests, tag_prefix);
+        merge_field!(same_tokens; ahasher, shasher);
+        res
+    }
+}
+
+// Evaluate a `cfg` predicate from the external configuration, e.g.,
+// `target_os = "linux"`, against the current compilation target
+fn cfg_predicate_matches(cx: &ExtCtxt, pred: &str) -> Result<bool, String> {
+    let tts = cx.parse_tts(String::from(pred));
+    let mi = cx.new_parser_from_tts(&tts)
+        .parse_meta_item()
+        .map_err(|mut e| {
+            e.cancel();
+            format!("invalid cfg_predicate: {}", pred)
+        })?;
+    Ok(attr::cfg_matches(&mi, cx.parse_sess, cx.ecfg.features))
+}
+
+fn check_hash_width(width: u8) -> Result<u8, String> {
+    match width {
+        32 | 64 => Ok(width),
+        _ => Err(format!("invalid hash_width: {}, expected 32 or 64", width))
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct FunctionCheckConfig {
     pub args: HashMap<xcfg::FieldIndex, xcfg::XCheckType>,
     pub entry_extra: Vec<xcfg::ExtraXCheck>,
     pub exit_extra: Vec<xcfg::ExtraXCheck>,
+    pub arg_extra: HashMap<String, Vec<xcfg::ExtraXCheck>>,
+    pub only_on_panic: bool,
+    pub callback_check: bool,
+    pub no_inline: bool,
+    pub demangle: bool,
+    pub monotonic: bool,
+    pub abi_check: bool,
+    pub hash_before_cast: bool,
+    pub hash_errors: bool,
 }
 
 // We want all_args set to None, so we need a custom Default implementation
@@ -56,18 +182,86 @@ impl Default for FunctionCheckConfig {
             args: Default::default(),
             entry_extra: Default::default(),
             exit_extra: Default::default(),
+            arg_extra: Default::default(),
+            only_on_panic: false,
+            callback_check: false,
+            no_inline: false,
+            demangle: false,
+            monotonic: false,
+            abi_check: false,
+            hash_before_cast: false,
+            hash_errors: false,
         }
     }
 }
 
-#[derive(Debug, Default)]
+impl FunctionCheckConfig {
+    // Add the extra cross-checks from the external configuration to the
+    // ones inherited from the parent scope, or replace the inherited
+    // ones if the configuration asks for it
+    pub fn merge_extra_xchecks(&mut self, xcfg_func: &xcfg::FunctionConfig) {
+        if xcfg_func.no_inherit_extra {
+            self.clear_extra_xchecks();
+        }
+        self.entry_extra.extend(xcfg_func.entry_extra.iter().cloned());
+        self.exit_extra.extend(xcfg_func.exit_extra.iter().cloned());
+        for (arg, extra) in &xcfg_func.arg_extra {
+            self.arg_extra.entry(arg.clone())
+                .or_insert_with(Vec::new)
+                .extend(extra.iter().map(xcfg::ArgExtraXCheck::to_extra_xcheck));
+        }
+    }
+
+    pub fn clear_extra_xchecks(&mut self) {
+        self.entry_extra.clear();
+        self.exit_extra.clear();
+        self.arg_extra.clear();
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct StructCheckConfig {
     pub custom_hash: Option<String>,
     pub field_hasher: Option<String>,
     pub fields: HashMap<xcfg::FieldIndex, xcfg::XCheckType>,
+    pub hash_if: HashMap<xcfg::FieldIndex, String>,
+    pub all_fields: Option<xcfg::XCheckType>,
+    pub phantom_field_hash: bool,
+    pub field_order: Option<xcfg::FieldOrder>,
+    pub discriminant_only: bool,
+    pub timeout_ms: Option<u64>,
+    pub bitfield_hash: bool,
+    pub field_salt: bool,
+    pub c_align: Option<usize>,
+    pub mirror_c_layout: bool,
+    pub hash_display: bool,
+    pub hash_debug: bool,
+    pub field_groups: Vec<Vec<String>>,
+    pub parallel_hash: bool,
+    pub include_size: bool,
+    pub ignore_padding: bool,
+    // Types from other crates whose fields we hash through
+    // a local wrapper, set using `remote_hash_impl` attributes
+    pub remote_types: Vec<String>,
 }
 
-#[derive(Debug)]
+impl StructCheckConfig {
+    // Get the cross-check for a field, either by its exact name or
+    // index, or from the first regex that matches its name; since the
+    // fields are stored in a HashMap, we try the regexes in sorted
+    // order, so that the result is deterministic
+    pub fn field_xcheck(&self, field: &xcfg::FieldIndex) -> Option<&xcfg::XCheckType> {
+        self.fields.get(field).or_else(|| {
+            let mut regexes = self.fields.iter()
+                .filter(|&(idx, _)| idx.matches(field))
+                .collect::<Vec<_>>();
+            regexes.sort_by_key(|&(idx, _)| format!("{:?}", idx));
+            regexes.into_iter().next().map(|(_, xcheck)| xcheck)
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum ItemCheckConfig {
     // Top-level configuration
     Top,
@@ -88,6 +282,86 @@ pub enum ItemCheckConfig {
     Other,
 }
 
+// A single difference between two ScopeCheckConfig's
+#[derive(Debug)]
+pub struct ConfigDiff {
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+impl fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.old, self.new)
+    }
+}
+
+// Flat view of the effective configuration at some point in the scope
+// stack: the inherited settings of the innermost scope, along with the
+// configurations of the innermost enclosing function and structure
+#[derive(Debug)]
+pub struct ResolvedConfig {
+    pub inherited: InheritedCheckConfig,
+    pub function: Option<FunctionCheckConfig>,
+    pub structure: Option<StructCheckConfig>,
+}
+
+impl ResolvedConfig {
+    // Resolve a stack of configurations, going from the outermost
+    // scope to the innermost one, so that inner scopes win
+    pub fn resolve<'a, I>(scopes: I) -> ResolvedConfig
+            where I: IntoIterator<Item = &'a ScopeCheckConfig> {
+        let mut res = ResolvedConfig {
+            inherited: Default::default(),
+            function: None,
+            structure: None,
+        };
+        for scope in scopes {
+            res.inherited = (*scope.inherited).clone();
+            match scope.item {
+                ItemCheckConfig::Function(ref func) => res.function = Some(func.clone()),
+                ItemCheckConfig::Struct(ref struc) => res.structure = Some(struc.clone()),
+                _ => ()
+            }
+        }
+        res
+    }
+}
+
+impl fmt::Display for ResolvedConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        macro_rules! write_field {
+            ($name:expr, $value:expr) => {
+                writeln!(f, "  {}: {:?}", $name, $value)?;
+            }
+        }
+        let hasher_str = |h: &Option<Vec<TokenTree>>| {
+            h.as_ref().map(|tts| pprust::tts_to_string(tts))
+        };
+        let inh = &self.inherited;
+        write_field!("enabled",            inh.enabled);
+        write_field!("entry",              inh.entry);
+        write_field!("exit",               inh.exit);
+        write_field!("all_args",           inh.all_args);
+        write_field!("ret",                inh.ret);
+        write_field!("ahasher",            hasher_str(&inh.ahasher));
+        write_field!("shasher",            hasher_str(&inh.shasher));
+        write_field!("unsafe_block_check", inh.unsafe_block_check);
+        write_field!("loop_count_check",   inh.loop_count_check);
+        write_field!("check_match_arms",   inh.check_match_arms);
+        write_field!("check_mode",         inh.check_mode);
+        write_field!("max_args",           inh.max_args);
+        write_field!("min_complexity",     inh.min_complexity);
+        write_field!("hash_width",         inh.hash_width);
+        write_field!("public_only",        inh.public_only);
+        write_field!("check_static_mut",   inh.check_static_mut);
+        write_field!("enabled_for_tests",  inh.enabled_for_tests);
+        write_field!("tag_prefix",         inh.tag_prefix);
+        write_field!("function",           self.function);
+        write!(f, "  structure: {:?}", self.structure)
+    }
+}
+
 #[derive(Debug)]
 pub struct ScopeCheckConfig {
     // Cross-check configuration inherited from parent
@@ -121,7 +395,27 @@ impl ScopeCheckConfig {
     }
 
     pub fn inherit(&self, item: &ast::Item) -> Self {
-        Self::from_item(item, Rc::clone(&self.inherited))
+        let mut new_config = Self::from_item(item, Rc::clone(&self.inherited));
+        // Nested functions inherit the extra cross-checks of their parent
+        if let (&ItemCheckConfig::Function(ref parent_func),
+                &mut ItemCheckConfig::Function(ref mut func)) = (&self.item, &mut new_config.item) {
+            func.entry_extra = parent_func.entry_extra.clone();
+            func.exit_extra = parent_func.exit_extra.clone();
+        }
+        new_config
+    }
+
+    // Take a deep copy of this configuration, which can be passed
+    // to `restore` to undo any changes made after this call
+    pub fn snapshot(&self) -> ScopeCheckConfig {
+        ScopeCheckConfig {
+            inherited: Rc::new((*self.inherited).clone()),
+            item: self.item.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: ScopeCheckConfig) {
+        *self = snapshot;
     }
 
     pub fn new_file(&self) -> Self {
@@ -131,6 +425,45 @@ impl ScopeCheckConfig {
         }
     }
 
+    // Compute the list of fields that changed between this
+    // configuration and the other one, e.g., between the
+    // configuration of a parent scope and one of its children
+    pub fn diff(&self, other: &ScopeCheckConfig) -> Vec<ConfigDiff> {
+        let mut res = vec![];
+        macro_rules! diff_field {
+            ($name:expr, $old:expr, $new:expr) => {
+                let (old, new) = (format!("{:?}", $old), format!("{:?}", $new));
+                if old != new {
+                    res.push(ConfigDiff { field: $name, old: old, new: new });
+                }
+            }
+        }
+        let hasher_str = |h: &Option<Vec<TokenTree>>| {
+            h.as_ref().map(|tts| pprust::tts_to_string(tts))
+        };
+        let (old, new) = (&self.inherited, &other.inherited);
+        diff_field!("enabled",            old.enabled,            new.enabled);
+        diff_field!("entry",              old.entry,              new.entry);
+        diff_field!("exit",               old.exit,               new.exit);
+        diff_field!("all_args",           old.all_args,           new.all_args);
+        diff_field!("ret",                old.ret,                new.ret);
+        diff_field!("ahasher",            hasher_str(&old.ahasher), hasher_str(&new.ahasher));
+        diff_field!("shasher",            hasher_str(&old.shasher), hasher_str(&new.shasher));
+        diff_field!("unsafe_block_check", old.unsafe_block_check, new.unsafe_block_check);
+        diff_field!("loop_count_check",   old.loop_count_check,   new.loop_count_check);
+        diff_field!("check_match_arms",   old.check_match_arms,   new.check_match_arms);
+        diff_field!("check_mode",         old.check_mode,         new.check_mode);
+        diff_field!("max_args",           old.max_args,           new.max_args);
+        diff_field!("min_complexity",     old.min_complexity,     new.min_complexity);
+        diff_field!("hash_width",         old.hash_width,         new.hash_width);
+        diff_field!("public_only",        old.public_only,        new.public_only);
+        diff_field!("check_static_mut",   old.check_static_mut,   new.check_static_mut);
+        diff_field!("enabled_for_tests",  old.enabled_for_tests,  new.enabled_for_tests);
+        diff_field!("tag_prefix",         old.tag_prefix,         new.tag_prefix);
+        diff_field!("item",               self.item,              other.item);
+        res
+    }
+
     // Getters for various options
     pub fn function_config(&self) -> &FunctionCheckConfig {
         if let ItemCheckConfig::Function(ref func) = self.item {
@@ -150,10 +483,21 @@ impl ScopeCheckConfig {
         }
     }
 
+    // Apply the settings of the item's group on top of its own configuration
+    pub fn apply_group_config(&mut self, group: &xcfg::GroupConfig) {
+        if let Some(enabled) = group.enabled {
+            Rc::make_mut(&mut self.inherited).enabled = enabled;
+        }
+    }
+
     pub fn parse_attr_config(&mut self, cx: &ExtCtxt, mi: &ast::MetaItem) {
         assert!(mi.name == "cross_check");
         let args = xcfg::attr::get_syntax_item_args(mi);
         for (name, arg) in args.iter() {
+            if *name == "config" {
+                self.parse_json_config(cx, arg.as_str());
+                continue;
+            }
             match (*name, &mut self.item) {
                 ("disabled", _) |
                 ("none", _) => {
@@ -163,6 +507,9 @@ impl ScopeCheckConfig {
                 ("yes", _) => {
                     Rc::make_mut(&mut self.inherited).enabled = true
                 }
+                ("enabled_for_tests", _) => {
+                    Rc::make_mut(&mut self.inherited).enabled_for_tests = true
+                }
                 ("ahasher", _) => {
                     Rc::make_mut(&mut self.inherited).ahasher =
                         Some(cx.parse_tts(String::from(arg.as_str())));
@@ -172,6 +519,58 @@ impl ScopeCheckConfig {
                         Some(cx.parse_tts(String::from(arg.as_str())));
                 }
 
+                ("check_static_mut", &mut ItemCheckConfig::Top) |
+                ("check_static_mut", &mut ItemCheckConfig::FileDefaults) => {
+                    Rc::make_mut(&mut self.inherited).check_static_mut = true;
+                }
+                ("public_only", &mut ItemCheckConfig::Top) |
+                ("public_only", &mut ItemCheckConfig::FileDefaults) => {
+                    Rc::make_mut(&mut self.inherited).public_only = true;
+                }
+                ("tag_prefix", &mut ItemCheckConfig::Top) |
+                ("tag_prefix", &mut ItemCheckConfig::FileDefaults) => {
+                    Rc::make_mut(&mut self.inherited).tag_prefix =
+                        Some(String::from(arg.as_str()));
+                }
+
+                ("unsafe_block_check", &mut ItemCheckConfig::FileDefaults) |
+                ("unsafe_block_check", &mut ItemCheckConfig::Function(_)) => {
+                    Rc::make_mut(&mut self.inherited).unsafe_block_check = true;
+                }
+                ("loop_count_check", &mut ItemCheckConfig::FileDefaults) |
+                ("loop_count_check", &mut ItemCheckConfig::Function(_)) => {
+                    Rc::make_mut(&mut self.inherited).loop_count_check = true;
+                }
+                ("check_match_arms", &mut ItemCheckConfig::FileDefaults) |
+                ("check_match_arms", &mut ItemCheckConfig::Function(_)) => {
+                    Rc::make_mut(&mut self.inherited).check_match_arms = true;
+                }
+                ("min_complexity", &mut ItemCheckConfig::FileDefaults) |
+                ("min_complexity", &mut ItemCheckConfig::Function(_)) => {
+                    let min_complexity = match *arg {
+                        xcfg::attr::ArgValue::Int(n) => n as usize,
+                        _ => panic!("invalid min_complexity: {:?}", arg)
+                    };
+                    Rc::make_mut(&mut self.inherited).min_complexity = Some(min_complexity);
+                }
+                ("hash_width", &mut ItemCheckConfig::FileDefaults) |
+                ("hash_width", &mut ItemCheckConfig::Function(_)) => {
+                    // Check the width before narrowing it to `u8`,
+                    // so that e.g. 288 does not wrap around to 32
+                    let width = match *arg {
+                        xcfg::attr::ArgValue::Int(32) => 32,
+                        xcfg::attr::ArgValue::Int(64) => 64,
+                        _ => panic!("invalid hash_width: {:?}, expected 32 or 64", arg)
+                    };
+                    Rc::make_mut(&mut self.inherited).hash_width = width;
+                }
+                ("check_mode", &mut ItemCheckConfig::FileDefaults) |
+                ("check_mode", &mut ItemCheckConfig::Function(_)) => {
+                    Rc::make_mut(&mut self.inherited).check_mode =
+                        xcfg::CheckMode::from_str(arg.as_str())
+                        .expect(&format!("invalid cross-check mode: {}", arg.as_str()));
+                }
+
                 // Function-specific attributes
                 ("entry", &mut ItemCheckConfig::FileDefaults) |
                 ("entry", &mut ItemCheckConfig::Function(_)) => {
@@ -196,6 +595,14 @@ impl ScopeCheckConfig {
                         .unwrap_or(xcfg::XCheckType::Default);
                 }
 
+                ("args", &mut ItemCheckConfig::Function(_)) if arg.get_str().is_some() => {
+                    // A single cross-check type for all arguments,
+                    // e.g., args = "default", which is the same as all_args
+                    Rc::make_mut(&mut self.inherited).all_args =
+                        xcheck_util::parse_xcheck_arg(&arg)
+                        .unwrap_or(xcfg::XCheckType::Default);
+                }
+
                 ("args", &mut ItemCheckConfig::Function(ref mut func)) => {
                     // Parse per-argument cross-check types
                     func.args.extend(arg.as_list().iter().filter_map(|(name, arg)| {
@@ -216,6 +623,51 @@ impl ScopeCheckConfig {
                         .unwrap_or(xcfg::XCheckType::Default);
                 }
 
+                // Per-argument types, which `build_arg_xcheck`
+                // looks up directly in the function's attributes
+                ("arg", &mut ItemCheckConfig::Function(_)) |
+                ("as_type", &mut ItemCheckConfig::Function(_)) => {}
+
+                ("hash_fn", &mut ItemCheckConfig::Function(_)) => {
+                    // Shorthand for a custom return value cross-check
+                    // that calls the given function on the return value
+                    Rc::make_mut(&mut self.inherited).ret = xcfg::XCheckType::Custom(
+                        format!("{}(&__c2rust_fn_result)", arg.as_str()));
+                }
+
+                ("no_inherit_extra", &mut ItemCheckConfig::Function(ref mut func)) => {
+                    func.clear_extra_xchecks();
+                }
+
+                ("only_on_panic", &mut ItemCheckConfig::Function(ref mut func)) => {
+                    func.only_on_panic = true;
+                }
+
+                ("callback_check", &mut ItemCheckConfig::Function(ref mut func)) => {
+                    func.callback_check = true;
+                }
+
+                ("no_inline", &mut ItemCheckConfig::Function(ref mut func)) => {
+                    func.no_inline = true;
+                }
+
+                ("demangle", &mut ItemCheckConfig::Function(ref mut func)) => {
+                    func.demangle = true;
+                }
+                ("monotonic", &mut ItemCheckConfig::Function(ref mut func)) => {
+                    func.monotonic = true;
+                }
+
+                ("abi_check", &mut ItemCheckConfig::Function(ref mut func)) => {
+                    func.abi_check = true;
+                }
+                ("hash_before_cast", &mut ItemCheckConfig::Function(ref mut func)) => {
+                    func.hash_before_cast = true;
+                }
+                ("hash_errors", &mut ItemCheckConfig::Function(ref mut func)) => {
+                    func.hash_errors = true;
+                }
+
                 // TODO: handle entry_extra and exit_extra for Function
 
                 // Structure-specific attributes
@@ -223,16 +675,113 @@ impl ScopeCheckConfig {
                     struc.custom_hash = Some(String::from(arg.as_str()));
                 },
 
+                ("remote_hash_impl", &mut ItemCheckConfig::Struct(ref mut struc)) => {
+                    // Repeated attribute arguments override each other,
+                    // so several types are given as a comma-separated list
+                    struc.remote_types.extend(arg.as_str().split(',')
+                        .map(str::trim)
+                        .filter(|ty| !ty.is_empty())
+                        .map(String::from));
+                },
+
                 ("field_hasher", &mut ItemCheckConfig::Struct(ref mut struc)) => {
                     struc.field_hasher = Some(String::from(arg.as_str()));
                 }
 
+                ("all_fields", &mut ItemCheckConfig::Struct(ref mut struc)) => {
+                    struc.all_fields = Some(xcheck_util::parse_xcheck_arg(&arg)
+                        .unwrap_or(xcfg::XCheckType::Default));
+                }
+
+                ("phantom_field_hash", &mut ItemCheckConfig::Struct(ref mut struc)) => {
+                    struc.phantom_field_hash = true;
+                }
+
+                ("discriminant_only", &mut ItemCheckConfig::Struct(ref mut struc)) => {
+                    struc.discriminant_only = true;
+                }
+
+                ("hash_display", &mut ItemCheckConfig::Struct(ref mut struc)) => {
+                    struc.hash_display = true;
+                }
+
+                ("hash_debug", &mut ItemCheckConfig::Struct(ref mut struc)) => {
+                    struc.hash_debug = true;
+                }
+
+                ("c_align", &mut ItemCheckConfig::Struct(ref mut struc)) => {
+                    struc.c_align = match *arg {
+                        xcfg::attr::ArgValue::Int(n) => Some(n as usize),
+                        _ => panic!("invalid c_align: {:?}", arg)
+                    };
+                }
+
+                ("mirror_c_layout", &mut ItemCheckConfig::Struct(ref mut struc)) => {
+                    struc.mirror_c_layout = true;
+                }
+
+                ("field_salt", &mut ItemCheckConfig::Struct(ref mut struc)) => {
+                    struc.field_salt = true;
+                }
+
+                ("parallel_hash", &mut ItemCheckConfig::Struct(ref mut struc)) => {
+                    struc.parallel_hash = true;
+                }
+
+                ("include_size", &mut ItemCheckConfig::Struct(ref mut struc)) => {
+                    struc.include_size = true;
+                }
+
+                ("ignore_padding", &mut ItemCheckConfig::Struct(ref mut struc)) => {
+                    struc.ignore_padding = true;
+                }
+
+                ("field_groups", &mut ItemCheckConfig::Struct(ref mut struc)) => {
+                    struc.field_groups = arg.as_str().split(',').map(|group| {
+                        group.split(':').map(|field| String::from(field.trim())).collect()
+                    }).collect();
+                }
+
+                ("bitfield_hash", &mut ItemCheckConfig::Struct(ref mut struc)) => {
+                    struc.bitfield_hash = true;
+                }
+
+                ("timeout_ms", &mut ItemCheckConfig::Struct(ref mut struc)) => {
+                    struc.timeout_ms = match *arg {
+                        xcfg::attr::ArgValue::Int(n) => Some(n as u64),
+                        _ => panic!("invalid timeout_ms: {:?}", arg)
+                    };
+                }
+
+                ("field_order", &mut ItemCheckConfig::Struct(ref mut struc)) => {
+                    struc.field_order = Some(xcfg::FieldOrder::from_str(arg.as_str())
+                        .expect(&format!("invalid field_order: {}", arg.as_str())));
+                }
+
                 (name@_, _) => panic!("unknown cross_check item: {}", name)
             }
         }
     }
 
-    pub fn parse_xcfg_config(&mut self, cx: &ExtCtxt, xcfg: &xcfg::ItemConfig) {
+    // Parse an inline JSON configuration for the current item,
+    // passed as `#[cross_check(config = "...")]`, and apply it
+    // on top of the current configuration
+    fn parse_json_config(&mut self, cx: &ExtCtxt, json: &str) {
+        let xcfg = match self.item {
+            ItemCheckConfig::FileDefaults => xcfg::parse_json_string(json).map(xcfg::ItemConfig::Defaults),
+            ItemCheckConfig::Function(_) => xcfg::parse_json_string(json).map(xcfg::ItemConfig::Function),
+            ItemCheckConfig::Struct(_) => xcfg::parse_json_string(json).map(xcfg::ItemConfig::Struct),
+            _ => panic!("inline cross-check configuration not supported for this item")
+        };
+        let xcfg = xcfg.unwrap_or_else(|e| panic!("invalid inline cross-check configuration: {}", e));
+        self.parse_xcfg_config(cx, &xcfg)
+            .unwrap_or_else(|e| panic!("invalid inline cross-check configuration: {}", e));
+    }
+
+    // Apply the external configuration to this scope; on errors, the
+    // configuration is left partially applied, so callers that want
+    // to recover should take a snapshot first
+    pub fn parse_xcfg_config(&mut self, cx: &ExtCtxt, xcfg: &xcfg::ItemConfig) -> Result<(), String> {
         macro_rules! parse_optional_field {
             // Field for the current scope
             (>$self_name:ident, $self_parent:ident, $xcfg_parent:ident, $xcfg_name:ident, $new_value:expr) => (
@@ -255,6 +804,17 @@ impl ScopeCheckConfig {
                 parse_optional_field!(^exit,     xcfg_defs, exit,     exit.clone());
                 parse_optional_field!(^all_args, xcfg_defs, all_args, all_args.clone());
                 parse_optional_field!(^ret,      xcfg_defs, ret,      ret.clone());
+                parse_optional_field!(^unsafe_block_check, xcfg_defs, unsafe_block_check, *unsafe_block_check);
+                parse_optional_field!(^loop_count_check,   xcfg_defs, loop_count_check,   *loop_count_check);
+                parse_optional_field!(^check_match_arms,   xcfg_defs, check_match_arms,   *check_match_arms);
+                parse_optional_field!(^check_mode,         xcfg_defs, check_mode,         *check_mode);
+                parse_optional_field!(^hash_width,         xcfg_defs, hash_width,         check_hash_width(*hash_width)?);
+                parse_optional_field!(^max_args,           xcfg_defs, max_args,           Some(*max_args));
+                parse_optional_field!(^min_complexity,     xcfg_defs, min_complexity,     Some(*min_complexity));
+                parse_optional_field!(^public_only,        xcfg_defs, public_only,        *public_only);
+                parse_optional_field!(^check_static_mut,   xcfg_defs, check_static_mut,   *check_static_mut);
+                parse_optional_field!(^enabled_for_tests,  xcfg_defs, enabled_for_tests,  *enabled_for_tests);
+                parse_optional_field!(^tag_prefix,         xcfg_defs, tag_prefix,         Some(tag_prefix.clone()));
             },
 
             (&mut ItemCheckConfig::Function(ref mut self_func), &xcfg::ItemConfig::Function(ref xcfg_func)) => {
@@ -264,6 +824,13 @@ impl ScopeCheckConfig {
                 parse_optional_field!(^exit,     xcfg_func, exit,     exit.clone());
                 parse_optional_field!(^all_args, xcfg_func, all_args, all_args.clone());
                 parse_optional_field!(^ret,      xcfg_func, ret,      ret.clone());
+                parse_optional_field!(^unsafe_block_check, xcfg_func, unsafe_block_check, *unsafe_block_check);
+                parse_optional_field!(^loop_count_check,   xcfg_func, loop_count_check,   *loop_count_check);
+                parse_optional_field!(^check_match_arms,   xcfg_func, check_match_arms,   *check_match_arms);
+                parse_optional_field!(^check_mode,         xcfg_func, check_mode,         *check_mode);
+                parse_optional_field!(^hash_width,         xcfg_func, hash_width,         check_hash_width(*hash_width)?);
+                parse_optional_field!(^min_complexity,     xcfg_func, min_complexity,     Some(*min_complexity));
+                parse_optional_field!(^enabled_for_tests,  xcfg_func, enabled_for_tests,  *enabled_for_tests);
                 // TODO: add a way for the external config to reset these to default
                 parse_optional_field!(^ahasher, xcfg_func, ahasher, Some(cx.parse_tts(ahasher.clone())));
                 parse_optional_field!(^shasher, xcfg_func, shasher, Some(cx.parse_tts(shasher.clone())));
@@ -271,8 +838,17 @@ impl ScopeCheckConfig {
                 self_func.args.extend(xcfg_func.args.iter().map(|(k, v)| {
                     (xcfg::FieldIndex::from_str(k), v.clone())
                 }));
-                self_func.entry_extra.extend(xcfg_func.entry_extra.iter().cloned());
-                self_func.exit_extra.extend(xcfg_func.exit_extra.iter().cloned());
+                self_func.merge_extra_xchecks(xcfg_func);
+                parse_optional_field!(>only_on_panic, self_func, xcfg_func, only_on_panic, *only_on_panic);
+                parse_optional_field!(>callback_check, self_func, xcfg_func, callback_check, *callback_check);
+                parse_optional_field!(>no_inline, self_func, xcfg_func, no_inline, *no_inline);
+                parse_optional_field!(>demangle, self_func, xcfg_func, demangle, *demangle);
+                parse_optional_field!(>monotonic, self_func, xcfg_func, monotonic, *monotonic);
+                parse_optional_field!(>abi_check, self_func, xcfg_func, abi_check, *abi_check);
+                parse_optional_field!(>hash_before_cast, self_func, xcfg_func, hash_before_cast, *hash_before_cast);
+                parse_optional_field!(>hash_errors, self_func, xcfg_func, hash_errors, *hash_errors);
+                parse_optional_field!(^enabled, xcfg_func, cfg_predicate,
+                                      self.inherited.enabled && cfg_predicate_matches(cx, cfg_predicate)?);
                 // TODO: parse more fields: exit, ret
             },
 
@@ -285,7 +861,25 @@ impl ScopeCheckConfig {
                 // Structure-specific fields
                 parse_optional_field!(>custom_hash,  self_struc, xcfg_struc, custom_hash,  Some(custom_hash.clone()));
                 parse_optional_field!(>field_hasher, self_struc, xcfg_struc, field_hasher, Some(field_hasher.clone()));
+                parse_optional_field!(>all_fields,   self_struc, xcfg_struc, all_fields,   Some(all_fields.clone()));
+                parse_optional_field!(>phantom_field_hash, self_struc, xcfg_struc, phantom_field_hash, *phantom_field_hash);
+                parse_optional_field!(>field_order,  self_struc, xcfg_struc, field_order,  Some(*field_order));
+                parse_optional_field!(>discriminant_only, self_struc, xcfg_struc, discriminant_only, *discriminant_only);
+                parse_optional_field!(>timeout_ms,   self_struc, xcfg_struc, timeout_ms,   Some(*timeout_ms));
+                parse_optional_field!(>bitfield_hash, self_struc, xcfg_struc, bitfield_hash, *bitfield_hash);
+                parse_optional_field!(>field_salt,   self_struc, xcfg_struc, field_salt,   *field_salt);
+                parse_optional_field!(>c_align,      self_struc, xcfg_struc, c_align,      Some(*c_align));
+                parse_optional_field!(>mirror_c_layout, self_struc, xcfg_struc, mirror_c_layout, *mirror_c_layout);
+                parse_optional_field!(>hash_display, self_struc, xcfg_struc, hash_display, *hash_display);
+                parse_optional_field!(>hash_debug,   self_struc, xcfg_struc, hash_debug,   *hash_debug);
+                parse_optional_field!(>field_groups, self_struc, xcfg_struc, field_groups, field_groups.clone());
+                parse_optional_field!(>parallel_hash, self_struc, xcfg_struc, parallel_hash, *parallel_hash);
+                parse_optional_field!(>include_size, self_struc, xcfg_struc, include_size, *include_size);
+                parse_optional_field!(>ignore_padding, self_struc, xcfg_struc, ignore_padding, *ignore_padding);
+                parse_optional_field!(^enabled, xcfg_struc, cfg_predicate,
+                                      self.inherited.enabled && cfg_predicate_matches(cx, cfg_predicate)?);
                 self_struc.fields.extend(xcfg_struc.fields.clone().into_iter());
+                self_struc.hash_if.extend(xcfg_struc.hash_if.clone().into_iter());
             },
 
             // Parse the relevant fields for `impl`s
@@ -298,5 +892,6 @@ impl ScopeCheckConfig {
             },
             (_, _) => ()
         }
+        Ok(())
     }
 }
@@ -1,4 +1,4 @@
-#![feature(plugin_registrar, quote, rustc_private, try_from)]
+#![feature(plugin_registrar, quote, rustc_private, try_from, integer_atomics)]
 
 extern crate rustc_plugin;
 extern crate syntax;
@@ -6,30 +6,42 @@ extern crate syntax;
 #[macro_use]
 extern crate matches;
 
+extern crate rustc_demangle;
+
 extern crate cross_check_config as xcfg;
 
 mod config;
 mod xcheck_util;
+#[cfg(feature="network-config")]
+mod network;
 
 use rustc_plugin::Registry;
+use syntax::abi::Abi;
 use syntax::ast;
+use syntax::attr;
 use syntax::fold;
 
 use std::borrow::Cow;
 use std::cell::{Cell, RefCell};
 use std::collections::{HashSet, HashMap};
-use std::path::PathBuf;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature="network-config")]
+use std::time::Duration;
 
 use syntax::ext::base::{SyntaxExtension, ExtCtxt, Annotatable, MultiItemModifier};
+use syntax::ext::build::AstBuilder;
 use syntax::ext::quote::rt::{ToTokens, ExtParseUtils};
-use syntax::codemap::{Span, FileLoader, RealFileLoader};
+use syntax::codemap::{Span, CodeMap, ExpnFormat, FileLoader, RealFileLoader};
 use syntax::fold::Folder;
-use syntax::symbol::Symbol;
+use syntax::symbol::{keywords, Symbol};
 use syntax::print::pprust;
 use syntax::ptr::P;
 use syntax::tokenstream::TokenTree;
 use syntax::util::small_vector::SmallVector;
+use syntax::visit::{self, Visitor};
 
 use xcheck_util::CrossCheckBuilder;
 
@@ -42,19 +54,36 @@ struct ScopeConfig<'xcfg> {
     // We use this to keep track of the index/ident of the next field
     // in a tuple
     field_idx: Cell<usize>,
+
+    // Index of the next `unsafe` block in this scope, which we
+    // use as the value for the unsafe block cross-checks
+    unsafe_block_idx: Cell<usize>,
+
+    // Index of the next closure coerced to a C function pointer,
+    // used to give each generated wrapper a unique name
+    closure_idx: Cell<usize>,
+
+    // Function that cross-checks the `static mut` variables
+    // of the current module, if `check_static_mut` is enabled
+    statics_fn: Cell<Option<ast::Ident>>,
 }
 
 impl<'xcfg> ScopeConfig<'xcfg> {
-    fn new(cfg: &'xcfg xcfg::Config, file_name: String,
+    fn new(file_items: Vec<&'xcfg xcfg::ItemList>, file_name: String,
            ccc: config::ScopeCheckConfig) -> ScopeConfig<'xcfg> {
-        let items = cfg.get_file_items(&file_name)
-                       .map(xcfg::NamedItemList::new)
-                       .map(Rc::new);
+        let items = if file_items.is_empty() {
+            None
+        } else {
+            Some(Rc::new(xcfg::NamedItemList::from_lists(&file_items)))
+        };
         ScopeConfig {
             file_name: Rc::new(file_name),
             items: items,
             check_config: ccc,
             field_idx: Cell::new(0),
+            unsafe_block_idx: Cell::new(0),
+            closure_idx: Cell::new(0),
+            statics_fn: Cell::new(None),
         }
     }
 
@@ -74,6 +103,9 @@ impl<'xcfg> ScopeConfig<'xcfg> {
                               .map(Rc::new),
             check_config: ccc,
             field_idx: Cell::new(0),
+            unsafe_block_idx: Cell::new(0),
+            closure_idx: Cell::new(0),
+            statics_fn: Cell::new(self.statics_fn.get()),
         }
     }
 
@@ -88,10 +120,19 @@ struct CrossChecker<'a, 'cx: 'a, 'exp> {
     scope_stack: Vec<ScopeConfig<'exp>>,
     default_ahasher: Vec<TokenTree>,
     default_shasher: Vec<TokenTree>,
+    default_hasher32: Vec<TokenTree>,
 
     // New items to add at the next item boundary
     pending_items: Vec<P<ast::Item>>,
 
+    // Names of the remote types we already built hash wrappers
+    // for, one set for each enclosing module or block
+    remote_wrappers: Vec<HashSet<String>>,
+
+    // Whether we're folding the crate root, where we add the
+    // runtime settings from the configuration to `main`
+    crate_root: bool,
+
     // Whether to skip calling build_new_scope() on the first scope.
     // We set this to true for #[cross_check(...)] invocations caused
     // by macro expansions, since the compiler passes the attribute to us
@@ -104,6 +145,248 @@ fn find_cross_check_attr(attrs: &[ast::Attribute]) -> Option<&ast::Attribute> {
     attrs.iter().find(|attr| attr.check_name("cross_check"))
 }
 
+// Lints that the `bindgen` documentation tells users to allow
+// at the top of the module that includes the bindings
+const BINDGEN_ALLOWED_LINTS: &[&str] = &[
+    "non_camel_case_types",
+    "non_snake_case",
+    "non_upper_case_globals",
+];
+
+// Check if a module looks like it was generated by `bindgen`, i.e., it is
+// either named `bindings`, has a file-level `#![allow(...)]` attribute with
+// exactly the lints that `bindgen` needs, or defines a `BINDGEN_GUARD`
+// constant; the translated modules from c2rust also allow
+// `non_camel_case_types`, but together with several other lints
+fn is_bindgen_module(item: &ast::Item) -> bool {
+    let m = match item.node {
+        ast::ItemKind::Mod(ref m) => m,
+        _ => return false
+    };
+    let has_allow_attr = item.attrs.iter().any(|attr| {
+        attr.style == ast::AttrStyle::Inner && attr.check_name("allow") &&
+            attr.meta_item_list().map_or(false, |list| {
+                let mut lints = list.iter()
+                    .filter_map(|nmi| nmi.name())
+                    .map(|name| name.to_string())
+                    .collect::<Vec<_>>();
+                lints.sort();
+                lints.len() == list.len() && lints == BINDGEN_ALLOWED_LINTS
+            })
+    });
+    let has_guard = m.items.iter().any(|i| {
+        i.ident.name == "BINDGEN_GUARD" && matches!(i.node, ast::ItemKind::Const(..))
+    });
+    item.ident.name == "bindings" || has_allow_attr || has_guard
+}
+
+// Get the names of all the `static mut` variables defined in a module
+fn static_muts(m: &ast::Mod) -> Vec<ast::Ident> {
+    m.items.iter().filter_map(|item| match item.node {
+        ast::ItemKind::Static(_, ast::Mutability::Mutable, _) => Some(item.ident),
+        _ => None
+    }).collect()
+}
+
+// Check if a type is a `PhantomData<T>`, which we can't always hash
+// since `T` isn't required to implement `CrossCheckHash`
+fn is_phantom_data(ty: &ast::Ty) -> bool {
+    let ty_str = pprust::ty_to_string(ty);
+    ty_str.split('<').next().map_or(false, |path| path.trim().ends_with("PhantomData"))
+}
+
+// Check if a structure has the same layout as its only non-zero-sized field,
+// i.e., whether it has a `#[repr(transparent)]` attribute
+fn is_repr_transparent(attrs: &[ast::Attribute]) -> bool {
+    attrs.iter()
+        .filter(|attr| attr.check_name("repr"))
+        .filter_map(|attr| attr.meta_item_list())
+        .flat_map(|list| list.into_iter())
+        .any(|nmi| nmi.check_name("transparent"))
+}
+
+// Get the name of the type, without any path prefix or generic arguments,
+// e.g., `Inner` for `foo::Inner<T>`
+fn ty_name(ty: &ast::Ty) -> String {
+    let ty_str = pprust::ty_to_string(ty);
+    let path = ty_str.split('<').next().unwrap_or("").trim();
+    String::from(path.rsplit("::").next().unwrap_or(path))
+}
+
+// Computes the cyclomatic complexity of a function body, i.e., the number
+// of decision points plus one. We don't descend into nested items, since
+// they get checked (or skipped) separately.
+struct ComplexityCounter {
+    branches: usize,
+}
+
+impl<'ast> Visitor<'ast> for ComplexityCounter {
+    fn visit_expr(&mut self, expr: &'ast ast::Expr) {
+        self.branches += match expr.node {
+            ast::ExprKind::If(..) |
+            ast::ExprKind::IfLet(..) |
+            ast::ExprKind::While(..) |
+            ast::ExprKind::WhileLet(..) |
+            ast::ExprKind::ForLoop(..) |
+            ast::ExprKind::Loop(..) => 1,
+            ast::ExprKind::Match(_, ref arms) => arms.len().saturating_sub(1),
+            ast::ExprKind::Binary(op, ..) =>
+                matches!(op.node, ast::BinOpKind::And | ast::BinOpKind::Or) as usize,
+            _ => 0
+        };
+        visit::walk_expr(self, expr);
+    }
+
+    fn visit_item(&mut self, _: &'ast ast::Item) {}
+}
+
+fn cyclomatic_complexity(block: &ast::Block) -> usize {
+    let mut counter = ComplexityCounter { branches: 0 };
+    counter.visit_block(block);
+    counter.branches + 1
+}
+
+// Collects the types of all structure fields in a crate,
+// which we need to resolve nested field paths, e.g., "outer.inner.leaf"
+#[derive(Default)]
+struct StructFieldCollector {
+    field_types: HashMap<String, HashMap<xcfg::FieldIndex, String>>,
+}
+
+impl<'ast> Visitor<'ast> for StructFieldCollector {
+    fn visit_item(&mut self, item: &'ast ast::Item) {
+        match item.node {
+            ast::ItemKind::Struct(ref vdata, _) |
+            ast::ItemKind::Union(ref vdata, _) => {
+                let fields = vdata.fields().iter().enumerate().map(|(idx, sf)| {
+                    let sf_name = sf.ident
+                        .map(|ident| xcfg::FieldIndex::from_str(&*ident.name.as_str()))
+                        .unwrap_or(xcfg::FieldIndex::Int(idx));
+                    (sf_name, ty_name(&sf.ty))
+                }).collect();
+                self.field_types.insert(item.ident.name.to_string(), fields);
+            }
+            _ => ()
+        }
+        visit::walk_item(self, item);
+    }
+}
+
+// Prefix of the instrumented copies of crate roots that `xcheck-rustc`
+// and `xcheck-bench` compile, which are named `.xcheck-N-<original name>`
+const INSTRUMENTED_ROOT_PREFIX: &'static str = ".xcheck-";
+
+// Name of the file that contains the given span; for an instrumented
+// copy of a crate root, we return the name of the original file instead,
+// so that the external configuration entries for that file apply to it
+fn source_file_name(cx: &ExtCtxt, sp: Span) -> String {
+    let file_name = cx.codemap().span_to_filename(sp).to_string();
+    let original_name = {
+        let path = Path::new(&file_name);
+        path.file_name()
+            .and_then(|base_name| base_name.to_str())
+            .and_then(|base_name| {
+                if !base_name.starts_with(INSTRUMENTED_ROOT_PREFIX) {
+                    return None;
+                }
+                let rest = &base_name[INSTRUMENTED_ROOT_PREFIX.len()..];
+                let (idx, name) = rest.split_at(rest.find('-')?);
+                if idx.is_empty() || !idx.chars().all(|c| c.is_digit(10)) {
+                    return None;
+                }
+                Some(path.with_file_name(&name[1..]).to_string_lossy().into_owned())
+            })
+    };
+    original_name.unwrap_or(file_name)
+}
+
+// Check whether the given span was produced by a macro expansion
+fn is_from_expansion(sp: Span) -> bool {
+    sp.ctxt().outer().expn_info().is_some()
+}
+
+// Check whether the given span was produced by a `#[derive]`
+// expansion, e.g., the `impl Clone` from `#[derive(Clone)]`
+fn is_derive_expansion(sp: Span) -> bool {
+    let mut ctxt = sp.ctxt();
+    while let Some(ei) = ctxt.outer().expn_info() {
+        if let ExpnFormat::MacroAttribute(name) = ei.callee.format {
+            if name.as_str().starts_with("derive(") {
+                return true;
+            }
+        }
+        ctxt = ei.call_site.ctxt();
+    }
+    false
+}
+
+// Checks whether a function body can return early, either using `return`
+// or the `?` operator; we don't descend into nested items and closures,
+// since their early returns do not leave the function
+#[derive(Default)]
+struct EarlyReturnFinder {
+    found: bool,
+}
+
+impl<'ast> Visitor<'ast> for EarlyReturnFinder {
+    fn visit_expr(&mut self, expr: &'ast ast::Expr) {
+        match expr.node {
+            ast::ExprKind::Ret(..) |
+            ast::ExprKind::Try(..) => self.found = true,
+            ast::ExprKind::Closure(..) => (),
+            _ => visit::walk_expr(self, expr)
+        }
+    }
+
+    fn visit_item(&mut self, _: &'ast ast::Item) {}
+}
+
+// Split a function body ending in a cast, e.g., `{ ...; p as usize }`,
+// into the body without the cast and the type it casts to; this only
+// works if the body has no early returns, which would skip the cast
+fn split_tail_cast(block: &ast::Block) -> Option<(P<ast::Block>, P<ast::Ty>)> {
+    let (inner, ty) = match block.stmts.last().map(|stmt| &stmt.node) {
+        Some(&ast::StmtKind::Expr(ref e)) => match strip_parens(e).node {
+            ast::ExprKind::Cast(ref inner, ref ty) => (inner.clone(), ty.clone()),
+            _ => return None
+        },
+        _ => return None
+    };
+    let mut finder = EarlyReturnFinder::default();
+    finder.visit_block(block);
+    if finder.found {
+        return None;
+    }
+    let mut block = block.clone();
+    block.stmts.last_mut().unwrap().node = ast::StmtKind::Expr(inner);
+    Some((P(block), ty))
+}
+
+fn strip_parens(expr: &ast::Expr) -> &ast::Expr {
+    match expr.node {
+        ast::ExprKind::Paren(ref e) => strip_parens(e),
+        _ => expr
+    }
+}
+
+// Check if a type is an `extern "C" fn(...)` function pointer
+fn is_c_fn_ptr(ty: &ast::Ty) -> bool {
+    match ty.node {
+        ast::TyKind::BareFn(ref bare_fn) => bare_fn.abi == Abi::C,
+        _ => false
+    }
+}
+
+// Check if an expression coerces a closure to a C function pointer,
+// e.g., `(|x| x + 1) as extern "C" fn(i32) -> i32`
+fn is_c_closure_coercion(expr: &ast::Expr) -> bool {
+    match expr.node {
+        ast::ExprKind::Cast(ref e, ref ty) =>
+            matches!(strip_parens(e).node, ast::ExprKind::Closure(..)) && is_c_fn_ptr(ty),
+        _ => false
+    }
+}
+
 impl<'a, 'cx, 'exp> CrossChecker<'a, 'cx, 'exp> {
     fn new(expander: &'exp CrossCheckExpander,
            cx: &'a mut ExtCtxt<'cx>,
@@ -117,13 +400,20 @@ impl<'a, 'cx, 'exp> CrossChecker<'a, 'cx, 'exp> {
             let q = quote_ty!(cx, ::cross_check_runtime::hash::simple::SimpleHasher);
             q.to_tokens(cx)
         };
+        let default_hasher32 = {
+            let q = quote_ty!(cx, ::cross_check_runtime::hash::fnv::Fnv32Hasher);
+            q.to_tokens(cx)
+        };
         CrossChecker {
             expander: expander,
             cx: cx,
             scope_stack: vec![top_scope],
             default_ahasher: default_ahasher,
             default_shasher: default_shasher,
+            default_hasher32: default_hasher32,
             pending_items: vec![],
+            remote_wrappers: vec![],
+            crate_root: false,
             skip_first_scope: skip_first_scope,
         }
     }
@@ -138,14 +428,19 @@ impl<'a, 'cx, 'exp> CrossChecker<'a, 'cx, 'exp> {
         &self.last_scope().check_config
     }
 
+    // Get the effective configuration of the current scope,
+    // resolved across the entire scope stack
+    fn effective_config(&self) -> config::ResolvedConfig {
+        config::ResolvedConfig::resolve(self.scope_stack.iter().map(|scope| &scope.check_config))
+    }
+
     fn build_new_scope(&self, item: &ast::Item) -> ScopeConfig<'exp> {
         let span = match item.node {
             ast::ItemKind::Mod(ref m) => m.inner,
             _ => item.span
         };
         let last_scope = self.last_scope();
-        let mod_file_name = self.cx.codemap().span_to_filename(span);
-        let mod_file_name = mod_file_name.to_string();
+        let mod_file_name = source_file_name(self.cx, span);
         let same_file = last_scope.same_file(&mod_file_name);
 
         // Check if there are any file-level defaults, and if so, apply them
@@ -163,13 +458,17 @@ impl<'a, 'cx, 'exp> CrossChecker<'a, 'cx, 'exp> {
         };
 
         // We have either a #[cross_check] attribute
-        // or external config, so create a new ScopeCheckConfig
-        // TODO: order???
+        // or external config, so create a new ScopeCheckConfig;
+        // we parse the two separately, then merge the inherited
+        // fields so that the external configuration wins
+        let base_inherited = Rc::clone(&new_config.inherited);
         let xcheck_attr = find_cross_check_attr(&item.attrs);
         if let Some(ref attr) = xcheck_attr {
             let mi = attr.parse_meta(self.cx.parse_sess).unwrap();
             new_config.parse_attr_config(self.cx, &mi);
         };
+        let attr_inherited = mem::replace(&mut new_config.inherited,
+                                          Rc::clone(&base_inherited));
 
         let item_xcfg_config = {
             let item_ident_str = item.ident.name.as_str();
@@ -186,9 +485,53 @@ impl<'a, 'cx, 'exp> CrossChecker<'a, 'cx, 'exp> {
             last_scope.get_item_config(&*item_name)
         };
         if let Some(ref xcfg) = item_xcfg_config {
-            new_config.parse_xcfg_config(self.cx, xcfg);
+            self.expander.used_items.borrow_mut().insert(*xcfg as *const _);
+            if self.expander.verbosity >= 3 {
+                if let Some(loc) = xcfg.location() {
+                    eprintln!("cross_check: {}: using external configuration from {}",
+                              item.ident, loc);
+                }
+            }
+            // Roll back the external configuration if it's invalid,
+            // so we can keep going with the attribute configuration;
+            // entries that only name the item have nothing to apply
+            if !xcfg.is_empty() {
+                let snapshot = new_config.snapshot();
+                if let Err(e) = new_config.parse_xcfg_config(self.cx, xcfg) {
+                    self.cx.span_warn(item.span, &format!(
+                        "ignoring invalid external cross-check configuration: {}", e));
+                    new_config.restore(snapshot);
+                }
+            }
+        };
+        new_config.inherited = Rc::new(attr_inherited.merge_with_priority(
+            &new_config.inherited, &base_inherited, config::Priority::External));
+        if let Some(ref xcfg) = item_xcfg_config {
+            let group_config = xcfg.group()
+                .and_then(|group| self.expander.external_config.get_group(group));
+            if let Some(group_config) = group_config {
+                new_config.apply_group_config(group_config);
+            }
         };
 
+        // Apply the cross-checks configured using field paths in
+        // other structures; the structure's own configuration wins
+        if let config::ItemCheckConfig::Struct(ref mut struc) = new_config.item {
+            let field_path_xchecks = self.expander.field_path_xchecks.borrow();
+            let item_ident_str = item.ident.name.as_str();
+            if let Some(path_fields) = field_path_xchecks.get(&*item_ident_str) {
+                for (field, xcheck) in path_fields.iter() {
+                    struc.fields.entry(field.clone()).or_insert_with(|| xcheck.clone());
+                }
+            }
+        }
+
+        if self.expander.verbosity >= 3 {
+            for diff in self.config().diff(&new_config) {
+                eprintln!("cross_check: {}: {}", item.ident, diff);
+            }
+        }
+
         // Since rustc switched FileName from String to an enum,
         // we need to convert it to String ourselves, using format!
         // FIXME: ideally, we find a better way to handle all the
@@ -197,41 +540,127 @@ impl<'a, 'cx, 'exp> CrossChecker<'a, 'cx, 'exp> {
             // We should only ever get a file name mismatch
             // at the top of a module
             assert_matches!(item.node, ast::ItemKind::Mod(_));
-            ScopeConfig::new(&self.expander.external_config, mod_file_name, new_config)
+            let file_items = self.expander.file_items(&mod_file_name);
+            ScopeConfig::new(file_items, mod_file_name, new_config)
         } else {
             last_scope.from_item(item_xcfg_config, new_config)
         }
     }
 
     // Get the ahasher/shasher pair
+    // For 32-bit hashes, we default to a 32-bit hasher for both
     fn get_hasher_pair(&self) -> (&Vec<TokenTree>, &Vec<TokenTree>) {
-        (self.config().inherited.ahasher.as_ref().unwrap_or(self.default_ahasher.as_ref()),
-         self.config().inherited.shasher.as_ref().unwrap_or(self.default_shasher.as_ref()))
+        let (default_ahasher, default_shasher) = if self.config().inherited.hash_width == 32 {
+            (&self.default_hasher32, &self.default_hasher32)
+        } else {
+            (&self.default_ahasher, &self.default_shasher)
+        };
+        (self.config().inherited.ahasher.as_ref().unwrap_or(default_ahasher),
+         self.config().inherited.shasher.as_ref().unwrap_or(default_shasher))
+    }
+
+    // Build the expression that computes the cross-check for
+    // the value of the given identifier. By default, we use
+    // cross_check_hash to hash the value, unless we're
+    // cross-checking by equality, or the cross-check type
+    // gives us the hash function call in `hash_expr`.
+    fn build_value_xcheck_expr(&self, tag: ast::Ident, val_ident: ast::Ident,
+                               pre_hash_stmts: Vec<ast::Stmt>,
+                               hash_expr: Option<P<ast::Expr>>) -> P<ast::Expr> {
+        if let Some(hash_expr) = hash_expr {
+            return quote_expr!(self.cx, {
+                let val_ref = &$val_ident;
+                $pre_hash_stmts
+                ::cross_check_runtime::test_mode::record_value(val_ref);
+                Some(($tag, $hash_expr))
+            });
+        }
+        match self.config().inherited.check_mode {
+            xcfg::CheckMode::Hash if self.config().inherited.hash_width == 32 => {
+                // Truncate the hash to 32 bits before emitting it
+                let (ahasher, shasher) = self.get_hasher_pair();
+                quote_expr!(self.cx, {
+                    use cross_check_runtime::hash::CrossCheckHash32 as XCH32;
+                    let val_ref = &$val_ident;
+                    $pre_hash_stmts
+                    ::cross_check_runtime::test_mode::record_value(val_ref);
+                    let hash = XCH32::cross_check_hash32::<$ahasher, $shasher>(val_ref);
+                    hash.map(|hash| ($tag, hash as u64))
+                })
+            }
+            xcfg::CheckMode::Hash => {
+                let (ahasher, shasher) = self.get_hasher_pair();
+                quote_expr!(self.cx, {
+                    use cross_check_runtime::hash::CrossCheckHash as XCH;
+                    let val_ref = &$val_ident;
+                    $pre_hash_stmts
+                    ::cross_check_runtime::test_mode::record_value(val_ref);
+                    let hash = XCH::cross_check_hash::<$ahasher, $shasher>(val_ref);
+                    hash.map(|hash| ($tag, hash))
+                })
+            }
+            xcfg::CheckMode::Eq => {
+                quote_expr!(self.cx, {
+                    use cross_check_runtime::eq::CrossCheckEq as XCE;
+                    let val_ref = &$val_ident;
+                    $pre_hash_stmts
+                    ::cross_check_runtime::test_mode::record_value(val_ref);
+                    let val = XCE::cross_check_eq_value(val_ref);
+                    val.map(|val| ($tag, val))
+                })
+            }
+        }
     }
 
     // Get the cross-check block for this argument
-    fn build_arg_xcheck(&self, arg: &ast::Arg) -> Option<ast::Stmt> {
+    fn build_arg_xcheck(&self, arg: &ast::Arg, fn_attrs: &[ast::Attribute]) -> Option<ast::Stmt> {
         match arg.pat.node {
             ast::PatKind::Ident(_, ref ident, _) => {
                 // Parameter pattern is just an identifier,
                 // so we can reference it directly by name
-                let arg_idx = xcfg::FieldIndex::from_str(&*ident.node.name.as_str());
+                let arg_ident = ident.node;
+                let arg_idx = xcfg::FieldIndex::from_str(&*arg_ident.name.as_str());
                 let arg_xcheck_cfg = self.config().function_config()
                     .args.get(&arg_idx)
                     .unwrap_or(&self.config().inherited.all_args);
-                arg_xcheck_cfg.build_xcheck(self.cx, "FUNCTION_ARG_TAG", "val_ref",
-                                            |tag, pre_hash_stmts| {
-                    // By default, we use cross_check_hash
-                    // to hash the value of the identifier
-                    let (ahasher, shasher) = self.get_hasher_pair();
-                    quote_expr!(self.cx, {
-                        use cross_check_runtime::hash::CrossCheckHash as XCH;
-                        let val_ref = &$ident;
-                        $pre_hash_stmts
-                        let hash = XCH::cross_check_hash::<$ahasher, $shasher>(val_ref);
-                        hash.map(|hash| ($tag, hash))
+                // If the argument has an `as_type`, we hash a copy
+                // of it that was cast to that type
+                let as_type = self.parse_arg_as_type(fn_attrs, &*arg_ident.name.as_str());
+                let val_ident = if as_type.is_some() {
+                    ast::Ident::from_str(&format!("__c2rust_arg_{}", arg_ident.name))
+                } else {
+                    arg_ident
+                };
+                let site = self.expander.next_scope_id();
+                let xcheck = arg_xcheck_cfg.build_xcheck(self.cx, "FUNCTION_ARG_TAG",
+                                                         self.prefixed_tag("FUNCTION_ARG", arg.pat.span),
+                                                         site, "val_ref",
+                                                         |tag, pre_hash_stmts, hash_expr| {
+                    self.build_value_xcheck_expr(tag, val_ident, pre_hash_stmts, hash_expr)
+                });
+                let xcheck = match as_type {
+                    Some(as_type) => {
+                        let ty = self.cx.parse_tts(as_type);
+                        xcheck.map(|xcheck| quote_stmt!(self.cx, {
+                            let $val_ident = $arg_ident as $ty;
+                            $xcheck
+                        }).unwrap())
+                    }
+                    None => xcheck
+                };
+                // Append the extra cross-checks for this argument
+                let extra_xchecks = self.config().function_config()
+                    .arg_extra.get(&*arg_ident.name.as_str())
+                    .map(|extra| self.build_extra_xchecks(extra, arg.pat.span))
+                    .unwrap_or_default();
+                if extra_xchecks.is_empty() {
+                    xcheck
+                } else {
+                    quote_stmt!(self.cx, {
+                        $xcheck
+                        $extra_xchecks
                     })
-                })
+                }
             }
             _ => unimplemented!()
         }
@@ -263,91 +692,468 @@ impl<'a, 'cx, 'exp> CrossChecker<'a, 'cx, 'exp> {
             let mi = format!("custom_hash=\"{}\"", custom_hash);
             res.push(mi);
         }
+        if let Some(field_order) = struct_config.field_order {
+            let mi = format!("field_order=\"{}\"", field_order.as_str());
+            res.push(mi);
+        }
+        if struct_config.discriminant_only {
+            res.push(String::from("discriminant_only"));
+        }
+        if struct_config.hash_display {
+            res.push(String::from("hash_display"));
+        }
+        if struct_config.hash_debug {
+            res.push(String::from("hash_debug"));
+        }
+        if struct_config.field_salt {
+            res.push(String::from("field_salt"));
+        }
+        if struct_config.bitfield_hash {
+            res.push(String::from("bitfield_hash"));
+        }
+        if struct_config.parallel_hash {
+            res.push(String::from("parallel_hash"));
+        }
+        if struct_config.include_size {
+            res.push(String::from("include_size"));
+        }
+        if struct_config.ignore_padding {
+            res.push(String::from("ignore_padding"));
+        }
+        if !struct_config.field_groups.is_empty() {
+            let groups = struct_config.field_groups.iter()
+                .map(|group| group.join(":"))
+                .collect::<Vec<_>>();
+            let mi = format!("field_groups=\"{}\"", groups.join(","));
+            res.push(mi);
+        }
+        if let Some(timeout_ms) = struct_config.timeout_ms {
+            let mi = format!("timeout_ms={}", timeout_ms);
+            res.push(mi);
+        }
         res
     }
 
-    fn build_extra_xchecks(&self, extra_xchecks: &[xcfg::ExtraXCheck]) -> Vec<ast::Stmt> {
+    // Look up the value of a built-in tag, e.g., `FUNCTION_ENTRY`, in the
+    // `tag_map` after prepending the `tag_prefix` of the current scope;
+    // returns `None` if there is no prefix, and reports an error at `sp`
+    // if the prefixed tag is missing from the map
+    fn prefixed_tag(&self, tag_name: &str, sp: Span) -> Option<u8> {
+        self.config().inherited.tag_prefix.as_ref().and_then(|prefix| {
+            let prefixed_name = format!("{}{}", prefix, tag_name);
+            let tag_val = self.expander.external_config.get_tag(&prefixed_name);
+            if tag_val.is_none() {
+                self.cx.span_err(sp, &format!("cross-check tag `{}` is missing from the \
+                                               tag_map, but `tag_prefix` is set to `{}`",
+                                              prefixed_name, prefix));
+            }
+            tag_val
+        })
+    }
+
+    fn build_extra_xchecks(&self, extra_xchecks: &[xcfg::ExtraXCheck], sp: Span) -> Vec<ast::Stmt> {
         extra_xchecks.iter().flat_map(|ex| {
             // TODO: allow the custom functions to return Option or an iterator???
             let expr = self.cx.parse_expr(ex.custom.clone());
-            let tag_str = match ex.tag {
+            let site = self.expander.next_scope_id();
+            let tag = match ex.tag {
+                xcfg::ExtraXCheckTag::Builtin(ref tag) => tag,
+                xcfg::ExtraXCheckTag::Symbolic(ref name) => {
+                    // Symbolic tags are resolved using the global tag_map
+                    let tag_val = match self.expander.external_config.get_tag(name) {
+                        Some(tag_val) => tag_val,
+                        None => {
+                            self.cx.span_err(sp, &format!("unknown cross-check tag `{}`, \
+                                                           missing from the tag_map", name));
+                            return None;
+                        }
+                    };
+                    return quote_stmt!(self.cx, {
+                        use std::iter::once;
+                        cross_check_iter!(once(($tag_val, $expr as u64)), site = $site)
+                    });
+                }
+            };
+            let tag_str = match *tag {
                 xcfg::XCheckTag::Unknown        => "UNKNOWN_TAG",
                 xcfg::XCheckTag::FunctionEntry  => "FUNCTION_ENTRY_TAG",
                 xcfg::XCheckTag::FunctionExit   => "FUNCTION_EXIT_TAG",
                 xcfg::XCheckTag::FunctionArg    => "FUNCTION_ARG_TAG",
                 xcfg::XCheckTag::FunctionReturn => "FUNCTION_RETURN_TAG",
             };
+            if let Some(tag_val) = self.prefixed_tag(tag_str.trim_right_matches("_TAG"), sp) {
+                return quote_stmt!(self.cx, {
+                    use std::iter::once;
+                    cross_check_iter!(once(($tag_val, $expr as u64)), site = $site)
+                });
+            }
             let tag = ast::Ident::from_str(tag_str);
-            quote_stmt!(self.cx, cross_check_raw!($tag, $expr))
+            quote_stmt!(self.cx, cross_check_raw!($tag, $expr, site = $site))
         }).collect::<Vec<ast::Stmt>>()
     }
 
+    // Build a guard that emits the exit cross-check when it gets dropped
+    // while the function unwinds, since the unwinding skips the normal
+    // exit cross-check; this only works for exit cross-checks with
+    // a constant value, since the guard cannot access the function locals
+    fn build_exit_guard(&self, exit: &xcfg::XCheckType, fn_ident: &ast::Ident,
+                        site: u64, fn_span: Span) -> Vec<ast::Stmt> {
+        let fn_hash = match *exit {
+            xcfg::XCheckType::Default => xcheck_util::djb2_hash(&*fn_ident.name.as_str()) as u64,
+            xcfg::XCheckType::Djb2(ref s) => xcheck_util::djb2_hash(s) as u64,
+            xcfg::XCheckType::Fixed(id) => id,
+            _ => return vec![]
+        };
+        let exit_xcheck = match self.prefixed_tag("FUNCTION_EXIT", fn_span) {
+            Some(tag_val) => quote_stmt!(self.cx, {
+                use std::iter::once;
+                cross_check_iter!(once(($tag_val, self.fn_hash)), site = $site)
+            }),
+            None => quote_stmt!(self.cx,
+                cross_check_raw!(FUNCTION_EXIT_TAG, self.fn_hash, site = $site))
+        };
+        quote_block!(self.cx, {
+            struct __XCheckGuard {
+                fn_hash: u64,
+            }
+            impl Drop for __XCheckGuard {
+                fn drop(&mut self) {
+                    if ::std::thread::panicking() {
+                        $exit_xcheck
+                    }
+                }
+            }
+            let __c2rust_exit_guard = __XCheckGuard { fn_hash: $fn_hash };
+        }).into_inner().stmts
+    }
+
+    // Get the cross-check name of a function with the `demangle` option
+    // from the mangled symbol name in its `export_name` attribute, e.g.,
+    // `_ZN3foo3barE` becomes `foo::bar`; the symbol names rustc generates
+    // itself are not known yet at this point, so we need the attribute
+    fn demangled_fn_ident(&self, attrs: &[ast::Attribute], sp: Span) -> Option<ast::Ident> {
+        let export_name = match attr::first_attr_value_str_by_name(attrs, "export_name") {
+            Some(export_name) => export_name,
+            None => {
+                self.cx.span_warn(sp, "`demangle` requires an `export_name` \
+                                              attribute with the mangled name");
+                return None;
+            }
+        };
+        match rustc_demangle::try_demangle(&*export_name.as_str()) {
+            // The alternate format omits the trailing hash
+            Ok(demangled) => Some(ast::Ident::from_str(&format!("{:#}", demangled))),
+            Err(_) => {
+                self.cx.span_warn(sp, &format!(
+                    "could not demangle symbol name: {}", export_name));
+                None
+            }
+        }
+    }
+
     fn build_function_xchecks(&mut self, fn_ident: &ast::Ident,
                               fn_decl: &ast::FnDecl,
+                              fn_attrs: &[ast::Attribute],
+                              fn_span: Span,
+                              fn_vis: &ast::Visibility,
                               block: P<ast::Block>) -> P<ast::Block> {
-        let checked_block = if self.config().inherited.enabled {
+        // Skip private functions if we only want to check the public API
+        let is_public = matches!(fn_vis.node, ast::VisibilityKind::Public);
+        // Skip simple functions that are unlikely to diverge, e.g., getters
+        let too_simple = self.config().inherited.min_complexity
+            .map_or(false, |min_complexity| cyclomatic_complexity(&block) < min_complexity);
+        if too_simple && self.config().inherited.enabled {
+            let skipped = &self.expander.stats.skipped_min_complexity;
+            skipped.set(skipped.get() + 1);
+        }
+        // Keep a copy of the original body for non-test builds
+        let unchecked_block = if self.config().inherited.enabled &&
+                                 self.config().inherited.enabled_for_tests {
+            Some(block.clone())
+        } else {
+            None
+        };
+        let checked_block = if self.config().inherited.enabled &&
+                               (is_public || !self.config().inherited.public_only) &&
+                               !too_simple {
             // Add the cross-check to the beginning of the function
             // TODO: only add the checks to C abi functions???
             let ref cfg = self.config();
             let entry_xcheck = cfg.inherited.entry
-                .build_ident_xcheck(self.cx, "FUNCTION_ENTRY_TAG", fn_ident);
+                .build_ident_xcheck(self.cx, fn_span, "FUNCTION_ENTRY_TAG",
+                                    self.prefixed_tag("FUNCTION_ENTRY", fn_span),
+                                    self.expander.next_scope_id(), fn_ident);
+            let exit_site = self.expander.next_scope_id();
             let exit_xcheck = cfg.inherited.exit
-                .build_ident_xcheck(self.cx, "FUNCTION_EXIT_TAG", fn_ident);
-            // Insert cross-checks for function arguments
-            let arg_xchecks = fn_decl.inputs.iter()
-                .flat_map(|ref arg| self.build_arg_xcheck(arg))
-                .collect::<Vec<ast::Stmt>>();
+                .build_ident_xcheck(self.cx, fn_span, "FUNCTION_EXIT_TAG",
+                                    self.prefixed_tag("FUNCTION_EXIT", fn_span),
+                                    exit_site, fn_ident);
+            // Insert cross-checks for function arguments,
+            // unless the function has too many of them
+            let too_many_args = cfg.inherited.max_args
+                .map_or(false, |max_args| fn_decl.inputs.len() > max_args);
+            let arg_xchecks = if too_many_args {
+                self.cx.span_warn(fn_span, &format!(
+                    "skipping argument cross-checks for function with {} arguments",
+                    fn_decl.inputs.len()));
+                vec![]
+            } else {
+                fn_decl.inputs.iter()
+                    .flat_map(|ref arg| self.build_arg_xcheck(arg, fn_attrs))
+                    .collect::<Vec<ast::Stmt>>()
+            };
             let result_xcheck = cfg.inherited.ret
-                .build_xcheck(self.cx, "FUNCTION_RETURN_TAG", "val_ref",
-                              |tag, pre_hash_stmts| {
-                // By default, we use cross_check_hash
-                // to hash the value of the identifier
-                let (ahasher, shasher) = self.get_hasher_pair();
-                quote_expr!(self.cx, {
-                    use cross_check_runtime::hash::CrossCheckHash as XCH;
-                    let val_ref = &__c2rust_fn_result;
-                    $pre_hash_stmts
-                    let hash = XCH::cross_check_hash::<$ahasher, $shasher>(val_ref);
-                    hash.map(|hash| ($tag, hash))
-                })
+                .build_xcheck(self.cx, "FUNCTION_RETURN_TAG",
+                              self.prefixed_tag("FUNCTION_RETURN", fn_span),
+                              self.expander.next_scope_id(), "val_ref",
+                              |tag, pre_hash_stmts, hash_expr| {
+                let result_ident = ast::Ident::from_str("__c2rust_fn_result");
+                self.build_value_xcheck_expr(tag, result_ident, pre_hash_stmts, hash_expr)
             });
 
             let ref fcfg = cfg.function_config();
-            let entry_extra_xchecks = self.build_extra_xchecks(&fcfg.entry_extra);
-            let exit_extra_xchecks = self.build_extra_xchecks(&fcfg.exit_extra);
+            let entry_extra_xchecks = self.build_extra_xchecks(&fcfg.entry_extra, fn_span);
+            let exit_extra_xchecks = self.build_extra_xchecks(&fcfg.exit_extra, fn_span);
             // Extract the result type from the function signature,
             // so we can attach it to the __c2rust_fn_body closure
             let result_ty = match fn_decl.output {
                 ast::FunctionRetTy::Default(_) => quote_ty!(self.cx, ()),
                 ast::FunctionRetTy::Ty(ref ty) => ty.clone(),
             };
-            quote_block!(self.cx, {
-                $entry_xcheck
-                $arg_xchecks
-                $entry_extra_xchecks
-                let mut __c2rust_fn_body = || -> $result_ty { $block };
-                let __c2rust_fn_result = __c2rust_fn_body();
-                $exit_xcheck
-                $result_xcheck
-                $exit_extra_xchecks
-                __c2rust_fn_result
-            })
+            if fcfg.callback_check {
+                // Only record the call, so it can be compared against
+                // the call sites of the callback on the C side
+                let fn_hash = xcheck_util::djb2_hash(&*fn_ident.name.as_str()) as u64;
+                quote_block!(self.cx, {
+                    ::cross_check_runtime::callback_entry($fn_hash);
+                    $block
+                })
+            } else if fcfg.only_on_panic {
+                // Only emit the entry and exit cross-checks if the
+                // function unwinds; we skip the argument checks, since
+                // the body might have moved the arguments, and there
+                // is no return value to check
+                quote_block!(self.cx, {
+                    let __c2rust_fn_body = ::std::panic::AssertUnwindSafe(|| -> $result_ty { $block });
+                    match ::std::panic::catch_unwind(__c2rust_fn_body) {
+                        Ok(__c2rust_fn_result) => __c2rust_fn_result,
+                        Err(__c2rust_panic) => {
+                            $entry_xcheck
+                            $entry_extra_xchecks
+                            $exit_xcheck
+                            $exit_extra_xchecks
+                            ::std::panic::resume_unwind(__c2rust_panic)
+                        }
+                    }
+                })
+            } else {
+                let statics_xcheck = self.last_scope().statics_fn.get().map(|statics_fn| {
+                    quote_stmt!(self.cx, $statics_fn();).unwrap()
+                });
+                let exit_guard = self.build_exit_guard(&cfg.inherited.exit, fn_ident,
+                                                       exit_site, fn_span);
+                let monotonic_check = if fcfg.monotonic {
+                    let fn_hash = xcheck_util::djb2_hash(&*fn_ident.name.as_str()) as u64;
+                    quote_stmt!(self.cx,
+                        ::cross_check_runtime::monotonic::check($fn_hash, __c2rust_fn_result as u64);)
+                } else { None };
+                // Cross-check the error values separately from the
+                // return value cross-check, with their own tag
+                let error_xcheck = if fcfg.hash_errors {
+                    let (ahasher, shasher) = self.get_hasher_pair();
+                    quote_stmt!(self.cx,
+                        if let Err(ref __c2rust_fn_error) = __c2rust_fn_result {
+                            cross_check_value!(ERROR_TAG, *__c2rust_fn_error, $ahasher, $shasher);
+                        })
+                } else { None };
+                // With `hash_before_cast`, the body returns the value before
+                // its final cast, so the cross-checks see the original value,
+                // and we apply the cast ourselves at the end
+                let pre_cast = if fcfg.hash_before_cast {
+                    let pre_cast = split_tail_cast(&block);
+                    if pre_cast.is_none() {
+                        self.cx.span_warn(fn_span, "`hash_before_cast` requires a function \
+                                                    body that ends in a cast, without any \
+                                                    early returns");
+                    }
+                    pre_cast
+                } else { None };
+                let (fn_body, fn_result) = match pre_cast {
+                    Some((pre_cast_block, cast_ty)) => (
+                        quote_stmt!(self.cx, let mut __c2rust_fn_body = || $pre_cast_block;),
+                        quote_expr!(self.cx, __c2rust_fn_result as $cast_ty)),
+                    None => (
+                        quote_stmt!(self.cx, let mut __c2rust_fn_body = || -> $result_ty { $block };),
+                        quote_expr!(self.cx, __c2rust_fn_result)),
+                };
+                quote_block!(self.cx, {
+                    $entry_xcheck
+                    $exit_guard
+                    $statics_xcheck
+                    $arg_xchecks
+                    $entry_extra_xchecks
+                    $fn_body
+                    let __c2rust_fn_result = __c2rust_fn_body();
+                    $exit_xcheck
+                    $result_xcheck
+                    $error_xcheck
+                    $monotonic_check
+                    $exit_extra_xchecks
+                    $fn_result
+                })
+            }
         } else {
             block
         };
-        // Add our typedefs to the beginning of each function;
-        // whatever the configuration says, we should always add these
+        // Only keep the cross-checks in `#[cfg(test)]` builds, if requested
+        let checked_block = match unchecked_block {
+            Some(unchecked_block) => quote_block!(self.cx, {
+                #[cfg(test)]
+                let __c2rust_fn_result = $checked_block;
+                #[cfg(not(test))]
+                let __c2rust_fn_result = $unchecked_block;
+                __c2rust_fn_result
+            }),
+            None => checked_block
+        };
+        // Add our hasher imports to the beginning of each function;
+        // whatever the configuration says, we should always add these,
+        // but only silence the warnings for the ones that go unused
         let (ahasher, shasher) = self.get_hasher_pair();
         quote_block!(self.cx, {
-            #[allow(dead_code)]
             mod cross_check_types {
-                pub type DefaultAggHasher    = $ahasher;
-                pub type DefaultSimpleHasher = $shasher;
+                #[allow(unused_imports)]
+                pub use $ahasher as DefaultAggHasher;
+                #[allow(unused_imports)]
+                pub use $shasher as DefaultSimpleHasher;
             };
             $checked_block
         })
     }
 
+    // Name of the function that cross-checks the `static mut` variables
+    // of the given module, if there are any and `check_static_mut` is set;
+    // the name is built from the name of the current file
+    fn build_statics_fn_ident(&self, m: &ast::Mod) -> Option<ast::Ident> {
+        if !self.config().inherited.check_static_mut || static_muts(m).is_empty() {
+            return None;
+        }
+        let file_stem = Path::new(&**self.last_scope().file_name)
+            .file_stem()
+            .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+        let file_stem = file_stem.chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        Some(ast::Ident::from_str(&format!("__c2rust_check_statics_{}", file_stem)))
+    }
+
+    fn build_statics_fn(&self, fn_ident: ast::Ident, statics: &[ast::Ident]) -> P<ast::Item> {
+        let (ahasher, shasher) = self.get_hasher_pair();
+        let xchecks = statics.iter().map(|static_ident| {
+            quote_stmt!(self.cx,
+                cross_check_value!(STATIC_MUT_TAG, $static_ident, $ahasher, $shasher);
+            ).unwrap()
+        }).collect::<Vec<_>>();
+        quote_item!(self.cx,
+            #[allow(dead_code, non_snake_case)]
+            #[inline(never)]
+            fn $fn_ident() {
+                unsafe { $xchecks }
+            }
+        ).unwrap()
+    }
+
+    // Check if we can hash a folded `#[repr(transparent)]` structure as its
+    // inner field, which matches C code where the wrapper type is a typedef;
+    // this requires the structure to have no custom hash, and exactly one
+    // field with the default cross-check, and all others disabled
+    fn use_transparent_hash(&self, item: &ast::Item) -> bool {
+        let vdata = match item.node {
+            ast::ItemKind::Struct(ref vdata, _) => vdata,
+            _ => return false
+        };
+        if !is_repr_transparent(&item.attrs) ||
+           self.config().struct_config().custom_hash.is_some() {
+            return false;
+        }
+        let mut hashed_fields = 0;
+        for sf in vdata.fields() {
+            let hash_args = sf.attrs.iter()
+                .filter(|attr| attr.check_name("cross_check_hash"))
+                .filter_map(|attr| attr.meta_item_list())
+                .flat_map(|list| list.into_iter())
+                .collect::<Vec<_>>();
+            if hash_args.is_empty() {
+                hashed_fields += 1;
+            } else if !hash_args.iter().all(|nmi| nmi.check_name("none") ||
+                                                 nmi.check_name("disabled")) {
+                return false;
+            }
+        }
+        hashed_fields == 1
+    }
+
+    // Add #[repr(C)] to a structure that doesn't have it already; other
+    // representation hints are kept, but might not match the C layout
+    fn add_repr_c(&self, attrs: &mut Vec<ast::Attribute>, sp: Span) {
+        let repr_hints = attrs.iter()
+            .filter(|attr| attr.check_name("repr"))
+            .filter_map(|attr| attr.meta_item_list())
+            .flat_map(|list| list.into_iter())
+            .collect::<Vec<_>>();
+        if repr_hints.iter().any(|nmi| nmi.check_name("C")) {
+            return;
+        }
+        if repr_hints.iter().any(|nmi| nmi.check_name("packed") || nmi.check_name("align")) {
+            self.cx.span_warn(sp, "adding #[repr(C)] to structure with #[repr(packed)] \
+                                   or #[repr(align)], which might not match the C layout");
+        }
+        attrs.push(quote_attr!(self.cx, #[repr(C)]));
+    }
+
+    // Build a static that holds the name and ABI of a function as
+    // `name=abi\0`, e.g., `foo=C\0`, in the `.cross_check_abi` link
+    // section, so that a tool can compare it to the C entries for
+    // the same function; the identifier gets a unique suffix, since
+    // functions in different modules can have the same name
+    fn build_abi_check(&self, fn_ident: &ast::Ident, abi: Abi, sp: Span) -> P<ast::Item> {
+        let abi_entry = format!("{}={}\0", fn_ident, abi.name());
+        let abi_len = abi_entry.len();
+        let abi_lit = self.cx.expr_lit(sp, ast::LitKind::ByteStr(Rc::new(abi_entry.into_bytes())));
+        let static_ident = ast::Ident::from_str(&format!(
+            "__c2rust_abi_{}_{}", fn_ident, self.expander.next_scope_id()));
+        quote_item!(self.cx,
+            #[allow(dead_code, non_upper_case_globals)]
+            #[no_mangle]
+            #[link_section = ".cross_check_abi"]
+            pub static $static_ident: [u8; $abi_len] = *$abi_lit;
+        ).unwrap()
+    }
+
+    // Build a compile-time check that the alignment of the given structure
+    // matches the alignment of its C counterpart; if it doesn't, the array
+    // lengths below differ, and rustc reports a type mismatch for a constant
+    // whose name describes the failed check
+    fn build_align_check(&self, item: &ast::Item, c_align: usize) -> Option<P<ast::Item>> {
+        let has_generics = match item.node {
+            ast::ItemKind::Struct(_, ref generics) |
+            ast::ItemKind::Enum(_, ref generics) => !generics.params.is_empty(),
+            _ => false
+        };
+        if has_generics {
+            self.cx.span_warn(item.span, "skipping c_align check for generic type");
+            return None;
+        }
+        let ty_ident = item.ident;
+        let check_ident = ast::Ident::from_str(&format!(
+            "__c2rust_c_align_mismatch_for_{}_expected_{}", ty_ident, c_align));
+        quote_item!(self.cx,
+            #[allow(dead_code, non_upper_case_globals)]
+            const $check_ident: [(); 0] =
+                [(); (::std::mem::align_of::<$ty_ident>() != $c_align) as usize];
+        )
+    }
+
     fn build_union_hash(&mut self, union_ident: &ast::Ident) -> P<ast::Item> {
         let custom_hash_opt = &self.config().struct_config().custom_hash;
         let hash_body = if let Some(ref custom_hash) = *custom_hash_opt {
@@ -405,12 +1211,262 @@ impl<'a, 'cx, 'exp> CrossChecker<'a, 'cx, 'exp> {
                           ty_ident.to_string())))
     }
 
+    // Get the wrapper that hashes fields of the given type, if the type
+    // is defined in another crate and configured with `remote_hash_impl`,
+    // either in the external configuration entry for the type or in the
+    // attribute of the current structure
+    fn remote_hash_wrapper(&mut self, ty: &P<ast::Ty>) -> Option<ast::Ident> {
+        let ty_name = ty_name(ty);
+        let ty_xcfg = self.scope_stack.iter().rev()
+            .filter_map(|scope| scope.get_item_config(&ty_name))
+            .next();
+        let custom_hash = match ty_xcfg {
+            Some(ty_xcfg) => match *ty_xcfg {
+                xcfg::ItemConfig::Struct(ref struc) if struc.remote_hash_impl == Some(true) => {
+                    self.expander.used_items.borrow_mut().insert(ty_xcfg as *const _);
+                    struc.custom_hash.clone()
+                }
+                _ => return None
+            },
+            None if self.config().struct_config().remote_types.contains(&ty_name) => None,
+            None => return None
+        };
+        let newtype = ast::Ident::from_str(&format!("__c2rust_remote_{}", ty_name));
+        // Only build the wrapper once for each module or block
+        let is_new = self.remote_wrappers.last_mut()
+            .map_or(true, |wrappers| wrappers.insert(ty_name));
+        if is_new {
+            self.build_remote_hash_impl(newtype, ty, custom_hash);
+        }
+        Some(newtype)
+    }
+
+    // Types defined in other crates can't implement `CrossCheckHash`
+    // in this crate, so we wrap references to them in a local newtype
+    // `__c2rust_remote_T` that implements it instead. The hash is
+    // computed using either the custom hash function, or the `Hash`
+    // implementation of the type.
+    fn build_remote_hash_impl(&mut self, newtype: ast::Ident, ty: &P<ast::Ty>,
+                              custom_hash: Option<String>) {
+        let newtype_item = quote_item!(self.cx,
+            #[allow(non_camel_case_types)]
+            pub struct $newtype<'a>(pub &'a $ty);
+        ).unwrap();
+        let hash_expr = match custom_hash {
+            Some(ref s) => {
+                let custom_hash = self.cx.parse_expr(s.clone());
+                quote_expr!(self.cx, $custom_hash::<HA, HS>(self.0, depth))
+            }
+            None => quote_expr!(self.cx, {
+                use std::hash::{Hash, Hasher};
+                let mut h = HA::default();
+                self.0.hash(&mut h);
+                h.finish()
+            })
+        };
+        let hash_impl_item = quote_item!(self.cx,
+            impl<'a> ::cross_check_runtime::hash::CrossCheckHash for $newtype<'a> {
+                #[inline]
+                #[allow(unused_variables)]
+                fn cross_check_hash_depth<HA, HS>(&self, depth: usize) -> u64
+                        where HA: ::cross_check_runtime::hash::CrossCheckHasher,
+                              HS: ::cross_check_runtime::hash::CrossCheckHasher {
+                    $hash_expr
+                }
+            }
+        ).expect(&format!("unable to implement CrossCheckHash for remote type '{}'", newtype));
+        self.pending_items.push(newtype_item);
+        self.pending_items.push(hash_impl_item);
+    }
+
+    // Wrap an `unsafe` block with entry and exit cross-checks
+    // FIXME: early exits from the block, e.g., `return` or `break`,
+    // skip the exit cross-check
+    fn build_unsafe_block_xchecks(&mut self, expr: P<ast::Expr>) -> P<ast::Expr> {
+        let idx = self.last_scope().unsafe_block_idx.get();
+        self.last_scope().unsafe_block_idx.set(idx + 1);
+        let idx = idx as u64;
+        let entry_site = self.expander.next_scope_id();
+        let exit_site = self.expander.next_scope_id();
+        quote_expr!(self.cx, {
+            cross_check_raw!(UNSAFE_BLOCK_ENTRY_TAG, $idx, site = $entry_site);
+            let __c2rust_unsafe_result = $expr;
+            cross_check_raw!(UNSAFE_BLOCK_EXIT_TAG, $idx, site = $exit_site);
+            __c2rust_unsafe_result
+        })
+    }
+
+    // Count the iterations of a `loop` or `while` loop,
+    // and cross-check the total count on loop exit
+    fn build_loop_count_xcheck(&mut self, expr: P<ast::Expr>) -> P<ast::Expr> {
+        let count_stmt = quote_stmt!(self.cx, __c2rust_loop_count += 1;).unwrap();
+        let add_count = |block: P<ast::Block>| block.map(|mut b| {
+            b.stmts.insert(0, count_stmt);
+            b
+        });
+        let counted_expr = expr.map(|e| {
+            let node = match e.node {
+                ast::ExprKind::Loop(block, label) =>
+                    ast::ExprKind::Loop(add_count(block), label),
+                ast::ExprKind::While(cond, block, label) =>
+                    ast::ExprKind::While(cond, add_count(block), label),
+                n @ _ => panic!("unexpected loop expression: {:?}", n)
+            };
+            ast::Expr { node: node, ..e }
+        });
+        let site = self.expander.next_scope_id();
+        quote_expr!(self.cx, {
+            let mut __c2rust_loop_count = 0u64;
+            let __c2rust_loop_result = $counted_expr;
+            cross_check_raw!(LOOP_COUNT_TAG, __c2rust_loop_count, site = $site);
+            __c2rust_loop_result
+        })
+    }
+
+    // Cross-check the index of the arm that a `match` takes, before
+    // evaluating the body of the arm; the arms of the C `switch`
+    // (or `if` chain) need to be numbered in the same order
+    fn build_match_arm_xchecks(&mut self, expr: P<ast::Expr>) -> P<ast::Expr> {
+        let site = self.expander.next_scope_id();
+        expr.map(|e| {
+            let node = match e.node {
+                ast::ExprKind::Match(scrutinee, arms) => {
+                    let arms = arms.into_iter().enumerate().map(|(idx, arm)| {
+                        let idx = idx as u64;
+                        let body = arm.body;
+                        ast::Arm {
+                            body: quote_expr!(self.cx, {
+                                cross_check_raw!(MATCH_ARM_TAG, $idx, site = $site);
+                                $body
+                            }),
+                            ..arm
+                        }
+                    }).collect();
+                    ast::ExprKind::Match(scrutinee, arms)
+                }
+                n @ _ => panic!("unexpected match expression: {:?}", n)
+            };
+            ast::Expr { node: node, ..e }
+        })
+    }
+
+    // Replace a closure coerced to a C function pointer with
+    // a cross-checked `extern "C"` wrapper function, e.g.:
+    // `(|x| x + 1) as extern "C" fn(i32) -> i32` becomes
+    // `{ extern "C" fn __c2rust_closure_0(x: i32) -> i32 { x + 1 } __c2rust_closure_0 as ... }`
+    // The closure body should not capture any variables, since
+    // it gets moved to a separate function
+    fn build_closure_wrapper(&mut self, expr: P<ast::Expr>) -> P<ast::Expr> {
+        let idx = self.last_scope().closure_idx.get();
+        self.last_scope().closure_idx.set(idx + 1);
+        let wrapper_ident = ast::Ident::from_str(&format!("__c2rust_closure_{}", idx));
+
+        let (closure, fn_ty) = match expr.node {
+            ast::ExprKind::Cast(ref e, ref ty) => (strip_parens(e).clone(), ty.clone()),
+            ref n @ _ => panic!("unexpected closure coercion: {:?}", n)
+        };
+        let (closure_decl, closure_body) = match closure.node {
+            ast::ExprKind::Closure(.., decl, body, _) => (decl, body),
+            n @ _ => panic!("unexpected closure expression: {:?}", n)
+        };
+        let bare_fn = match fn_ty.node {
+            ast::TyKind::BareFn(ref bare_fn) => bare_fn.clone(),
+            ref n @ _ => panic!("unexpected function pointer type: {:?}", n)
+        };
+        // Take the argument patterns from the closure, and
+        // their types from the function pointer type, since
+        // the closure arguments are usually left untyped
+        let inputs = closure_decl.inputs.iter()
+            .zip(bare_fn.decl.inputs.iter())
+            .map(|(closure_arg, fn_arg)| ast::Arg {
+                ty: fn_arg.ty.clone(),
+                pat: closure_arg.pat.clone(),
+                id: ast::DUMMY_NODE_ID,
+            })
+            .collect();
+        let fn_decl = P(ast::FnDecl {
+            inputs: inputs,
+            ..(*bare_fn.decl).clone()
+        });
+
+        let wrapper_item = quote_item!(self.cx, extern "C" fn $wrapper_ident() {})
+            .unwrap()
+            .map(|item| {
+                let node = match item.node {
+                    ast::ItemKind::Fn(_, _, constness, _, generics, _) => {
+                        let body = quote_block!(self.cx, { $closure_body });
+                        ast::ItemKind::Fn(fn_decl, bare_fn.unsafety, constness,
+                                          bare_fn.abi, generics, body)
+                    }
+                    n @ _ => panic!("unexpected wrapper item: {:?}", n)
+                };
+                ast::Item { node: node, ..item }
+            });
+
+        // Give the wrapper its own scope, so it can be
+        // configured just like any other function
+        let new_scope = self.build_new_scope(&wrapper_item);
+        self.scope_stack.push(new_scope);
+        let checked_item = wrapper_item.map(|item| {
+            let node = match item.node {
+                ast::ItemKind::Fn(decl, unsafety, constness, abi, generics, block) => {
+                    let checked_block = self.build_function_xchecks(
+                        &item.ident, &*decl, &item.attrs, expr.span, &item.vis, block);
+                    ast::ItemKind::Fn(decl, unsafety, constness, abi, generics, checked_block)
+                }
+                n @ _ => panic!("unexpected wrapper item: {:?}", n)
+            };
+            ast::Item { node: node, ..item }
+        });
+        self.scope_stack.pop();
+
+        quote_expr!(self.cx, {
+            $checked_item
+            $wrapper_ident as $fn_ty
+        })
+    }
+
     fn internal_fold_item_simple(&mut self, item: ast::Item) -> ast::Item {
+        if let ast::ItemKind::Mod(ref m) = item.node {
+            // Set this before folding the contents of the module,
+            // so that all its functions call the statics function
+            let statics_fn = self.build_statics_fn_ident(m);
+            self.last_scope().statics_fn.set(statics_fn);
+        }
         let folded_item = fold::noop_fold_item_simple(item, self);
         match folded_item.node {
             ast::ItemKind::Fn(fn_decl, unsafety, constness, abi, generics, block) => {
+                let xcheck_ident = if self.config().function_config().demangle {
+                    self.demangled_fn_ident(&folded_item.attrs, folded_item.span)
+                } else {
+                    None
+                };
+                if self.expander.verbosity >= 4 {
+                    eprintln!("cross_check: {}: effective configuration:\n{}",
+                              folded_item.ident, self.effective_config());
+                }
+                if self.config().inherited.enabled && self.config().function_config().abi_check {
+                    let abi_check = self.build_abi_check(
+                        xcheck_ident.as_ref().unwrap_or(&folded_item.ident),
+                        abi, folded_item.span);
+                    self.pending_items.push(abi_check);
+                }
                 let checked_block = self.build_function_xchecks(
-                    &folded_item.ident, &*fn_decl, block);
+                    xcheck_ident.as_ref().unwrap_or(&folded_item.ident),
+                    &*fn_decl, &folded_item.attrs, folded_item.span, &folded_item.vis, block);
+                // Enable path normalization before anything gets hashed;
+                // the crate root scope and the scope of `main` are the
+                // only ones on the stack for the `main` function itself
+                let checked_block = if self.crate_root && self.scope_stack.len() == 2 &&
+                                       folded_item.ident.name == "main" &&
+                                       self.expander.external_config.normalize_paths() {
+                    quote_block!(self.cx, {
+                        ::cross_check_runtime::hash::path::set_normalize_paths(true);
+                        $checked_block
+                    })
+                } else {
+                    checked_block
+                };
                 let checked_fn = ast::ItemKind::Fn(
                     fn_decl,
                     unsafety,
@@ -418,8 +1474,17 @@ impl<'a, 'cx, 'exp> CrossChecker<'a, 'cx, 'exp> {
                     abi,
                     generics,
                     checked_block);
+                // Keep the optimizer from inlining the function into its
+                // callers, which could move or eliminate the cross-checks;
+                // this replaces any `#[inline]` attribute already present
+                let mut item_attrs = folded_item.attrs;
+                if self.config().inherited.enabled && self.config().function_config().no_inline {
+                    item_attrs.retain(|attr| !attr.check_name("inline"));
+                    item_attrs.insert(0, quote_attr!(self.cx, #[inline(never)]));
+                }
                 // Build and return the replacement function item
                 ast::Item {
+                    attrs: item_attrs,
                     node: checked_fn,
                     ..folded_item
                 }
@@ -437,12 +1502,17 @@ impl<'a, 'cx, 'exp> CrossChecker<'a, 'cx, 'exp> {
             ast::ItemKind::Struct(_, _) => {
                 // Prepend #[derive(CrossCheckHash)] automatically
                 // to every structure definition
+                let use_transparent_hash = self.use_transparent_hash(&folded_item);
                 let mut item_attrs = folded_item.attrs;
                 if self.config().inherited.enabled {
                     let xcheck_hash_derive_attr = quote_attr!(self.cx, #[derive(CrossCheckHash)]);
                     item_attrs.push(xcheck_hash_derive_attr);
 
-                    let attr_args = self.cx.parse_tts(self.build_hash_attr_args().join(","));
+                    let mut hash_attr_args = self.build_hash_attr_args();
+                    if use_transparent_hash {
+                        hash_attr_args.push(String::from("transparent_hash"));
+                    }
+                    let attr_args = self.cx.parse_tts(hash_attr_args.join(","));
                     if !attr_args.is_empty() {
                         let xcheck_hash_attr = quote_attr!(self.cx, #[cross_check_hash($attr_args)]);
                         item_attrs.push(xcheck_hash_attr);
@@ -453,13 +1523,31 @@ impl<'a, 'cx, 'exp> CrossChecker<'a, 'cx, 'exp> {
                         self.pending_items.extend(c_hash_func.into_iter());
                     }
                 }
+                if let Some(c_align) = self.config().struct_config().c_align {
+                    let align_check = self.build_align_check(&folded_item, c_align);
+                    self.pending_items.extend(align_check.into_iter());
+                }
+                let is_struct = matches!(folded_item.node, ast::ItemKind::Struct(..));
+                if is_struct && self.config().struct_config().mirror_c_layout {
+                    self.add_repr_c(&mut item_attrs, folded_item.span);
+                }
                 ast::Item {
                     attrs: item_attrs,
                     ..folded_item
                 }
             }
+            ast::ItemKind::Mod(mut m) => {
+                if let Some(statics_fn) = self.last_scope().statics_fn.get() {
+                    let statics = static_muts(&m);
+                    m.items.push(self.build_statics_fn(statics_fn, &statics));
+                }
+                ast::Item {
+                    node: ast::ItemKind::Mod(m),
+                    ..folded_item
+                }
+            }
             ast::ItemKind::Mac(_) => {
-                if !cfg!(feature = "expand_macros") {
+                if !cfg!(feature = "expand-macros") {
                     self.expander.insert_macro_scope(folded_item.span, &self.config());
                 }
                 folded_item
@@ -468,6 +1556,39 @@ impl<'a, 'cx, 'exp> CrossChecker<'a, 'cx, 'exp> {
         }
     }
 
+    fn make_coercion_explicit(&self, local: ast::Local) -> ast::Local {
+        let is_coercion = match (&local.ty, &local.init) {
+            (&Some(ref ty), &Some(ref init)) =>
+                is_c_fn_ptr(ty) && matches!(strip_parens(init).node, ast::ExprKind::Closure(..)),
+            _ => false
+        };
+        if is_coercion {
+            let ty = local.ty.clone().unwrap();
+            let init = local.init.map(|init| self.cx.expr_cast(init.span, init, ty));
+            ast::Local { init: init, ..local }
+        } else {
+            local
+        }
+    }
+
+    // Find the type to cast an argument to before hashing it, which is set
+    // using `#[cross_check(arg = "x", as_type = "i32")]`; the keys cannot
+    // repeat inside one attribute, so each argument needs its own one
+    fn parse_arg_as_type(&self, attrs: &[ast::Attribute], arg_name: &str) -> Option<String> {
+        attrs.iter()
+            .filter(|attr| attr.check_name("cross_check"))
+            .filter_map(|attr| attr.parse_meta(self.cx.parse_sess).ok())
+            .filter_map(|mi| {
+                let args = xcfg::attr::get_syntax_item_args(&mi);
+                match (args.get("arg"), args.get("as_type")) {
+                    (Some(arg), Some(as_type)) if arg.as_str() == arg_name =>
+                        Some(String::from(as_type.as_str())),
+                    _ => None
+                }
+            })
+            .next()
+    }
+
     // Parse the #[cross_check(...)] attribute and turn it into a XCheck
     fn parse_field_attr(&self, attrs: &[ast::Attribute]) -> Option<xcfg::XCheckType> {
         let xcheck_attr = find_cross_check_attr(attrs);
@@ -482,16 +1603,30 @@ impl<'a, 'cx, 'exp> CrossChecker<'a, 'cx, 'exp> {
 
 impl<'a, 'cx, 'exp> Folder for CrossChecker<'a, 'cx, 'exp> {
     fn fold_item_simple(&mut self, item: ast::Item) -> ast::Item {
+        if self.expander.skip_bindgen && !self.skip_first_scope && is_bindgen_module(&item) {
+            // Leave the FFI bindings generated by `bindgen` alone
+            return item;
+        }
+        if self.expander.skip_autogenerated && is_derive_expansion(item.span) {
+            // Skip the trait implementations from #[derive], which
+            // are unlikely to diverge between C and Rust
+            return item;
+        }
         if self.skip_first_scope {
             // If skip_first_scope is true, skip building a new scope
             // (see the comment for skip_first_scope in CrossChecker above)
             self.skip_first_scope = false;
             self.internal_fold_item_simple(item)
         } else {
+            let depth = self.scope_stack.len();
             let new_scope = self.build_new_scope(&item);
             self.scope_stack.push(new_scope);
             let new_item = self.internal_fold_item_simple(item);
             self.scope_stack.pop();
+            // Every scope pushed while folding the item's contents
+            // must have been popped again by the time we're done
+            debug_assert_eq!(self.scope_stack.len(), depth,
+                             "unbalanced cross-check scope stack after folding item");
             new_item
         }
     }
@@ -555,6 +1690,16 @@ impl<'a, 'cx, 'exp> Folder for CrossChecker<'a, 'cx, 'exp> {
            self.expander.insert_macro_scope(s.span, &self.config());
        }
 
+       // Turn `let x: extern "C" fn(...) = |...| ...;` into an explicit
+       // cast, so that fold_expr can replace the closure with a wrapper
+       let s = match s.node {
+           ast::StmtKind::Local(local) => ast::Stmt {
+               node: ast::StmtKind::Local(local.map(|l| self.make_coercion_explicit(l))),
+               ..s
+           },
+           _ => s
+       };
+
        let folded_stmt = fold::noop_fold_stmt(s, self);
        folded_stmt.into_iter().flat_map(|s| {
            let new_stmt = match s.node {
@@ -603,9 +1748,21 @@ impl<'a, 'cx, 'exp> Folder for CrossChecker<'a, 'cx, 'exp> {
             });
 
         let sf_attr_xcheck = self.parse_field_attr(&folded_sf.attrs);
-        let sf_xcfg_xcheck = self.config().struct_config().fields.get(&sf_name);
-        let sf_xcheck = sf_xcfg_xcheck.or(sf_attr_xcheck.as_ref());
-        let hash_attr = sf_xcheck.and_then(|sf_xcheck| {
+        let sf_xcheck = {
+            let sf_xcfg_xcheck = self.config().struct_config().field_xcheck(&sf_name);
+            // Skip PhantomData fields by default, so that
+            // the CrossCheckHash derive works without annotations
+            let sf_phantom_xcheck = if !self.config().struct_config().phantom_field_hash &&
+                                       is_phantom_data(&folded_sf.ty) {
+                Some(xcfg::XCheckType::None)
+            } else { None };
+            sf_xcfg_xcheck
+                .or(sf_attr_xcheck.as_ref())
+                .or(sf_phantom_xcheck.as_ref())
+                .or(self.config().struct_config().all_fields.as_ref())
+                .cloned()
+        };
+        let hash_attr = sf_xcheck.as_ref().and_then(|sf_xcheck| {
             match *sf_xcheck {
                 xcfg::XCheckType::Default => None,
 
@@ -618,6 +1775,18 @@ impl<'a, 'cx, 'exp> Folder for CrossChecker<'a, 'cx, 'exp> {
 
                 xcfg::XCheckType::Djb2(_) => unimplemented!(),
 
+                xcfg::XCheckType::Crc32 =>
+                    Some(quote_attr!(self.cx, #[cross_check_hash(crc32)])),
+
+                xcfg::XCheckType::Blake3 =>
+                    Some(quote_attr!(self.cx, #[cross_check_hash(blake3)])),
+
+                xcfg::XCheckType::Adler32 =>
+                    Some(quote_attr!(self.cx, #[cross_check_hash(adler32)])),
+
+                xcfg::XCheckType::SipHash =>
+                    Some(quote_attr!(self.cx, #[cross_check_hash(siphash)])),
+
                 xcfg::XCheckType::Fixed(id) => {
                     // FIXME: we're passing the id in as a string because
                     // that's how derive-macros parses it
@@ -629,11 +1798,25 @@ impl<'a, 'cx, 'exp> Folder for CrossChecker<'a, 'cx, 'exp> {
                     Some(quote_attr!(self.cx, #[cross_check_hash(custom_hash=$s)])),
             }
         });
+        // Fields of types from other crates that have no cross-check
+        // of their own get hashed through the local wrapper for the type
+        let hash_attr = match hash_attr {
+            Some(attr) => Some(attr),
+            None => self.remote_hash_wrapper(&folded_sf.ty).map(|wrapper| {
+                let wrapper = wrapper.to_string();
+                quote_attr!(self.cx, #[cross_check_hash(remote_hash=$wrapper)])
+            })
+        };
+
+        let hash_if_attr = self.config().struct_config().hash_if.get(&sf_name).map(|cond| {
+            quote_attr!(self.cx, #[cross_check_hash(hash_if=$cond)])
+        });
 
         // Remove #[cross_check] from attributes, then append #[cross_check_hash]
         let sf_attrs = folded_sf.attrs.into_iter()
             .filter(|attr| !attr.check_name("cross_check"))
             .chain(hash_attr.into_iter())
+            .chain(hash_if_attr.into_iter())
             .collect();
         ast::StructField {
             attrs: sf_attrs,
@@ -650,6 +1833,18 @@ impl<'a, 'cx, 'exp> Folder for CrossChecker<'a, 'cx, 'exp> {
                     .flat_map(|item| self.fold_item(item).into_iter())
                     .collect();
             }
+        } else if let ast::ItemKind::Mac(_) = item.node {
+            // Without `expand-macros`, the items generated by this macro
+            // only exist after we're done, so we tag the invocation with
+            // #[cross_check] to get CrossCheckExpander::expand called on it
+            // again, which then cross-checks the generated items using
+            // the configuration we store here
+            self.expander.insert_macro_scope(item.span, &self.config());
+            let xcheck_attr = quote_attr!(self.cx, #[cross_check]);
+            return SmallVector::one(item.map(|mut item| {
+                item.attrs.push(ast::Attribute { span: item.span, ..xcheck_attr });
+                item
+            }));
         }
         let mut res = fold::noop_fold_item(item, self);
         // Add the pending items
@@ -666,10 +1861,43 @@ impl<'a, 'cx, 'exp> Folder for CrossChecker<'a, 'cx, 'exp> {
         } else {
            self.expander.insert_macro_scope(expr.span, &self.config());
         }
-        expr.map(|e| fold::noop_fold_expr(e, self))
+        let folded_expr = expr.map(|e| fold::noop_fold_expr(e, self));
+        let is_unsafe_block = match folded_expr.node {
+            ast::ExprKind::Block(ref block, ..) =>
+                matches!(block.rules, ast::BlockCheckMode::Unsafe(ast::UnsafeSource::UserProvided)),
+            _ => false
+        };
+        let is_loop = matches!(folded_expr.node,
+                               ast::ExprKind::Loop(..) | ast::ExprKind::While(..));
+        let is_match = matches!(folded_expr.node, ast::ExprKind::Match(..));
+        if !self.config().inherited.enabled {
+            folded_expr
+        } else if is_c_closure_coercion(&folded_expr) {
+            self.build_closure_wrapper(folded_expr)
+        } else if is_unsafe_block && self.config().inherited.unsafe_block_check {
+            self.build_unsafe_block_xchecks(folded_expr)
+        } else if is_loop && self.config().inherited.loop_count_check {
+            self.build_loop_count_xcheck(folded_expr)
+        } else if is_match && self.config().inherited.check_match_arms {
+            self.build_match_arm_xchecks(folded_expr)
+        } else {
+            folded_expr
+        }
     }
 
-    // TODO: fold_block???
+    fn fold_block(&mut self, block: P<ast::Block>) -> P<ast::Block> {
+        self.remote_wrappers.push(HashSet::new());
+        let folded_block = fold::noop_fold_block(block, self);
+        self.remote_wrappers.pop();
+        folded_block
+    }
+
+    fn fold_mod(&mut self, m: ast::Mod) -> ast::Mod {
+        self.remote_wrappers.push(HashSet::new());
+        let folded_mod = fold::noop_fold_mod(m, self);
+        self.remote_wrappers.pop();
+        folded_mod
+    }
 
     fn fold_foreign_item(&mut self, ni: ast::ForeignItem) -> ast::ForeignItem {
         let folded_ni = fold::noop_fold_foreign_item(ni, self);
@@ -707,6 +1935,16 @@ impl<'a, 'cx, 'exp> Folder for CrossChecker<'a, 'cx, 'exp> {
     }
 }
 
+// Counters for the items we skipped, which we print after
+// each top-level expansion at verbosity 1 and above
+#[derive(Default)]
+struct Stats {
+    skipped_min_complexity: Cell<usize>,
+}
+
+// Names of the per-directory configuration files, in the order we look for them
+const SIDECAR_CONFIG_FILES: &[&str] = &["cross_check.yaml", ".cross_check.yaml"];
+
 #[derive(Default)]
 struct CrossCheckExpander {
     // Arguments passed to plugin
@@ -714,6 +1952,52 @@ struct CrossCheckExpander {
     external_config: xcfg::Config,
     macro_scopes: RefCell<HashMap<Span, Rc<config::InheritedCheckConfig>>>,
 
+    // How much debugging information to print; at level 3 and above,
+    // we print the configuration changes at each scope transition,
+    // and at level 4 and above, the full configuration of each function
+    verbosity: usize,
+
+    // Statistics about the instrumented code
+    stats: Stats,
+
+    // Plugin-wide default for the maximum number of cross-checked arguments
+    max_args: Option<usize>,
+
+    // Skip the modules that look like they were generated by `bindgen`
+    skip_bindgen: bool,
+
+    // Skip the items generated by #[derive] attributes
+    skip_autogenerated: bool,
+
+    // Report the external configuration entries that did not get
+    // applied to any item, as warnings, or as errors in strict mode
+    validate_config: bool,
+    strict_config: bool,
+
+    // External configuration items that got applied to an item,
+    // and the files we looked up configuration items for
+    used_items: RefCell<HashSet<*const xcfg::ItemConfig>>,
+    seen_files: RefCell<HashSet<String>>,
+
+    // Counter for the site identifiers attached to each emitted
+    // cross-check, which are unique even when function names collide
+    scope_id: AtomicU64,
+
+    // Cross-checks for fields of nested structures, configured
+    // using field paths in their outer structures, indexed by
+    // the name of the structure that contains the field
+    field_path_xchecks: RefCell<HashMap<String, HashMap<xcfg::FieldIndex, xcfg::XCheckType>>>,
+
+    // Sidecar configurations, indexed by the directory they were
+    // loaded from, or `None` for directories without one; entries
+    // are never removed or replaced, so the boxed configurations
+    // stay in place for as long as the expander lives
+    sidecar_configs: RefCell<HashMap<PathBuf, Option<Box<xcfg::Config>>>>,
+
+    // Warnings from parsing the configuration files, e.g., about deprecated
+    // format versions, which we report once at the next top-level expansion
+    config_warnings: RefCell<Vec<String>>,
+
     // List of already emitted C ABI hash functions,
     // used to prevent the emission of duplicates
     #[cfg(feature="c-hash-functions")]
@@ -721,15 +2005,73 @@ struct CrossCheckExpander {
 }
 
 impl CrossCheckExpander {
-    fn new(args: &[ast::NestedMetaItem]) -> CrossCheckExpander {
+    fn new(args: &[ast::NestedMetaItem], codemap: &CodeMap) -> CrossCheckExpander {
+        let watch_config = CrossCheckExpander::parse_bool_arg(args, "watch_config").unwrap_or(false);
+        let codemap = if watch_config { Some(codemap) } else { None };
+        let verbosity = CrossCheckExpander::parse_int_arg(args, "verbosity").unwrap_or(0);
+        let mut external_config = CrossCheckExpander::parse_config_files(args, codemap, verbosity);
+        let config_warnings = external_config.take_warnings();
         CrossCheckExpander {
-            external_config: CrossCheckExpander::parse_config_files(args),
+            external_config: external_config,
+            config_warnings: RefCell::new(config_warnings),
             macro_scopes: Default::default(),
+            verbosity: verbosity,
+            max_args: CrossCheckExpander::parse_int_arg(args, "max_args"),
+            skip_bindgen: CrossCheckExpander::parse_bool_arg(args, "skip_bindgen").unwrap_or(false),
+            skip_autogenerated: CrossCheckExpander::parse_bool_arg(args, "skip_autogenerated").unwrap_or(false),
+            validate_config: CrossCheckExpander::parse_bool_arg(args, "validate_config").unwrap_or(false),
+            strict_config: CrossCheckExpander::parse_bool_arg(args, "strict_config").unwrap_or(false),
             ..Default::default()
         }
     }
 
-    fn parse_config_files(args: &[ast::NestedMetaItem]) -> xcfg::Config {
+    // Get a new site identifier; these start at 1, since
+    // the runtime uses 0 for cross-checks without a site
+    fn next_scope_id(&self) -> u64 {
+        self.scope_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    // Parse a boolean argument of the form
+    // #[plugin(cross_check_plugin(name = true))]
+    // or #[plugin(cross_check_plugin(name))]
+    fn parse_bool_arg(args: &[ast::NestedMetaItem], name: &str) -> Option<bool> {
+        args.iter()
+            .filter(|nmi| nmi.check_name(name))
+            .filter_map(|nmi| nmi.meta_item())
+            .map(|mi| match mi.node {
+                ast::MetaItemKind::Word => true,
+                ast::MetaItemKind::NameValue(ref lit) => match lit.node {
+                    ast::LitKind::Bool(b) => b,
+                    _ => panic!("invalid boolean for {}: {:?}", name, lit)
+                },
+                _ => panic!("expected boolean value for {}", name)
+            })
+            .last()
+    }
+
+    // Parse an integer argument of the form
+    // #[plugin(cross_check_plugin(name = 123))]
+    fn parse_int_arg(args: &[ast::NestedMetaItem], name: &str) -> Option<usize> {
+        args.iter()
+            .filter(|nmi| nmi.check_name(name))
+            .filter_map(|nmi| nmi.meta_item())
+            .map(|mi| match mi.node {
+                ast::MetaItemKind::NameValue(ref lit) => match lit.node {
+                    ast::LitKind::Int(i, _) => i as usize,
+                    _ => panic!("invalid integer for {}: {:?}", name, lit)
+                },
+                _ => panic!("expected integer value for {}", name)
+            })
+            .last()
+    }
+
+    // If `codemap` is set, we load the configuration files through it,
+    // which adds them to the dependencies that rustc reports to cargo,
+    // so that changing any of them triggers a rebuild of the crate;
+    // at verbosity 3 and above, we print the entries that each file
+    // overrides on top of the ones before it
+    fn parse_config_files(args: &[ast::NestedMetaItem], codemap: Option<&CodeMap>,
+                          verbosity: usize) -> xcfg::Config {
         // Parse arguments of the form
         // #[plugin(cross_check_plugin(config_file = "..."))]
         let fl = RealFileLoader;
@@ -739,12 +2081,91 @@ impl CrossCheckExpander {
             .map(|fsym| PathBuf::from(&*fsym.as_str()))
             .map(|fp| fl.abs_path(&fp)
                         .expect(&format!("invalid path to config file: {:?}", fp)))
-            .map(|fp| fl.read_file(&fp)
-                        .expect(&format!("could not read config file: {:?}", fp)))
+            .map(|fp| (fp.display().to_string(), match codemap {
+                Some(cm) => cm.load_file(&fp).map(|fm| {
+                    fm.src.as_ref().map_or_else(String::new, |src| (**src).clone())
+                }),
+                None => fl.read_file(&fp)
+            }.expect(&format!("could not read config file: {:?}", fp))))
             // TODO: use a Reader to read&parse each configuration file
             // without storing its contents in an intermediate String buffer???
-            .map(|fd| xcfg::parse_string(&fd).expect("could not parse config file"))
-            .fold(Default::default(), |acc, fc| acc.merge(fc))
+            .chain(CrossCheckExpander::fetch_config_urls(args).into_iter())
+            .map(|(name, fd)| (xcfg::parse_file_string(&fd, &name)
+                                   .expect("could not parse config file"), name))
+            .fold(xcfg::Config::default(), |acc, (fc, name)| {
+                if verbosity >= 3 {
+                    let overrides = xcfg::write_string(&fc.subtract(&acc))
+                        .expect("could not print config overrides");
+                    eprintln!("cross_check: configuration entries from {}:\n{}",
+                              name, overrides);
+                }
+                acc.merge(fc)
+            })
+            .resolve_macros()
+            .unwrap_or_else(|e| panic!("could not resolve config macros: {}", e))
+    }
+
+    // Download the configuration files passed as arguments of the form
+    // #[plugin(cross_check_plugin(config_url = "https://..."))],
+    // returning the contents of each file along with its URL
+    #[cfg(feature="network-config")]
+    fn fetch_config_urls(args: &[ast::NestedMetaItem]) -> Vec<(String, String)> {
+        let timeout = CrossCheckExpander::parse_int_arg(args, "config_timeout").unwrap_or(30);
+        let timeout = Duration::from_secs(timeout as u64);
+        args.iter()
+            .filter(|nmi| nmi.check_name("config_url"))
+            .map(|mi| mi.value_str().expect("invalid string for config_url"))
+            .map(|url| (String::from(&*url.as_str()), network::fetch_config(&*url.as_str(), timeout)))
+            .collect()
+    }
+
+    #[cfg(not(feature="network-config"))]
+    fn fetch_config_urls(args: &[ast::NestedMetaItem]) -> Vec<(String, String)> {
+        if args.iter().any(|nmi| nmi.check_name("config_url")) {
+            panic!("config_url requires the network-config feature");
+        }
+        vec![]
+    }
+
+    // Resolve all field paths in the external configuration, e.g.,
+    // `outer.inner.leaf: none` in the configuration for `Foo`,
+    // to the structure that actually contains the `leaf` field,
+    // by following the types of `Foo::outer` and `Foo::outer.inner`
+    fn resolve_field_paths(&self, cx: &ExtCtxt, krate: &ast::Item) {
+        let mut collector = StructFieldCollector::default();
+        collector.visit_item(krate);
+        let mut field_path_xchecks = self.field_path_xchecks.borrow_mut();
+        for file_items in self.external_config.all_file_items() {
+            for item in file_items.items() {
+                let struc = match *item {
+                    xcfg::ItemConfig::Struct(ref struc) => struc,
+                    _ => continue
+                };
+                for (field, xcheck) in struc.fields.iter() {
+                    let mut struct_name = struc.name.clone();
+                    let mut path = field.clone();
+                    while let Some((first, rest)) = path.split_path() {
+                        let field_ty = collector.field_types.get(&struct_name)
+                            .and_then(|fields| fields.get(&first));
+                        match field_ty {
+                            Some(ty) => struct_name = ty.clone(),
+                            None => {
+                                cx.span_warn(krate.span, &format!(
+                                    "could not resolve field path {:?} for structure {}",
+                                    field, struc.name));
+                                break;
+                            }
+                        }
+                        path = rest;
+                    }
+                    if path != *field && path.split_path().is_none() {
+                        field_path_xchecks.entry(struct_name)
+                            .or_insert_with(HashMap::new)
+                            .insert(path, xcheck.clone());
+                    }
+                }
+            }
+        }
     }
 
     fn insert_macro_scope(&self, sp: Span, config: &config::ScopeCheckConfig) {
@@ -764,20 +2185,108 @@ impl CrossCheckExpander {
     /// if we have any FileDefaults in the external configuration
     fn build_file_defaults_config(&self, cx: &ExtCtxt, parent: &config::ScopeCheckConfig,
                                   file_name: &str) -> Option<config::ScopeCheckConfig> {
-        let file_items = self.external_config.get_file_items(file_name);
-        file_items.map(|file_items| {
-            let mut new_config = parent.new_file();
-            let mut file_cfg = xcfg::DefaultsConfig::default();
-            for item in file_items.items().iter() {
-                match item {
-                    &xcfg::ItemConfig::Defaults(ref def) => file_cfg.merge(def),
-                    _ => (),
-                }
-            }
-            let file_item_cfg = xcfg::ItemConfig::Defaults(file_cfg);
-            new_config.parse_xcfg_config(cx, &file_item_cfg);
-            new_config
-        })
+        let file_items = self.file_items(file_name);
+        if file_items.is_empty() {
+            return None;
+        }
+        let mut new_config = parent.new_file();
+        let mut file_cfg = xcfg::DefaultsConfig::default();
+        for item in file_items.iter().flat_map(|items| items.items().iter()) {
+            match item {
+                &xcfg::ItemConfig::Defaults(ref def) => file_cfg.merge(def),
+                _ => (),
+            }
+        }
+        let file_item_cfg = xcfg::ItemConfig::Defaults(file_cfg);
+        new_config.parse_xcfg_config(cx, &file_item_cfg)
+            .unwrap_or_else(|e| panic!("invalid defaults for {}: {}", file_name, e));
+        Some(new_config)
+    }
+
+    // Get the external configuration items for the given file, which
+    // come from the main configuration and the sidecar file next to it;
+    // the sidecar items come last, so they override the main ones
+    fn file_items(&self, file_name: &str) -> Vec<&xcfg::ItemList> {
+        self.seen_files.borrow_mut().insert(String::from(file_name));
+        let sidecar_items = self.load_sidecar_config(file_name).and_then(|cfg| {
+            Path::new(file_name).file_name()
+                .and_then(|base_name| base_name.to_str())
+                .and_then(|base_name| cfg.get_file_items(base_name))
+        });
+        self.external_config.get_file_items(file_name).into_iter()
+            .chain(sidecar_items.into_iter())
+            .collect()
+    }
+
+    // Report all the named items from the external configuration of the
+    // files we instrumented that did not get applied to any item, e.g.,
+    // entries for functions that were renamed or removed; the nested
+    // items of an unused item are not reported separately
+    fn report_unused_items(&self, cx: &ExtCtxt, sp: Span) {
+        fn collect_unused<'a>(items: &'a xcfg::ItemList,
+                              used_items: &HashSet<*const xcfg::ItemConfig>,
+                              unused: &mut Vec<&'a xcfg::ItemConfig>) {
+            for item in items.items().iter().filter(|item| item.name().is_some()) {
+                if !used_items.contains(&(item as *const _)) {
+                    unused.push(item);
+                } else if let Some(nested) = item.nested_items() {
+                    collect_unused(nested, used_items, unused);
+                }
+            }
+        }
+
+        let mut seen_files = self.seen_files.borrow().iter().cloned().collect::<Vec<_>>();
+        seen_files.sort();
+        let used_items = self.used_items.borrow();
+        for file_name in seen_files.iter() {
+            let mut unused = vec![];
+            for items in self.file_items(file_name) {
+                collect_unused(items, &used_items, &mut unused);
+            }
+            for item in unused {
+                let location = item.location()
+                    .map_or_else(String::new, |loc| format!(" at {}", loc));
+                let msg = format!("external cross-check configuration for `{}`{} \
+                                   did not get applied to any item in {}",
+                                  item.name().unwrap(), location, file_name);
+                if self.strict_config {
+                    cx.span_err(sp, &msg);
+                } else {
+                    cx.span_warn(sp, &msg);
+                }
+            }
+        }
+    }
+
+    // Load the `cross_check.yaml` (or `.cross_check.yaml`) sidecar
+    // configuration from the directory of the given source file;
+    // each directory is only loaded once
+    fn load_sidecar_config(&self, file_path: &str) -> Option<&xcfg::Config> {
+        // Virtual files, e.g., `<macros>`, have no directory
+        if file_path.starts_with('<') {
+            return None;
+        }
+        let dir = Path::new(file_path).parent()?.to_path_buf();
+        let mut sidecar_configs = self.sidecar_configs.borrow_mut();
+        let cfg = sidecar_configs.entry(dir.clone()).or_insert_with(|| {
+            let fl = RealFileLoader;
+            SIDECAR_CONFIG_FILES.iter()
+                .map(|name| dir.join(name))
+                .find(|path| fl.file_exists(path))
+                .map(|path| {
+                    let mut cfg = fl.read_file(&path)
+                        .map_err(|e| e.to_string())
+                        .and_then(|fd| xcfg::parse_file_string(&fd, &path.display().to_string()))
+                        .and_then(|cfg| cfg.resolve_macros())
+                        .unwrap_or_else(|e| panic!("could not parse sidecar config file \
+                                                    {:?}: {}", path, e));
+                    self.config_warnings.borrow_mut().extend(cfg.take_warnings());
+                    Box::new(cfg)
+                })
+        });
+        // The configuration is boxed and never dropped before the
+        // expander, so it outlives the `RefCell` borrow
+        cfg.as_ref().map(|cfg| unsafe { &*(&**cfg as *const xcfg::Config) })
     }
 }
 
@@ -795,30 +2304,74 @@ impl MultiItemModifier for CrossCheckExpander {
                 // ignore this expansion and let the higher level one do everything
                 let ni = match (&i.node, span_scope) {
                     (&ast::ItemKind::Mod(_), None) => {
+                        self.resolve_field_paths(cx, &i);
                         let mut top_config = config::ScopeCheckConfig::new();
+                        Rc::make_mut(&mut top_config.inherited).max_args = self.max_args;
                         top_config.parse_attr_config(cx, mi);
-                        let top_file_name = cx.codemap().span_to_filename(sp);
-                        let top_file_name = top_file_name.to_string();
+                        let top_file_name = source_file_name(cx, sp);
                         // FIXME: do we need to build a FileDefaults???
                         let top_config = self.build_file_defaults_config(cx, &top_config,
                                                                          &top_file_name)
                             .unwrap_or(top_config);
-                        let top_scope = ScopeConfig::new(&self.external_config,
+                        let top_scope = ScopeConfig::new(self.file_items(&top_file_name),
                                                          top_file_name,
                                                          top_config);
-                        CrossChecker::new(self, cx, top_scope, true)
-                            .fold_item(i)
-                            .expect_one("too many items returned")
+                        // rustc passes us the crate root as a module without a name
+                        let crate_root = i.ident.name == keywords::Invalid.name();
+                        let mut checker = CrossChecker::new(self, cx, top_scope, true);
+                        checker.crate_root = crate_root;
+                        let ni = checker.fold_item(i)
+                            .expect_one("too many items returned");
+                        // Report the warnings from the configuration files,
+                        // including the sidecar files we loaded while folding
+                        for warning in self.config_warnings.borrow_mut().drain(..) {
+                            cx.span_warn(sp, &warning);
+                        }
+                        if self.verbosity >= 1 {
+                            eprintln!("cross_check: skipped {} functions below min_complexity",
+                                      self.stats.skipped_min_complexity.get());
+                        }
+                        if self.validate_config || self.strict_config {
+                            self.report_unused_items(cx, sp);
+                        }
+                        ni
+                    }
+                    (&ast::ItemKind::Mac(_), Some(scope_config)) => {
+                        // This is a macro invocation tagged by CrossChecker::fold_item,
+                        // so we expand it here and retroactively cross-check all
+                        // the items it generates, e.g., functions from `macro_rules!`
+                        let i = i.map(|mut i| {
+                            i.attrs.retain(|attr| !attr.check_name("cross_check"));
+                            i
+                        });
+                        let file_name = source_file_name(cx, sp);
+                        let mut res = vec![];
+                        for item in cx.expander().fold_item(i) {
+                            if !is_from_expansion(item.span) {
+                                res.push(Annotatable::Item(item));
+                                continue;
+                            }
+                            let config = config::ScopeCheckConfig {
+                                inherited: Rc::clone(&scope_config),
+                                item: config::ItemCheckConfig::Other,
+                            };
+                            let scope = ScopeConfig::new(self.file_items(&file_name),
+                                                         file_name.clone(),
+                                                         config);
+                            let items = CrossChecker::new(self, cx, scope, false)
+                                .fold_item(item);
+                            res.extend(items.into_iter().map(Annotatable::Item));
+                        }
+                        return res;
                     }
                     (_, Some(scope_config)) => {
                         // If this #[cross_check(...)] expansion is caused by a
                         // macro expansion, handle it here
                         let mut config = config::ScopeCheckConfig::from_item(&i, scope_config);
                         config.parse_attr_config(cx, mi);
-                        let file_name = cx.codemap().span_to_filename(sp);
-                        let file_name = file_name.to_string();
+                        let file_name = source_file_name(cx, sp);
                         // TODO: build a FileDefaults???
-                        let scope = ScopeConfig::new(&self.external_config,
+                        let scope = ScopeConfig::new(self.file_items(&file_name),
                                                      file_name,
                                                      config);
                         CrossChecker::new(self, cx, scope, true)
@@ -836,11 +2389,42 @@ impl MultiItemModifier for CrossCheckExpander {
     }
 }
 
+// Expander for `#[cross_check_extern_crate]` on `extern crate` items;
+// the plugin only ever sees the AST of the crate it is compiling, so it
+// cannot fold the items of a dependency, which has already been compiled
+// to metadata by this point; instead, we report an error that tells the
+// user to instrument the dependency itself
+struct ExternCrateExpander;
+
+impl MultiItemModifier for ExternCrateExpander {
+    fn expand(&self,
+              cx: &mut ExtCtxt,
+              sp: Span,
+              _mi: &ast::MetaItem,
+              item: Annotatable) -> Vec<Annotatable> {
+        match item {
+            Annotatable::Item(ref i) if matches!(i.node, ast::ItemKind::ExternCrate(_)) => {
+                cx.span_err(sp, &format!("cannot cross-check the items of extern crate `{}`: \
+                                          add `#![cross_check]` to the crate itself and build \
+                                          it with the cross-check plugin",
+                                         i.ident));
+            }
+            _ => {
+                cx.span_err(sp, "#[cross_check_extern_crate] only applies to `extern crate` items");
+            }
+        }
+        vec![item]
+    }
+}
+
 #[plugin_registrar]
 pub fn plugin_registrar(reg: &mut Registry) {
-    let ecc = CrossCheckExpander::new(reg.args());
+    let ecc = CrossCheckExpander::new(reg.args(), reg.sess.codemap());
     // TODO: parse args
     reg.register_syntax_extension(
         Symbol::intern("cross_check"),
         SyntaxExtension::MultiModifier(Box::new(ecc)));
+    reg.register_syntax_extension(
+        Symbol::intern("cross_check_extern_crate"),
+        SyntaxExtension::MultiModifier(Box::new(ExternCrateExpander)));
 }
@@ -0,0 +1,47 @@
+// Support for loading external configuration files from HTTP(S) URLs
+
+extern crate reqwest;
+
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Local cache file for the configuration at the given URL
+fn cache_path(url: &str) -> PathBuf {
+    let mut h = DefaultHasher::new();
+    url.hash(&mut h);
+    env::temp_dir().join(format!("cross_check_config_{:016x}.c2r", h.finish()))
+}
+
+// Download the configuration file at the given URL, or read it
+// from the local cache if we already downloaded it before
+pub fn fetch_config(url: &str, timeout: Duration) -> String {
+    let cache_path = cache_path(url);
+    let mut contents = String::new();
+    if let Ok(mut f) = File::open(&cache_path) {
+        if f.read_to_string(&mut contents).is_ok() {
+            return contents;
+        }
+        contents.clear();
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .expect("could not build HTTP client");
+    let mut resp = client.get(url).send()
+        .and_then(|resp| resp.error_for_status())
+        .unwrap_or_else(|e| panic!("could not fetch config file {}: {}", url, e));
+    resp.read_to_string(&mut contents)
+        .unwrap_or_else(|e| panic!("could not read config file {}: {}", url, e));
+
+    // The cache is only an optimization, so ignore any errors here
+    if let Ok(mut f) = File::create(&cache_path) {
+        let _ = f.write_all(contents.as_bytes());
+    }
+    contents
+}
@@ -1,6 +1,7 @@
 
 use syntax::ast;
 
+use syntax::codemap::Span;
 use syntax::ext::base::ExtCtxt;
 use syntax::ext::quote::rt::{ExtParseUtils};
 use syntax::ptr::P;
@@ -10,20 +11,50 @@ use std::convert::TryInto;
 use xcfg;
 use xcfg::attr::{ArgValue, ArgList};
 
-fn djb2_hash(s: &str) -> u32 {
+pub fn djb2_hash(s: &str) -> u32 {
     s.bytes().fold(5381u32, |h, c| h.wrapping_mul(33).wrapping_add(c as u32))
 }
 
+// The tag of a cross-check is one of the constants from
+// `cross_check_runtime::xcheck`, e.g., `FUNCTION_ENTRY_TAG`,
+// unless `tag_value` overrides its value, e.g., from the `tag_map`.
+// The closure passed to `build_xcheck` builds the expression that
+// cross-checks the value; it gets the tag, the statements to run
+// before hashing, and the hash function call that replaces the
+// default hash, for the types that hash the value with a fixed
+// function, e.g., `crc32`
 pub trait CrossCheckBuilder {
-    fn build_ident_xcheck(&self, cx: &ExtCtxt, tag_str: &str, ident: &ast::Ident) -> Option<ast::Stmt>;
-    fn build_xcheck<F>(&self, cx: &ExtCtxt, tag_str: &str, val_ref_str: &str, f: F) -> Option<ast::Stmt>
-        where F: FnOnce(ast::Ident, Vec<ast::Stmt>) -> P<ast::Expr>;
+    fn build_ident_xcheck(&self, cx: &ExtCtxt, sp: Span, tag_str: &str, tag_value: Option<u8>,
+                          site: u64, ident: &ast::Ident) -> Option<ast::Stmt>;
+    fn build_xcheck<F>(&self, cx: &ExtCtxt, tag_str: &str, tag_value: Option<u8>,
+                       site: u64, val_ref_str: &str, f: F) -> Option<ast::Stmt>
+        where F: FnOnce(ast::Ident, Vec<ast::Stmt>, Option<P<ast::Expr>>) -> P<ast::Expr>;
+}
+
+// Name of the hash function that the cross-check type
+// hashes the value with, if it uses a fixed one
+fn hash_fn_name(ty: &xcfg::XCheckType) -> Option<&'static str> {
+    match *ty {
+        xcfg::XCheckType::Crc32   => Some("crc32"),
+        xcfg::XCheckType::Blake3  => Some("blake3"),
+        xcfg::XCheckType::Adler32 => Some("adler32"),
+        xcfg::XCheckType::SipHash => Some("siphash"),
+        _ => None
+    }
 }
 
 impl CrossCheckBuilder for xcfg::XCheckType {
-    fn build_ident_xcheck(&self, cx: &ExtCtxt, tag_str: &str, ident: &ast::Ident) -> Option<ast::Stmt> {
-        self.build_xcheck(cx, tag_str, &"$INVALID$", |tag, pre_hash_stmts| {
-            assert!(pre_hash_stmts.is_empty());
+    fn build_ident_xcheck(&self, cx: &ExtCtxt, sp: Span, tag_str: &str, tag_value: Option<u8>,
+                          site: u64, ident: &ast::Ident) -> Option<ast::Stmt> {
+        // There is no value to hash for the entry and exit cross-checks
+        if let Some(hash_fn) = hash_fn_name(self) {
+            cx.span_err(sp, &format!("`{}` is only valid for argument and \
+                                      return value cross-checks", hash_fn));
+            return None;
+        }
+        self.build_xcheck(cx, tag_str, tag_value, site, &"$INVALID$",
+                          |tag, pre_hash_stmts, hash_expr| {
+            assert!(pre_hash_stmts.is_empty() && hash_expr.is_none());
             let id = djb2_hash(&*ident.name.as_str()) as u64;
             quote_expr!(cx, Some(($tag, $id)))
         })
@@ -32,12 +63,14 @@ impl CrossCheckBuilder for xcfg::XCheckType {
     // Allow clients to specify the id or name manually, like this:
     // #[cross_check(name = "foo")]
     // #[cross_check(id = 0x12345678)]
-    fn build_xcheck<F>(&self, cx: &ExtCtxt, tag_str: &str,
-                       val_ref_str: &str, f: F) -> Option<ast::Stmt>
-            where F: FnOnce(ast::Ident, Vec<ast::Stmt>) -> P<ast::Expr> {
+    // The site is a unique identifier for this cross-check,
+    // which the runtime makes available to the backends
+    fn build_xcheck<F>(&self, cx: &ExtCtxt, tag_str: &str, tag_value: Option<u8>,
+                       site: u64, val_ref_str: &str, f: F) -> Option<ast::Stmt>
+            where F: FnOnce(ast::Ident, Vec<ast::Stmt>, Option<P<ast::Expr>>) -> P<ast::Expr> {
         let tag = ast::Ident::from_str(tag_str);
         let check = match *self {
-            xcfg::XCheckType::Default => f(tag, vec![]),
+            xcfg::XCheckType::Default => f(tag, vec![], None),
             xcfg::XCheckType::AsType(ref ty_str) => {
                 let val_ref_ident = ast::Ident::from_str(val_ref_str);
                 let ty = cx.parse_tts(ty_str.clone());
@@ -45,7 +78,36 @@ impl CrossCheckBuilder for xcfg::XCheckType {
                 let val_update = quote_stmt!(cx, let $val_ref_ident = &__c2rust_cast_val);
                 let stmts = val_cast.into_iter().chain(val_update.into_iter())
                     .collect::<Vec<_>>();
-                f(tag, stmts)
+                f(tag, stmts, None)
+            },
+
+            xcfg::XCheckType::Crc32 => {
+                let val_ref_ident = ast::Ident::from_str(val_ref_str);
+                f(tag, vec![], Some(quote_expr!(cx, {
+                    use cross_check_runtime::hash::crc32::crc32_value;
+                    crc32_value($val_ref_ident) as u64
+                })))
+            },
+            xcfg::XCheckType::Blake3 => {
+                let val_ref_ident = ast::Ident::from_str(val_ref_str);
+                f(tag, vec![], Some(quote_expr!(cx, {
+                    use cross_check_runtime::hash::blake3::blake3_value;
+                    blake3_value($val_ref_ident)
+                })))
+            },
+            xcfg::XCheckType::Adler32 => {
+                let val_ref_ident = ast::Ident::from_str(val_ref_str);
+                f(tag, vec![], Some(quote_expr!(cx, {
+                    use cross_check_runtime::hash::adler32::adler32_value;
+                    adler32_value($val_ref_ident) as u64
+                })))
+            },
+            xcfg::XCheckType::SipHash => {
+                let val_ref_ident = ast::Ident::from_str(val_ref_str);
+                f(tag, vec![], Some(quote_expr!(cx, {
+                    use cross_check_runtime::hash::siphash::siphash_value;
+                    siphash_value($val_ref_ident)
+                })))
             },
 
             xcfg::XCheckType::None |
@@ -61,18 +123,28 @@ impl CrossCheckBuilder for xcfg::XCheckType {
                 quote_expr!(cx, Some(($tag, $custom_expr)))
             },
         };
-        quote_stmt!(cx, {
-            use cross_check_runtime::xcheck::$tag;
-            cross_check_iter!($check.into_iter())
-        })
+        match tag_value {
+            Some(tag_value) => quote_stmt!(cx, {
+                const $tag: u8 = $tag_value;
+                cross_check_iter!($check.into_iter(), site = $site)
+            }),
+            None => quote_stmt!(cx, {
+                use cross_check_runtime::xcheck::$tag;
+                cross_check_iter!($check.into_iter(), site = $site)
+            })
+        }
     }
 }
 
-fn parse_xcheck_type(name: &'static str, arg: &ArgValue) -> xcfg::XCheckType {
+fn parse_xcheck_type(name: &str, arg: &ArgValue) -> xcfg::XCheckType {
     match name {
         "default"  => xcfg::XCheckType::Default,
         "none"     => xcfg::XCheckType::None,
         "disabled" => xcfg::XCheckType::Disabled,
+        "crc32"    => xcfg::XCheckType::Crc32,
+        "blake3"   => xcfg::XCheckType::Blake3,
+        "adler32"  => xcfg::XCheckType::Adler32,
+        "siphash"  => xcfg::XCheckType::SipHash,
 
         "djb2" => xcfg::XCheckType::Djb2(String::from(arg.as_str())),
         "fixed" => {
@@ -107,6 +179,8 @@ pub fn parse_xcheck_arg(arg: &ArgValue<'static>) -> Option<xcfg::XCheckType> {
     match *arg {
         ArgValue::Nothing => None,
         ArgValue::List(ref l) => parse_xcheck_arglist(l),
+        // Allow simple types to be passed as strings, e.g., entry = "none"
+        ArgValue::Str(ref s) => Some(parse_xcheck_type(s, &ArgValue::Nothing)),
         _ => panic!("unexpected argument to all_args():{:?}", *arg)
     }
 }
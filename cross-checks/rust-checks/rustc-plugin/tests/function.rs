@@ -11,6 +11,32 @@ pub use xcheck::rb_xcheck; // Export rb_xcheck for the runtime
 use xcheck::{expect_xcheck, expect_no_xchecks};
 use cross_check_runtime::xcheck::{FUNCTION_ENTRY_TAG, FUNCTION_ARG_TAG, FUNCTION_EXIT_TAG};
 
+macro_rules! make_fn {
+    ($name:ident) => { pub fn $name() { } }
+}
+
+#[cross_check(yes)]
+mod macro_fns {
+    make_fn!(abcd);
+}
+
+// Nested modules, each pushing their own scope while getting folded;
+// the plugin asserts that the scope stack is balanced after each item
+#[cross_check(yes)]
+mod outer {
+    #[cross_check(none)]
+    pub mod middle {
+        #[cross_check(yes)]
+        pub mod inner {
+            pub fn abcd() { }
+        }
+
+        pub fn efgh() { }
+    }
+
+    pub fn abcd() { }
+}
+
 #[test]
 fn test_entry() {
     #[cross_check(yes)]
@@ -124,3 +150,210 @@ fn test_args_override() {
     expect_xcheck(FUNCTION_EXIT_TAG,  0x7c93ee4f_u64);
     expect_no_xchecks();
 }
+
+#[test]
+fn test_args_crc32() {
+    #[cross_check(yes, args(x(crc32)))]
+    fn abcd(x: [u8; 9]) { }
+
+    abcd(*b"123456789");
+    expect_xcheck(FUNCTION_ENTRY_TAG, 0x7c93ee4f_u64);
+    expect_xcheck(FUNCTION_ARG_TAG, 0xcbf43926_u64);
+    expect_xcheck(FUNCTION_EXIT_TAG,  0x7c93ee4f_u64);
+    expect_no_xchecks();
+}
+
+#[test]
+fn test_str_entry_none() {
+    #[cross_check(yes, entry = "none", exit = "default")]
+    fn abcd() { }
+
+    abcd();
+    expect_xcheck(FUNCTION_EXIT_TAG,  0x7c93ee4f_u64);
+    expect_no_xchecks();
+}
+
+#[test]
+fn test_str_args_default() {
+    #[cross_check(yes, args = "default", ret = "none")]
+    fn abcd(_a: u8, _b: u64) { }
+
+    abcd(0x7fu8, 1u64);
+    expect_xcheck(FUNCTION_ENTRY_TAG, 0x7c93ee4f_u64);
+    expect_xcheck(FUNCTION_ARG_TAG, 0x7f_u64);
+    expect_xcheck(FUNCTION_ARG_TAG, 0x0f0f0f0f_0f0f0f0f_u64);
+    expect_xcheck(FUNCTION_EXIT_TAG,  0x7c93ee4f_u64);
+    expect_no_xchecks();
+}
+
+#[test]
+fn test_c_closure() {
+    #[cross_check(yes, ret = "none")]
+    fn abcd() {
+        let f: extern "C" fn() = || { };
+        f();
+    }
+
+    abcd();
+    expect_xcheck(FUNCTION_ENTRY_TAG, 0x7c93ee4f_u64);
+    expect_xcheck(FUNCTION_ENTRY_TAG, 0x95b06e11_u64);
+    expect_xcheck(FUNCTION_EXIT_TAG,  0x95b06e11_u64);
+    expect_xcheck(FUNCTION_EXIT_TAG,  0x7c93ee4f_u64);
+    expect_no_xchecks();
+}
+
+#[test]
+fn test_only_on_panic() {
+    #[cross_check(yes, only_on_panic)]
+    fn abcd(fail: bool) {
+        if fail {
+            panic!("abcd failed");
+        }
+    }
+
+    abcd(false);
+    expect_no_xchecks();
+    assert!(::std::panic::catch_unwind(|| abcd(true)).is_err());
+    expect_xcheck(FUNCTION_ENTRY_TAG, 0x7c93ee4f_u64);
+    expect_xcheck(FUNCTION_EXIT_TAG,  0x7c93ee4f_u64);
+    expect_no_xchecks();
+}
+
+#[test]
+fn test_exit_on_panic() {
+    #[cross_check(yes)]
+    fn abcd(fail: bool) {
+        if fail {
+            panic!("abcd failed");
+        }
+    }
+
+    // The exit cross-check comes from the guard while unwinding
+    assert!(::std::panic::catch_unwind(|| abcd(true)).is_err());
+    expect_xcheck(FUNCTION_ENTRY_TAG, 0x7c93ee4f_u64);
+    expect_xcheck(FUNCTION_EXIT_TAG,  0x7c93ee4f_u64);
+    expect_no_xchecks();
+
+    // The guard does not add a second exit cross-check on a normal return
+    abcd(false);
+    expect_xcheck(FUNCTION_ENTRY_TAG, 0x7c93ee4f_u64);
+    expect_xcheck(FUNCTION_EXIT_TAG,  0x7c93ee4f_u64);
+    expect_no_xchecks();
+}
+
+#[test]
+fn test_unsafe_block_check() {
+    use cross_check_runtime::xcheck::{UNSAFE_BLOCK_ENTRY_TAG, UNSAFE_BLOCK_EXIT_TAG};
+
+    #[cross_check(yes, unsafe_block_check, ret = "none")]
+    fn abcd(x: &u32) -> u32 {
+        let p = x as *const u32;
+        let a = unsafe { *p };
+        let b = unsafe { *p + 1 };
+        a + b
+    }
+
+    assert_eq!(abcd(&1), 3);
+    expect_xcheck(FUNCTION_ENTRY_TAG, 0x7c93ee4f_u64);
+    expect_xcheck(UNSAFE_BLOCK_ENTRY_TAG, 0);
+    expect_xcheck(UNSAFE_BLOCK_EXIT_TAG, 0);
+    expect_xcheck(UNSAFE_BLOCK_ENTRY_TAG, 1);
+    expect_xcheck(UNSAFE_BLOCK_EXIT_TAG, 1);
+    expect_xcheck(FUNCTION_EXIT_TAG,  0x7c93ee4f_u64);
+    expect_no_xchecks();
+}
+
+#[test]
+fn test_loop_count_check() {
+    use cross_check_runtime::xcheck::LOOP_COUNT_TAG;
+
+    #[cross_check(yes, loop_count_check, ret = "none")]
+    fn abcd(n: u32) -> u32 {
+        let mut i = 0;
+        while i < n {
+            i += 1;
+        }
+        let mut j = 0;
+        loop {
+            j += 1;
+            if j == 2 {
+                break;
+            }
+        }
+        i + j
+    }
+
+    assert_eq!(abcd(3), 5);
+    expect_xcheck(FUNCTION_ENTRY_TAG, 0x7c93ee4f_u64);
+    expect_xcheck(LOOP_COUNT_TAG, 3);
+    expect_xcheck(LOOP_COUNT_TAG, 2);
+    expect_xcheck(FUNCTION_EXIT_TAG,  0x7c93ee4f_u64);
+    expect_no_xchecks();
+}
+
+#[test]
+fn test_check_match_arms() {
+    use cross_check_runtime::xcheck::MATCH_ARM_TAG;
+
+    #[cross_check(yes, check_match_arms, ret = "none")]
+    fn abcd(x: u32) -> u32 {
+        match x {
+            0 => 10,
+            1 => 20,
+            _ => 30,
+        }
+    }
+
+    // The arms are numbered in declaration order, starting from 0
+    assert_eq!(abcd(1), 20);
+    expect_xcheck(FUNCTION_ENTRY_TAG, 0x7c93ee4f_u64);
+    expect_xcheck(MATCH_ARM_TAG, 1);
+    expect_xcheck(FUNCTION_EXIT_TAG,  0x7c93ee4f_u64);
+    expect_no_xchecks();
+
+    assert_eq!(abcd(0), 10);
+    assert_eq!(abcd(5), 30);
+    expect_xcheck(FUNCTION_ENTRY_TAG, 0x7c93ee4f_u64);
+    expect_xcheck(MATCH_ARM_TAG, 0);
+    expect_xcheck(FUNCTION_EXIT_TAG,  0x7c93ee4f_u64);
+    expect_xcheck(FUNCTION_ENTRY_TAG, 0x7c93ee4f_u64);
+    expect_xcheck(MATCH_ARM_TAG, 2);
+    expect_xcheck(FUNCTION_EXIT_TAG,  0x7c93ee4f_u64);
+    expect_no_xchecks();
+}
+
+#[test]
+fn test_callback_check() {
+    use cross_check_runtime::xcheck::CALLBACK_ENTRY_TAG;
+
+    #[cross_check(yes, callback_check)]
+    extern "C" fn abcd() { }
+
+    abcd();
+    expect_xcheck(CALLBACK_ENTRY_TAG, 0x7c93ee4f_u64);
+    expect_no_xchecks();
+}
+
+#[test]
+fn test_macro_fn() {
+    macro_fns::abcd();
+    expect_xcheck(FUNCTION_ENTRY_TAG, 0x7c93ee4f_u64);
+    expect_xcheck(FUNCTION_EXIT_TAG,  0x7c93ee4f_u64);
+    expect_no_xchecks();
+}
+
+#[test]
+fn test_nested_modules() {
+    outer::middle::inner::abcd();
+    expect_xcheck(FUNCTION_ENTRY_TAG, 0x7c93ee4f_u64);
+    expect_xcheck(FUNCTION_EXIT_TAG,  0x7c93ee4f_u64);
+    expect_no_xchecks();
+
+    outer::middle::efgh();
+    expect_no_xchecks();
+
+    outer::abcd();
+    expect_xcheck(FUNCTION_ENTRY_TAG, 0x7c93ee4f_u64);
+    expect_xcheck(FUNCTION_EXIT_TAG,  0x7c93ee4f_u64);
+    expect_no_xchecks();
+}
@@ -0,0 +1,28 @@
+#![feature(plugin, custom_attribute)]
+// Cargo runs rustc from the workspace root, so the
+// path to the configuration file is relative to it
+#![plugin(cross_check_plugin(config_file = "rustc-plugin/tests/priority.c2r"))]
+#![cross_check(none)]
+
+#[macro_use]
+extern crate cross_check_runtime;
+
+mod xcheck;
+pub use xcheck::rb_xcheck; // Export rb_xcheck for the runtime
+
+use xcheck::{expect_xcheck, expect_no_xchecks};
+use cross_check_runtime::xcheck::{FUNCTION_ENTRY_TAG, FUNCTION_EXIT_TAG};
+
+// Both the attribute and the external configuration set the entry
+// cross-check; the external one wins, while the fields that only
+// the attribute sets, e.g., `yes`, still apply
+#[cross_check(yes, entry(fixed = 0x1234))]
+fn abcd() { }
+
+#[test]
+fn test_external_overrides_attribute() {
+    abcd();
+    expect_xcheck(FUNCTION_ENTRY_TAG, 0x5678);
+    expect_xcheck(FUNCTION_EXIT_TAG, 0x7c93ee4f_u64);
+    expect_no_xchecks();
+}
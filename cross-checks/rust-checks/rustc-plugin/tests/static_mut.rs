@@ -0,0 +1,29 @@
+#![feature(plugin, custom_attribute)]
+#![plugin(cross_check_plugin)]
+#![cross_check(none, check_static_mut)]
+
+#[macro_use]
+extern crate cross_check_runtime;
+
+mod xcheck;
+pub use xcheck::rb_xcheck; // Export rb_xcheck for the runtime
+
+use xcheck::{expect_xcheck, expect_no_xchecks};
+use cross_check_runtime::xcheck::{FUNCTION_ENTRY_TAG, FUNCTION_EXIT_TAG, STATIC_MUT_TAG};
+
+static mut ABCD: u64 = 1;
+
+#[test]
+fn test_static_mut() {
+    #[cross_check(yes)]
+    fn abcd() {
+        unsafe { ABCD = 2; }
+    }
+
+    // The statics get cross-checked on entry, before the body runs
+    abcd();
+    expect_xcheck(FUNCTION_ENTRY_TAG, 0x7c93ee4f_u64);
+    expect_xcheck(STATIC_MUT_TAG, 0x0f0f0f0f_0f0f0f0f_u64);
+    expect_xcheck(FUNCTION_EXIT_TAG,  0x7c93ee4f_u64);
+    expect_no_xchecks();
+}
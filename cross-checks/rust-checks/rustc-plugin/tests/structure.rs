@@ -155,3 +155,37 @@ fn test_multi_field_hash() {
                    [] y: u64 = 1 }
                  [(Djb2Hasher, SimpleHasher, 0x3d17c937_u64)]);
 }
+
+#[test]
+fn test_all_fields_none() {
+    test_struct!([all_fields = "none"]
+                 { []        x: u64 = 0x12345678,
+                   [default] y: u64 = 0x34567812 }
+                 [(SimpleHasher, SimpleHasher, 0x34567812_u64)]);
+}
+
+#[test]
+fn test_crc32_field() {
+    test_struct!([]
+                 { [crc32] x: [u8; 9] = *b"123456789" }
+                 [(SimpleHasher, SimpleHasher, 0x0f0f0f0f_c4fb3628_u64)]);
+}
+
+#[test]
+fn test_remote_hash_impl() {
+    // `Duration` comes from `std`, so it can't implement `CrossCheckHash`
+    // here; the field gets hashed through its `Hash` implementation
+    use std::time::Duration;
+    test_struct!([remote_hash_impl="Duration"]
+                 { [] d: Duration = Duration::from_secs(7) }
+                 [(Djb2Hasher, SimpleHasher, 0x7827d53c_u64)]);
+}
+
+#[test]
+fn test_remote_hash_impl_list() {
+    use std::time::Duration;
+    test_struct!([remote_hash_impl="Duration, String"]
+                 { [] d: Duration = Duration::from_secs(7),
+                   [] s: String = String::from("abcd") }
+                 [(Djb2Hasher, SimpleHasher, 0xc3b89b63_u64)]);
+}
@@ -0,0 +1,24 @@
+#![feature(plugin, custom_attribute)]
+// Cargo runs rustc from the workspace root, so the
+// path to the configuration file is relative to it
+#![plugin(cross_check_plugin(config_file = "rustc-plugin/tests/tag_prefix.c2r"))]
+#![cross_check(none, tag_prefix = "TEST_")]
+
+#[macro_use]
+extern crate cross_check_runtime;
+
+mod xcheck;
+pub use xcheck::rb_xcheck; // Export rb_xcheck for the runtime
+
+use xcheck::{expect_xcheck, expect_no_xchecks};
+
+#[test]
+fn test_prefixed_entry_exit() {
+    #[cross_check(yes)]
+    fn abcd() { }
+
+    abcd();
+    expect_xcheck(0x81, 0x7c93ee4f_u64);
+    expect_xcheck(0x82, 0x7c93ee4f_u64);
+    expect_no_xchecks();
+}
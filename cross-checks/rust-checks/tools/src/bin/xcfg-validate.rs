@@ -0,0 +1,114 @@
+extern crate cross_check_config as xcfg;
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::process;
+
+fn usage() -> ! {
+    eprintln!("Usage: xcfg-validate [--check] [--merge] [--subtract BASE] FILE...");
+    process::exit(2)
+}
+
+// Read and parse a configuration file; unless we merge it with other
+// files first, we also resolve its macros, same as the compiler plugin
+fn read_config(path: &str, resolve_macros: bool) -> Result<xcfg::Config, String> {
+    let mut s = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut s))
+        .map_err(|e| format!("could not read config file: {}", e))?;
+    let mut cfg = xcfg::parse_string(&s)?;
+    for warning in cfg.take_warnings() {
+        eprintln!("{}: warning: {}", path, warning);
+    }
+    if resolve_macros {
+        cfg = cfg.resolve_macros()?;
+    }
+    Ok(cfg)
+}
+
+fn print_config(cfg: &xcfg::Config) {
+    match xcfg::write_string(cfg) {
+        Ok(s) => println!("{}", s),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1)
+        }
+    }
+}
+
+fn main() {
+    let mut check = false;
+    let mut merge = false;
+    let mut base = None;
+    let mut files = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--check" {
+            check = true;
+        } else if arg == "--merge" {
+            merge = true;
+        } else if arg == "--subtract" {
+            base = Some(args.next().unwrap_or_else(|| usage()));
+        } else if arg.starts_with("-") {
+            usage();
+        } else {
+            files.push(arg);
+        }
+    }
+    if files.is_empty() {
+        usage();
+    }
+
+    let mut configs = vec![];
+    let mut failed = false;
+    for file in files.iter() {
+        match read_config(file, !merge) {
+            Ok(cfg) => configs.push((file, cfg)),
+            Err(e) => {
+                eprintln!("{}: error: {}", file, e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+    // Only print the entries that differ from the base configuration
+    let base = base.map(|base_file| read_config(&base_file, true).unwrap_or_else(|e| {
+        eprintln!("{}: error: {}", base_file, e);
+        process::exit(1)
+    }));
+    // Merge the files in the order they were passed in, then resolve
+    // the macros, which may come from any of the files, same as the
+    // compiler plugin does
+    let merged = if merge {
+        let merged = configs.drain(..)
+            .map(|(_, cfg)| cfg)
+            .fold(xcfg::Config::default(), |acc, cfg| acc.merge(cfg))
+            .resolve_macros()
+            .unwrap_or_else(|e| {
+                eprintln!("error: {}", e);
+                process::exit(1)
+            });
+        Some(merged)
+    } else {
+        None
+    };
+    if check {
+        return;
+    }
+    let print_config = |cfg: &xcfg::Config| match base {
+        Some(ref base) => print_config(&cfg.subtract(base)),
+        None => print_config(cfg),
+    };
+
+    if let Some(merged) = merged {
+        print_config(&merged);
+    } else {
+        for (file, cfg) in configs.into_iter() {
+            println!("# {}", file);
+            print_config(&cfg);
+        }
+    }
+}
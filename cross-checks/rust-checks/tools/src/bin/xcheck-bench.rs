@@ -0,0 +1,163 @@
+// Measures the overhead of the cross-check plugin for a single crate,
+// by compiling it with and without the plugin and comparing the
+// compilation times and output sizes. The crate is instrumented the same
+// way `xcheck-rustc` does it, by compiling a copy of the crate root with
+// the plugin attributes added at the top, so the input file should not
+// load the plugin itself. The times are measured using `Instant`, without
+// a benchmarking framework, since the compiler runs in a separate process.
+extern crate cross_check_tools;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use cross_check_tools::InstrumentedRoot;
+
+const DEFAULT_RUNS: usize = 3;
+
+fn usage() -> ! {
+    eprintln!("Usage: xcheck-bench [--runs N] [--config FILE]... FILE.rs [RUSTC_ARGS...]");
+    eprintln!("Compiles FILE.rs with and without the cross-check plugin, \
+               and reports the compilation time and binary size overhead");
+    process::exit(2)
+}
+
+struct Options {
+    runs: usize,
+    config_files: Vec<String>,
+    input: String,
+    rustc_args: Vec<String>,
+}
+
+fn parse_args() -> Options {
+    let mut args = env::args().skip(1);
+    let mut runs = DEFAULT_RUNS;
+    let mut config_files = vec![];
+    loop {
+        match args.next() {
+            Some(ref arg) if arg == "--runs" => {
+                runs = match args.next().and_then(|n| n.parse().ok()) {
+                    Some(n) if n > 0 => n,
+                    _ => usage()
+                };
+            }
+            Some(ref arg) if arg == "--config" => {
+                config_files.push(args.next().unwrap_or_else(|| usage()));
+            }
+            Some(ref arg) if arg.starts_with("--") => usage(),
+            Some(input) => {
+                return Options {
+                    runs: runs,
+                    config_files: config_files,
+                    input: input,
+                    rustc_args: args.collect(),
+                };
+            }
+            None => usage()
+        }
+    }
+}
+
+struct BuildStats {
+    // Fastest of all the compilation runs, which is
+    // the least affected by noise from other processes
+    min_time: Duration,
+    mean_time: Duration,
+    size: u64,
+}
+
+fn duration_secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + f64::from(d.subsec_nanos()) * 1e-9
+}
+
+fn compile(opts: &Options, out_dir: &Path, instrument: bool) -> Result<BuildStats, String> {
+    fs::create_dir_all(out_dir)
+        .map_err(|e| format!("could not create {}: {}", out_dir.display(), e))?;
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+    let root = if instrument {
+        let root = InstrumentedRoot::new(Path::new(&opts.input), &opts.config_files)
+            .map_err(|e| format!("could not instrument {}: {}", opts.input, e))?;
+        Some(root)
+    } else { None };
+    let input = root.as_ref().map_or(Path::new(&opts.input), |root| root.path());
+    // rustc takes the crate name from the name of the input file by
+    // default, so we pass in the original name for the instrumented copy
+    let has_crate_name = opts.rustc_args.iter()
+        .any(|arg| arg == "--crate-name" || arg.starts_with("--crate-name="));
+    let crate_name = Path::new(&opts.input).file_stem()
+        .map(|stem| stem.to_string_lossy().replace('-', "_"));
+    let mut times = vec![];
+    for _ in 0..opts.runs {
+        let mut cmd = Command::new(&rustc);
+        cmd.arg(input)
+           .arg("--out-dir").arg(out_dir)
+           .args(&opts.rustc_args);
+        if let (false, Some(crate_name)) = (has_crate_name, crate_name.as_ref()) {
+            cmd.arg("--crate-name").arg(crate_name);
+        }
+        let start = Instant::now();
+        let status = cmd.status()
+            .map_err(|e| format!("could not run {}: {}", rustc, e))?;
+        times.push(start.elapsed());
+        if !status.success() {
+            return Err(format!("compilation failed with {}", status));
+        }
+    }
+
+    // Add up the sizes of all the outputs, since we do
+    // not know their names for all the crate types
+    let mut size = 0;
+    let entries = fs::read_dir(out_dir)
+        .map_err(|e| format!("could not read {}: {}", out_dir.display(), e))?;
+    for entry in entries {
+        let meta = entry.and_then(|e| e.metadata())
+            .map_err(|e| format!("could not read {}: {}", out_dir.display(), e))?;
+        if meta.is_file() {
+            size += meta.len();
+        }
+    }
+
+    let total = times.iter().fold(Duration::new(0, 0), |acc, &t| acc + t);
+    Ok(BuildStats {
+        min_time: *times.iter().min().unwrap(),
+        mean_time: total / times.len() as u32,
+        size: size,
+    })
+}
+
+fn report(name: &str, stats: &BuildStats) {
+    println!("{:<12} min {:>8.3}s  mean {:>8.3}s  size {:>10} bytes", name,
+             duration_secs(stats.min_time), duration_secs(stats.mean_time), stats.size);
+}
+
+fn main() {
+    let opts = parse_args();
+    // Use the current time to get a unique output directory
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::new(0, 0));
+    let out_root: PathBuf = env::temp_dir().join(format!("xcheck-bench-{}-{}",
+                                                         now.as_secs(), now.subsec_nanos()));
+    let results = compile(&opts, &out_root.join("plain"), false).and_then(|plain| {
+        compile(&opts, &out_root.join("xcheck"), true).map(|xcheck| (plain, xcheck))
+    });
+    let _ = fs::remove_dir_all(&out_root);
+    let (plain, xcheck) = match results {
+        Ok(res) => res,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1)
+        }
+    };
+
+    report("plain", &plain);
+    report("cross-check", &xcheck);
+    let plain_time = duration_secs(plain.min_time);
+    let time_overhead = if plain_time > 0. {
+        (duration_secs(xcheck.min_time) / plain_time - 1.) * 100.
+    } else { 0. };
+    let size_overhead = if plain.size > 0 {
+        (xcheck.size as f64 / plain.size as f64 - 1.) * 100.
+    } else { 0. };
+    println!("overhead     time {:+.1}%  size {:+.1}%", time_overhead, size_overhead);
+}
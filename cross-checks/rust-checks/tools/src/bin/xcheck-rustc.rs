@@ -0,0 +1,74 @@
+// Wrapper around rustc that propagates the cross-check plugin and its
+// configuration to dependencies, meant to be used as `RUSTC_WRAPPER`.
+// Cargo invokes it as `xcheck-rustc RUSTC ARGS...` for every crate it
+// builds; for crates whose `Cargo.toml` contains
+//   [package.metadata]
+//   cross_check = "propagate"
+// we compile a copy of the crate root with the attributes that load the
+// plugin and enable cross-checks added at the top, passing along the
+// configuration files from `XCHECK_CONFIG_FILES`
+extern crate cross_check_tools;
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::process::{self, Command};
+
+use cross_check_tools::{find_crate_root, is_build_script, wants_propagation, InstrumentedRoot};
+
+fn usage() -> ! {
+    eprintln!("Usage: xcheck-rustc RUSTC [ARGS...]");
+    eprintln!("This tool is meant to be used as RUSTC_WRAPPER by cargo");
+    process::exit(2)
+}
+
+// Check whether the given manifest opts into cross-check propagation
+fn manifest_wants_propagation(manifest: &Path) -> bool {
+    let mut s = String::new();
+    File::open(manifest).and_then(|mut f| f.read_to_string(&mut s)).is_ok() &&
+        wants_propagation(&s)
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let rustc = args.next().unwrap_or_else(|| usage());
+    let mut args = args.collect::<Vec<_>>();
+
+    // Cargo sets CARGO_MANIFEST_DIR for every crate it compiles,
+    // including build scripts, but not when it queries rustc
+    // itself, e.g., for `rustc -vV`
+    let propagate = !is_build_script(&args) && env::var_os("CARGO_MANIFEST_DIR")
+        .map(|dir| manifest_wants_propagation(&Path::new(&dir).join("Cargo.toml")))
+        .unwrap_or(false);
+    // Keep the instrumented copy of the crate root
+    // around until the compilation is done
+    let mut root = None;
+    if let (true, Some(idx)) = (propagate, find_crate_root(&args)) {
+        let config_files = env::var("XCHECK_CONFIG_FILES").unwrap_or_default();
+        let config_files = config_files.split(',')
+            .filter(|f| !f.is_empty())
+            .collect::<Vec<_>>();
+        let instrumented = match InstrumentedRoot::new(Path::new(&args[idx]), &config_files) {
+            Ok(instrumented) => instrumented,
+            Err(e) => {
+                eprintln!("error: could not instrument {}: {}", args[idx], e);
+                process::exit(1)
+            }
+        };
+        args[idx] = instrumented.path().to_string_lossy().into_owned();
+        root = Some(instrumented);
+    }
+
+    let status = Command::new(rustc).args(&args).status();
+    // process::exit() does not run any destructors,
+    // so we need to delete the copy ourselves
+    drop(root);
+    match status {
+        Ok(status) => process::exit(status.code().unwrap_or(1)),
+        Err(e) => {
+            eprintln!("error: could not run rustc: {}", e);
+            process::exit(1)
+        }
+    }
+}
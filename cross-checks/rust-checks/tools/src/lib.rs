@@ -0,0 +1,167 @@
+// Code shared between the cross-check tools
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+// Build the attribute that loads the cross-check plugin
+// with the given configuration files
+pub fn plugin_attr<S: AsRef<str>>(config_files: &[S]) -> String {
+    let args = config_files.iter()
+        .map(|f| format!("config_file = {:?}", f.as_ref()))
+        .collect::<Vec<_>>();
+    if args.is_empty() {
+        String::from("plugin(cross_check_plugin)")
+    } else {
+        format!("plugin(cross_check_plugin({}))", args.join(", "))
+    }
+}
+
+// Find the crate root in a list of rustc arguments, i.e.,
+// the only argument that is a Rust source file
+pub fn find_crate_root(args: &[String]) -> Option<usize> {
+    args.iter().position(|arg| !arg.starts_with('-') && arg.ends_with(".rs"))
+}
+
+// Check whether the rustc arguments build a build script; cargo
+// sets CARGO_MANIFEST_DIR for those as well, but they don't
+// depend on the cross-check runtime, so we can't instrument them
+pub fn is_build_script(args: &[String]) -> bool {
+    args.windows(2).any(|w| w[0] == "--crate-name" && w[1] == "build_script_build")
+}
+
+const PROPAGATE_KEY: &str = "cross_check";
+const PROPAGATE_VALUE: &str = "propagate";
+
+// Check whether the contents of a `Cargo.toml` opt into cross-check
+// propagation; we only need a single key, so we scan the file line
+// by line instead of pulling in a full TOML parser
+pub fn wants_propagation(manifest: &str) -> bool {
+    let mut in_metadata = false;
+    for line in manifest.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_metadata = line == "[package.metadata]";
+            continue;
+        }
+        if !in_metadata {
+            continue;
+        }
+        let mut parts = line.splitn(2, '=').map(str::trim);
+        if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+            if key == PROPAGATE_KEY && value.trim_matches('"') == PROPAGATE_VALUE {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+// Copy of a crate root with the attributes that load the plugin and
+// enable cross-checks added at the top. We can't add the attributes
+// from the command line, so we compile the copy instead of the original.
+// The copy goes in the same directory as the original, so that rustc
+// finds the files of all the `mod` declarations; it gets deleted
+// when this object is dropped.
+pub struct InstrumentedRoot {
+    path: PathBuf,
+}
+
+impl InstrumentedRoot {
+    pub fn new<S: AsRef<str>>(root: &Path, config_files: &[S]) -> io::Result<InstrumentedRoot> {
+        let mut src = String::new();
+        File::open(root)?.read_to_string(&mut src)?;
+        let file_name = root.file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("main.rs");
+        // Several copies of the same root may exist at the same time,
+        // e.g., when cargo builds the library and its tests in parallel
+        let mut idx = 0;
+        let (path, mut f) = loop {
+            let path = root.with_file_name(format!(".xcheck-{}-{}", idx, file_name));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(f) => break (path, f),
+                Err(ref e) if e.kind() == ErrorKind::AlreadyExists => idx += 1,
+                Err(e) => return Err(e),
+            }
+        };
+        let res = InstrumentedRoot { path: path };
+        // Put all the attributes on the first line, so that the
+        // line numbers in diagnostics match the original file
+        write!(f, "#![feature(plugin)] #![{}] #![cross_check] ", plugin_attr(config_files))?;
+        // Skip the byte order mark, which is only allowed at the beginning
+        let src = if src.starts_with('\u{feff}') { &src[3..] } else { &src[..] };
+        f.write_all(src.as_bytes())?;
+        Ok(res)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for InstrumentedRoot {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| String::from(*arg)).collect()
+    }
+
+    #[test]
+    fn test_find_crate_root() {
+        assert_eq!(find_crate_root(&args(&["--crate-name", "foo", "src/lib.rs",
+                                           "--crate-type", "lib"])), Some(2));
+        assert_eq!(find_crate_root(&args(&["--out-dir=foo.rs", "src/main.rs"])), Some(1));
+        assert_eq!(find_crate_root(&args(&["-vV"])), None);
+    }
+
+    #[test]
+    fn test_is_build_script() {
+        assert!(is_build_script(&args(&["--crate-name", "build_script_build", "build.rs"])));
+        assert!(!is_build_script(&args(&["--crate-name", "foo", "src/lib.rs"])));
+        assert!(!is_build_script(&args(&["build_script_build"])));
+    }
+
+    #[test]
+    fn test_wants_propagation() {
+        assert!(wants_propagation("[package]\nname = \"foo\"\n\n\
+                                   [package.metadata]\ncross_check = \"propagate\"\n"));
+        assert!(wants_propagation("[package.metadata]\n  cross_check=propagate\n"));
+        assert!(!wants_propagation("[package]\ncross_check = \"propagate\"\n"));
+        assert!(!wants_propagation("[package.metadata]\ncross_check = \"none\"\n\
+                                    [dependencies]\ncross_check = \"propagate\"\n"));
+        assert!(!wants_propagation(""));
+    }
+
+    #[test]
+    fn test_instrumented_root() {
+        let dir = env::temp_dir().join("xcheck-tools-test-instrumented-root");
+        fs::create_dir_all(&dir).unwrap();
+        let root = dir.join("lib.rs");
+        File::create(&root).unwrap().write_all("\u{feff}pub fn foo() {}\n".as_bytes()).unwrap();
+
+        let path = {
+            let first = InstrumentedRoot::new(&root, &["a.c2r"]).unwrap();
+            let second = InstrumentedRoot::new(&root, &[] as &[&str]).unwrap();
+            assert_eq!(first.path(), dir.join(".xcheck-0-lib.rs").as_path());
+            assert_eq!(second.path(), dir.join(".xcheck-1-lib.rs").as_path());
+
+            let mut src = String::new();
+            File::open(first.path()).unwrap().read_to_string(&mut src).unwrap();
+            assert_eq!(src, "#![feature(plugin)] \
+                             #![plugin(cross_check_plugin(config_file = \"a.c2r\"))] \
+                             #![cross_check] pub fn foo() {}\n");
+            first.path().to_path_buf()
+        };
+        // Dropping the copies deletes them
+        assert!(!path.exists());
+        assert!(!dir.join(".xcheck-1-lib.rs").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}